@@ -0,0 +1,303 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token::{self, Mint, Token, TokenAccount, Transfer},
+};
+
+// Mock lending program for local testing of skillstreak yield strategies.
+// Implements the narrow strategy interface expected by skillstreak_program:
+//   deposit(amount)  accounts: [payer, owner, pool, pool_token_account, position, owner_token_account, token_program, system_program]
+//   withdraw(amount) accounts: [owner, pool, pool_token_account, position, owner_token_account, token_program]
+// withdraw(u64::MAX) withdraws the full position balance.
+declare_id!("9sKmYfAysbNsCZi5eYwAcZkKJ4LXe325tpee3uhWkSw8");
+
+pub const POOL_SEED: &[u8] = b"pool";
+pub const POSITION_SEED: &[u8] = b"position";
+pub const SECONDS_PER_YEAR: i64 = 365 * 24 * 60 * 60;
+
+// Helper function to accrue simple interest on a position up to the given timestamp.
+pub fn accrue_interest(position: &mut Position, rate_basis_points: u16, now: i64) -> Result<()> {
+    let elapsed = now.saturating_sub(position.last_accrual_timestamp);
+    if elapsed > 0 && position.balance > 0 {
+        let interest = (position.balance as u128)
+            .checked_mul(rate_basis_points as u128)
+            .ok_or(ErrorCode::ArithmeticError)?
+            .checked_mul(elapsed as u128)
+            .ok_or(ErrorCode::ArithmeticError)?
+            .checked_div(10000u128 * SECONDS_PER_YEAR as u128)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        position.balance = position.balance
+            .checked_add(interest as u64)
+            .ok_or(ErrorCode::ArithmeticError)?;
+    }
+    position.last_accrual_timestamp = now;
+    Ok(())
+}
+
+#[program]
+pub mod mock_lending {
+    use super::*;
+
+    // Creates a pool for a mint paying a fixed simple APR on every position.
+    pub fn initialize_pool(ctx: Context<InitializePool>, rate_basis_points: u16) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.mint = ctx.accounts.mint.key();
+        pool.rate_basis_points = rate_basis_points;
+        pool.total_balance = 0;
+        pool.bump = ctx.bumps.pool;
+
+        msg!("Mock lending pool created for mint {} at {} bps", pool.mint, rate_basis_points);
+        Ok(())
+    }
+
+    // Adds liquidity that is not owned by any position, used to pay out interest
+    // (stands in for the interest borrowers would pay on a real lending market).
+    pub fn fund_pool(ctx: Context<FundPool>, amount: u64) -> Result<()> {
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.funder_token_account.to_account_info(),
+            to: ctx.accounts.pool_token_account.to_account_info(),
+            authority: ctx.accounts.funder.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        token::transfer(CpiContext::new(cpi_program, cpi_accounts), amount)?;
+
+        msg!("Pool funded with {}", amount);
+        Ok(())
+    }
+
+    pub fn deposit(ctx: Context<LendingDeposit>, amount: u64) -> Result<()> {
+        if amount == 0 {
+            return err!(ErrorCode::ZeroAmount);
+        }
+        let now = Clock::get()?.unix_timestamp;
+        let pool = &mut ctx.accounts.pool;
+        let position = &mut ctx.accounts.position;
+
+        // Fresh position (created by init_if_needed)
+        if position.owner == Pubkey::default() {
+            position.pool = pool.key();
+            position.owner = ctx.accounts.owner.key();
+            position.bump = ctx.bumps.position;
+        }
+        accrue_interest(position, pool.rate_basis_points, now)?;
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.owner_token_account.to_account_info(),
+            to: ctx.accounts.pool_token_account.to_account_info(),
+            authority: ctx.accounts.owner.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        token::transfer(CpiContext::new(cpi_program, cpi_accounts), amount)?;
+
+        position.balance = position.balance
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        pool.total_balance = pool.total_balance
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+
+        msg!("Deposited {}. Position balance: {}", amount, position.balance);
+        Ok(())
+    }
+
+    pub fn withdraw(ctx: Context<LendingWithdraw>, amount: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let pool = &mut ctx.accounts.pool;
+        let position = &mut ctx.accounts.position;
+
+        accrue_interest(position, pool.rate_basis_points, now)?;
+
+        let withdraw_amount = if amount == u64::MAX { position.balance } else { amount };
+        if withdraw_amount > position.balance {
+            return err!(ErrorCode::InsufficientPositionBalance);
+        }
+        if withdraw_amount > ctx.accounts.pool_token_account.amount {
+            return err!(ErrorCode::InsufficientLiquidity);
+        }
+
+        if withdraw_amount > 0 {
+            let mint_key = pool.mint;
+            let seeds = &[POOL_SEED, mint_key.as_ref(), &[pool.bump]];
+            let signer = &[&seeds[..]];
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.pool_token_account.to_account_info(),
+                to: ctx.accounts.owner_token_account.to_account_info(),
+                authority: pool.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token::transfer(cpi_ctx, withdraw_amount)?;
+        }
+
+        position.balance = position.balance
+            .checked_sub(withdraw_amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        pool.total_balance = pool.total_balance.saturating_sub(withdraw_amount);
+
+        msg!("Withdrew {}. Position balance: {}", withdraw_amount, position.balance);
+        Ok(())
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Pool {
+    pub mint: Pubkey,
+    pub rate_basis_points: u16, // Simple APR paid on position balances
+    pub total_balance: u64,     // Sum of position balances at their last accrual
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Position {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub balance: u64, // Principal plus accrued interest
+    pub last_accrual_timestamp: i64,
+    pub bump: u8,
+}
+
+#[derive(Accounts)]
+pub struct InitializePool<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Pool::INIT_SPACE,
+        seeds = [POOL_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        init,
+        payer = payer,
+        associated_token::mint = mint,
+        associated_token::authority = pool,
+    )]
+    pub pool_token_account: Account<'info, TokenAccount>,
+
+    pub mint: Account<'info, Mint>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct FundPool<'info> {
+    pub funder: Signer<'info>,
+
+    #[account(
+        seeds = [POOL_SEED, pool.mint.as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        mut,
+        associated_token::mint = pool.mint,
+        associated_token::authority = pool,
+    )]
+    pub pool_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = funder_token_account.mint == pool.mint,
+        constraint = funder_token_account.owner == funder.key()
+    )]
+    pub funder_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct LendingDeposit<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [POOL_SEED, pool.mint.as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        mut,
+        associated_token::mint = pool.mint,
+        associated_token::authority = pool,
+    )]
+    pub pool_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + Position::INIT_SPACE,
+        seeds = [POSITION_SEED, pool.key().as_ref(), owner.key().as_ref()],
+        bump
+    )]
+    pub position: Account<'info, Position>,
+
+    #[account(
+        mut,
+        constraint = owner_token_account.mint == pool.mint,
+        constraint = owner_token_account.owner == owner.key()
+    )]
+    pub owner_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct LendingWithdraw<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [POOL_SEED, pool.mint.as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        mut,
+        associated_token::mint = pool.mint,
+        associated_token::authority = pool,
+    )]
+    pub pool_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [POSITION_SEED, pool.key().as_ref(), owner.key().as_ref()],
+        bump = position.bump,
+        constraint = position.owner == owner.key()
+    )]
+    pub position: Account<'info, Position>,
+
+    #[account(
+        mut,
+        constraint = owner_token_account.mint == pool.mint,
+        constraint = owner_token_account.owner == owner.key()
+    )]
+    pub owner_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount cannot be zero.")]
+    ZeroAmount,
+    #[msg("Withdrawal exceeds the position balance.")]
+    InsufficientPositionBalance,
+    #[msg("Pool does not hold enough liquidity for this withdrawal.")]
+    InsufficientLiquidity,
+    #[msg("An arithmetic operation failed (overflow/underflow/divide by zero).")]
+    ArithmeticError,
+}
@@ -1,36 +1,1126 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{
+    hash::hash,
+    instruction::{AccountMeta, Instruction},
+    program::invoke_signed,
+};
 use anchor_spl::{
-    associated_token::AssociatedToken,
-    token::{self, Mint, Token, TokenAccount, Transfer},
+    associated_token::{self, get_associated_token_address, AssociatedToken, Create},
+    token::{self, Approve, Burn, CloseAccount, Mint, MintTo, Token, TokenAccount, Transfer},
 };
-use anchor_lang::system_program;
 
 // Update with the actual deployed Program ID
 declare_id!("7LeARRwbauXQ1W4Cr22ZEyPUVP5wHqYijXvkvPaVpguP");
 
 pub const USER_SEED: &[u8] = b"user";
 pub const VAULT_SEED: &[u8] = b"vault";
+pub const VAULT_STATE_SEED: &[u8] = b"vault_state";
+pub const STRATEGY_SEED: &[u8] = b"strategy";
+pub const YIELD_CONFIG_SEED: &[u8] = b"yield_config";
+pub const CONFIG_SEED: &[u8] = b"config";
+pub const SECONDS_PER_YEAR: i64 = 365 * 24 * 60 * 60;
 // New constants for betting markets
 pub const MARKET_SEED: &[u8] = b"market";
 pub const BET_SEED: &[u8] = b"bet";
 pub const MARKET_ESCROW_VAULT_SEED: &[u8] = b"market_escrow_vault";
-pub const DAILY_TASK_CYCLE_SECONDS: i64 = 24 * 60 * 60; // 24 hours
-pub const RESOLUTION_GRACE_PERIOD_SECONDS: i64 = 5 * 60; // 5 minutes after task deadline
-pub const DEFAULT_PLATFORM_FEE_BASIS_POINTS: u16 = 200; // 2.00%
-// New constant for automatic market creation betting window
-pub const DEFAULT_AUTO_MARKET_BETTING_WINDOW_SECONDS: u64 = 12 * 60 * 60; // 12 hours
-
-// Treasury wallet address (6R651eq74BXg8zeQEaGX8Fm25z1N8YDqWodv3S9kUFnn)
-pub const TREASURY_WALLET_BYTES: [u8; 32] = [
-    106, 82, 54, 49, 101, 113, 55, 52, 
-    66, 88, 103, 56, 122, 101, 81, 69, 
-    97, 71, 88, 56, 70, 109, 50, 53, 
-    122, 49, 78, 56, 89, 68, 113, 87
-];
-
-// Helper function to get treasury wallet pubkey
-pub fn treasury_wallet() -> Pubkey {
-    Pubkey::new_from_array(TREASURY_WALLET_BYTES)
+pub const AFFILIATES_SEED: &[u8] = b"affiliates";
+pub const BETTOR_STATS_SEED: &[u8] = b"bettor_stats";
+pub const SUBJECT_STATS_SEED: &[u8] = b"subject_stats";
+// Anti-manipulation rules for markets
+pub const MAX_AFFILIATES: usize = 10; // Wallets a subject can declare as their own
+pub const MAX_SHORT_POSITION_BASIS_POINTS: u16 = 5_000; // One bettor's short stake is capped at 50% of the subject's locked deposit
+pub const HEAVY_SHORT_POOL_BASIS_POINTS: u16 = 6_667; // Short side holding >= 2/3 of the pool counts as short-heavy
+pub const SHORT_HEAVY_FORFEIT_BASIS_POINTS: u16 = 1_000; // Missing a short-heavy market forfeits 10% of the locked deposit to longs
+// remaining_accounts per bet for settle_bets: BetState, bettor wallet, bettor USDC account,
+// bettor LONG token ATA, bettor SHORT token ATA, BettorStats
+pub const SETTLE_BET_ACCOUNTS: usize = 6;
+// Number of task cycles tracked in UserState.task_cycle_history for market resolution
+pub const TASK_CYCLE_HISTORY_LEN: u64 = 64;
+// Miss penalties, as basis points of the locked deposit (1st, 2nd, 3rd miss)
+pub const MISS_PENALTY_TIERS_BASIS_POINTS: [u16; 3] = [1_000, 2_000, 3_000]; // 10% / 20% / 30%
+// 4th and later misses deduct a flat 25% and halve the user's yield rate
+pub const REPEAT_MISS_PENALTY_BASIS_POINTS: u16 = 2_500;
+pub const REPEAT_MISS_THRESHOLD: u64 = 4;
+// Misses below REPEAT_MISS_THRESHOLD can be bought back with a top-up within this window after the deadline
+pub const TOP_UP_WINDOW_SECONDS: i64 = 12 * 60 * 60; // 12 hours
+pub const TOP_UP_TREASURY_SHARE_BASIS_POINTS: u16 = 5_000; // 50% to app, 50% to user balance
+
+// Helper function to resolve the treasury wallet. Every context that pays fees or penalties
+// constrains its treasury_wallet_account with this, so the destination can never be spoofed.
+pub fn treasury_wallet(program_config: &ProgramConfig) -> Pubkey {
+    program_config.treasury_wallet
+}
+
+// Helper function to move an unresolved market to Cancelled. Returns false if the market
+// is already resolved or cancelled, in which case it is left untouched.
+pub fn cancel_market(market_state: &mut Account<MarketState>) -> Result<bool> {
+    if market_state.status != MarketStatus::Open && market_state.status != MarketStatus::AwaitingResolution {
+        return Ok(false);
+    }
+    market_state.status = MarketStatus::Cancelled;
+    market_state.settled_timestamp = Clock::get()?.unix_timestamp;
+
+    emit!(MarketCancelled {
+        market: market_state.key(),
+        total_long_amount: market_state.total_long_amount,
+        total_short_amount: market_state.total_short_amount,
+    });
+    Ok(true)
+}
+
+// Helper function to close a market's state and escrow vault accounts, sending their rent to `destination`.
+// The escrow token account must already be closed.
+pub fn close_market_accounts<'info>(
+    market_state: &AccountInfo<'info>,
+    market_escrow_vault: &AccountInfo<'info>,
+    destination: &AccountInfo<'info>,
+) -> Result<()> {
+    let dest_starting_lamports = destination.lamports();
+    **destination.lamports.borrow_mut() = dest_starting_lamports
+        .checked_add(market_state.lamports())
+        .ok_or(ErrorCode::ArithmeticError)?
+        .checked_add(market_escrow_vault.lamports())
+        .ok_or(ErrorCode::ArithmeticError)?;
+
+    // Zero out both accounts' lamports and data
+    **market_state.lamports.borrow_mut() = 0;
+    **market_escrow_vault.lamports.borrow_mut() = 0;
+    market_state.data.borrow_mut().fill(0);
+    market_escrow_vault.data.borrow_mut().fill(0);
+    Ok(())
+}
+
+// Helper function to start a bettor's BetState on their first trade in a market. A bet state left
+// over from an earlier market at this address is started over. Returns whether it was started.
+pub fn start_bet_state(
+    bet_state: &mut BetState,
+    market_state: &mut MarketState,
+    market: Pubkey,
+    bettor: Pubkey,
+    is_affiliate: bool,
+    bump: u8,
+) -> Result<bool> {
+    let new_market = bet_state.bettor == Pubkey::default()
+        || bet_state.market_creation_timestamp != market_state.market_creation_timestamp;
+    if new_market {
+        bet_state.market = market;
+        bet_state.market_creation_timestamp = market_state.market_creation_timestamp;
+        bet_state.bettor = bettor;
+        bet_state.long_amount = 0;
+        bet_state.short_amount = 0;
+        bet_state.winnings_claimed = false;
+        bet_state.is_affiliate = is_affiliate;
+        bet_state.bump = bump;
+        market_state.bet_count = market_state.bet_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticError)?;
+    }
+    Ok(new_market)
+}
+
+// Helper function to tally a payout (and the bet position it settles, if any) on its market,
+// used by finalize_market.
+pub fn record_claim(market_state: &mut MarketState, amount: u64, bet_settled: bool) -> Result<()> {
+    if bet_settled {
+        market_state.claimed_bet_count = market_state.claimed_bet_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticError)?;
+    }
+    market_state.total_claimed_amount = market_state.total_claimed_amount
+        .checked_add(amount)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    Ok(())
+}
+
+// Helper function to initialize a wallet's BettorStats on first use.
+pub fn init_bettor_stats(bettor_stats: &mut BettorStats, bettor: Pubkey, bump: u8) {
+    if bettor_stats.bettor == Pubkey::default() {
+        bettor_stats.bettor = bettor;
+        bettor_stats.bump = bump;
+    }
+}
+
+// Helper function to recompute a bettor's derived leaderboard fields.
+pub fn update_bettor_ratios(bettor_stats: &mut BettorStats) -> Result<()> {
+    let net_pnl = (bettor_stats.total_won as i128)
+        .checked_add(bettor_stats.total_returned as i128)
+        .and_then(|returned| returned.checked_sub(bettor_stats.total_staked as i128))
+        .ok_or(ErrorCode::ArithmeticError)?;
+    bettor_stats.net_pnl = i64::try_from(net_pnl).map_err(|_| ErrorCode::ArithmeticError)?;
+
+    let decided_markets = bettor_stats.markets_won
+        .checked_add(bettor_stats.markets_lost)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    bettor_stats.win_rate_basis_points = if decided_markets == 0 {
+        0
+    } else {
+        (bettor_stats.markets_won as u128)
+            .checked_mul(10000)
+            .ok_or(ErrorCode::ArithmeticError)?
+            .checked_div(decided_markets as u128)
+            .ok_or(ErrorCode::ArithmeticError)? as u16
+    };
+    Ok(())
+}
+
+// Helper function to record USDC a bettor put into a market. `new_market` is set on their
+// first bet or trade in it.
+pub fn record_bettor_stake(bettor_stats: &mut BettorStats, amount: u64, new_market: bool) -> Result<()> {
+    bettor_stats.total_staked = bettor_stats.total_staked
+        .checked_add(amount)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    if new_market {
+        bettor_stats.markets_entered = bettor_stats.markets_entered
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticError)?;
+    }
+    update_bettor_ratios(bettor_stats)
+}
+
+// Helper function to record USDC paid out to a bettor. Winnings come from resolved markets,
+// refunds and AMM share sales are returned stakes. `settled_bet_won` is set when the payout
+// settles the bettor's own position on a resolved market.
+pub fn record_bettor_payout(
+    bettor_stats: &mut BettorStats,
+    won_amount: u64,
+    returned_amount: u64,
+    settled_bet_won: Option<bool>,
+) -> Result<()> {
+    bettor_stats.total_won = bettor_stats.total_won
+        .checked_add(won_amount)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    bettor_stats.total_returned = bettor_stats.total_returned
+        .checked_add(returned_amount)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    match settled_bet_won {
+        Some(true) => {
+            bettor_stats.markets_won = bettor_stats.markets_won
+                .checked_add(1)
+                .ok_or(ErrorCode::ArithmeticError)?;
+        }
+        Some(false) => {
+            bettor_stats.markets_lost = bettor_stats.markets_lost
+                .checked_add(1)
+                .ok_or(ErrorCode::ArithmeticError)?;
+        }
+        None => {}
+    }
+    update_bettor_ratios(bettor_stats)
+}
+
+// Helper function to record a market's final status on its subject's stats.
+pub fn record_subject_resolution(subject_stats: &mut SubjectStats, status: &MarketStatus) -> Result<()> {
+    match status {
+        MarketStatus::ResolvedLongsWin => {
+            subject_stats.markets_resolved_long = subject_stats.markets_resolved_long
+                .checked_add(1)
+                .ok_or(ErrorCode::ArithmeticError)?;
+        }
+        MarketStatus::ResolvedShortsWin => {
+            subject_stats.markets_resolved_short = subject_stats.markets_resolved_short
+                .checked_add(1)
+                .ok_or(ErrorCode::ArithmeticError)?;
+        }
+        MarketStatus::Refunding => {
+            subject_stats.markets_refunded = subject_stats.markets_refunded
+                .checked_add(1)
+                .ok_or(ErrorCode::ArithmeticError)?;
+        }
+        _ => return Ok(()),
+    }
+    let decided_markets = subject_stats.markets_resolved_long
+        .checked_add(subject_stats.markets_resolved_short)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    if decided_markets > 0 {
+        subject_stats.long_rate_basis_points = (subject_stats.markets_resolved_long as u128)
+            .checked_mul(10000)
+            .ok_or(ErrorCode::ArithmeticError)?
+            .checked_div(decided_markets as u128)
+            .ok_or(ErrorCode::ArithmeticError)? as u16;
+    }
+    Ok(())
+}
+
+// Helper function to get the outcome token mint of one side of a market.
+pub fn outcome_mint(market_state: &MarketState, position_is_long: bool) -> Pubkey {
+    if position_is_long {
+        market_state.long_mint
+    } else {
+        market_state.short_mint
+    }
+}
+
+// Helper function to mint outcome tokens of a market, signed by its escrow vault PDA.
+pub fn mint_outcome_tokens<'info>(
+    token_program: &Program<'info, Token>,
+    mint: &Account<'info, Mint>,
+    to: &Account<'info, TokenAccount>,
+    market_escrow_vault: &Account<'info, MarketEscrowVault>,
+    amount: u64,
+) -> Result<()> {
+    let seeds = &[
+        MARKET_ESCROW_VAULT_SEED,
+        market_escrow_vault.market.as_ref(),
+        &[market_escrow_vault.bump],
+    ];
+    let signer = &[&seeds[..]];
+    let cpi_accounts = MintTo {
+        mint: mint.to_account_info(),
+        to: to.to_account_info(),
+        authority: market_escrow_vault.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer);
+    token::mint_to(cpi_ctx, amount)
+}
+
+// Helper function to burn outcome tokens from a token account owned by the signer.
+pub fn burn_outcome_tokens<'info>(
+    token_program: &Program<'info, Token>,
+    mint: &Account<'info, Mint>,
+    from: &Account<'info, TokenAccount>,
+    owner: &Signer<'info>,
+    amount: u64,
+) -> Result<()> {
+    let cpi_accounts = Burn {
+        mint: mint.to_account_info(),
+        from: from.to_account_info(),
+        authority: owner.to_account_info(),
+    };
+    token::burn(CpiContext::new(token_program.to_account_info(), cpi_accounts), amount)
+}
+
+// Helper function to mint outcome tokens to a bettor and let the escrow vault PDA burn them on
+// the bettor's behalf in settle_bets. Revoking the delegation opts out of batch settlement until
+// the bettor's next bet or trade on the side. A delegate the bettor approved themselves is kept,
+// their position is then only redeemed via claim_winnings/claim_refund.
+pub fn issue_position_tokens<'info>(
+    token_program: &Program<'info, Token>,
+    mint: &Account<'info, Mint>,
+    to: &Account<'info, TokenAccount>,
+    bettor: &Signer<'info>,
+    market_escrow_vault: &Account<'info, MarketEscrowVault>,
+    amount: u64,
+) -> Result<()> {
+    mint_outcome_tokens(token_program, mint, to, market_escrow_vault, amount)?;
+    if to.delegate.is_some() && to.delegate != Some(market_escrow_vault.key()).into() {
+        return Ok(());
+    }
+    // `to` was loaded before the mint, so its balance is now amount higher
+    let position_amount = to.amount
+        .checked_add(amount)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    let cpi_accounts = Approve {
+        to: to.to_account_info(),
+        delegate: market_escrow_vault.to_account_info(),
+        authority: bettor.to_account_info(),
+    };
+    token::approve(CpiContext::new(token_program.to_account_info(), cpi_accounts), position_amount)
+}
+
+// Helper function to create a fee recipient's USDC ATA, paid by `payer`, unless it exists. Fee
+// accounts are only created when a fee is paid out to them, which also pays the caller a bounty.
+pub fn create_fee_token_account<'info>(
+    payer: &Signer<'info>,
+    token_account: &AccountInfo<'info>,
+    wallet: &AccountInfo<'info>,
+    usdc_mint: &Account<'info, Mint>,
+    system_program: &Program<'info, System>,
+    token_program: &Program<'info, Token>,
+    associated_token_program: &Program<'info, AssociatedToken>,
+) -> Result<()> {
+    if !token_account.data_is_empty() {
+        return Ok(());
+    }
+    let cpi_accounts = Create {
+        payer: payer.to_account_info(),
+        associated_token: token_account.clone(),
+        authority: wallet.clone(),
+        mint: usdc_mint.to_account_info(),
+        system_program: system_program.to_account_info(),
+        token_program: token_program.to_account_info(),
+    };
+    associated_token::create(CpiContext::new(associated_token_program.to_account_info(), cpi_accounts))
+}
+
+// Helper function for the USDC paid for outcome tokens burned on a settled market. Winning tokens
+// pay their share of the pool (1 USDC unit each in AMM markets); when stakes are refunded every
+// token returns 1 USDC unit. Shares of a cancelled AMM market pay nothing, as their price moved
+// with every trade; the traders' stakes are refunded instead, see refund_amm_stake.
+pub fn redemption_amount(market_state: &MarketState, long_tokens: u64, short_tokens: u64) -> Result<u64> {
+    let is_amm = market_state.market_mode == MarketMode::Amm;
+    if market_state.status.refunds_stakes() {
+        let returned_tokens = long_tokens
+            .checked_add(short_tokens)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        return Ok(if is_amm { 0 } else { returned_tokens });
+    }
+    let (winning_tokens, winning_side_total) = match market_state.status {
+        MarketStatus::ResolvedLongsWin => (long_tokens, market_state.total_long_amount),
+        MarketStatus::ResolvedShortsWin => (short_tokens, market_state.total_short_amount),
+        _ => return err!(ErrorCode::MarketNotSettled),
+    };
+    if is_amm || winning_tokens == 0 {
+        return Ok(winning_tokens);
+    }
+    // Parimutuel winning tokens were minted 1:1 with the winning side's stakes
+    let total_pool = market_state.total_long_amount
+        .checked_add(market_state.total_short_amount)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    parimutuel_payout(winning_tokens, winning_side_total, total_pool, market_state.platform_fee_basis_points)
+}
+
+// Helper function to tell whether a bet's own position won on a resolved market, for the bettor's
+// win rate (None if the market refunds stakes or nothing is left staked). Only the bet's stakes
+// count, not transferred tokens or forfeited deposit shares. A hedged parimutuel position wins if
+// its winning leg pays back more than both legs cost; AMM stakes don't record the shares they
+// bought, so a hedged AMM position wins if more of it was staked on the winning side.
+pub fn bet_position_won(market_state: &MarketState, bet_state: &BetState) -> Result<Option<bool>> {
+    let (winning_stake, losing_stake) = match market_state.status {
+        MarketStatus::ResolvedLongsWin => (bet_state.long_amount, bet_state.short_amount),
+        MarketStatus::ResolvedShortsWin => (bet_state.short_amount, bet_state.long_amount),
+        _ => return Ok(None),
+    };
+    if winning_stake == 0 || losing_stake == 0 {
+        return Ok((winning_stake > 0 || losing_stake > 0).then_some(winning_stake > 0));
+    }
+    if market_state.market_mode == MarketMode::Amm {
+        return Ok(Some(winning_stake > losing_stake));
+    }
+    let total_stake = winning_stake
+        .checked_add(losing_stake)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    let winning_payout = redemption_amount(market_state, bet_state.long_amount, bet_state.short_amount)?;
+    Ok(Some(winning_payout > total_stake))
+}
+
+// Helper function for the refund of a trader's bet state on a cancelled AMM market: the USDC they
+// paid for shares, net of what they received selling shares. Settles the stake on the market.
+pub fn refund_amm_stake(market_state: &mut MarketState, bet_state: &BetState) -> Result<u64> {
+    if market_state.market_mode != MarketMode::Amm || !market_state.status.refunds_stakes() {
+        return Ok(0);
+    }
+    let stake_amount = bet_state.long_amount
+        .checked_add(bet_state.short_amount)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    market_state.amm_stake_amount = market_state.amm_stake_amount
+        .checked_sub(stake_amount)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    Ok(stake_amount)
+}
+
+// Helper function for the share of a forfeited subject deposit paid for LONG tokens burned on a
+// short-heavy market the subject missed. The deposit is split per LONG token held outside the
+// subject's affiliates at resolution; payouts are capped by what is left of it, since affiliate
+// tokens may change hands after resolution.
+pub fn forfeit_share(market_state: &MarketState, long_tokens: u64) -> Result<u64> {
+    if market_state.status != MarketStatus::ResolvedShortsWin
+        || market_state.forfeited_amount == 0
+        || market_state.forfeit_eligible_long_tokens == 0
+        || long_tokens == 0
+    {
+        return Ok(0);
+    }
+    let share = (long_tokens as u128)
+        .checked_mul(market_state.forfeited_amount as u128)
+        .ok_or(ErrorCode::ArithmeticError)?
+        .checked_div(market_state.forfeit_eligible_long_tokens as u128)
+        .ok_or(ErrorCode::ArithmeticError)? as u64;
+    let remaining = market_state.forfeited_amount
+        .checked_sub(market_state.forfeit_claimed_amount)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    Ok(share.min(remaining))
+}
+
+// Helper function to check whether a wallet is the market's subject or one of their declared affiliates.
+pub fn is_subject_or_affiliate(market_state: &MarketState, subject_affiliates: &AccountInfo, wallet: &Pubkey) -> Result<bool> {
+    Ok(*wallet == market_state.user_being_bet_on
+        || load_affiliate_list(subject_affiliates)?.is_some_and(|list| list.affiliates.contains(wallet)))
+}
+
+// Helper function to load a bettor's outcome token balance that the escrow vault may burn for
+// settle_bets. The account must be the bettor's ATA for the mint; a missing ATA holds nothing.
+pub fn delegated_outcome_tokens<'info>(
+    token_account_info: &'info AccountInfo<'info>,
+    bettor: &Pubkey,
+    mint: &Pubkey,
+    market_escrow_vault: &Pubkey,
+) -> Result<Option<(Account<'info, TokenAccount>, u64)>> {
+    if token_account_info.key() != get_associated_token_address(bettor, mint) {
+        return err!(ErrorCode::InvalidSettleAccounts);
+    }
+    if token_account_info.data_is_empty() {
+        return Ok(None);
+    }
+    let token_account = Account::<TokenAccount>::try_from(token_account_info)?;
+    let burnable = if token_account.delegate == Some(*market_escrow_vault).into() {
+        token_account.amount.min(token_account.delegated_amount)
+    } else {
+        0
+    };
+    Ok(Some((token_account, burnable)))
+}
+
+// Helper function to load a subject's declared affiliate list, if they have declared one.
+pub fn load_affiliate_list(account: &AccountInfo) -> Result<Option<AffiliateList>> {
+    if account.owner != &crate::ID || account.data_is_empty() {
+        return Ok(None);
+    }
+    let data = account.try_borrow_data()?;
+    Ok(Some(AffiliateList::try_deserialize(&mut &data[..])?))
+}
+
+// Helper function to check whether short positions make up at least HEAVY_SHORT_POOL_BASIS_POINTS
+// of all positions. Positions are the outstanding outcome tokens: stakes in parimutuel markets,
+// traders' shares in AMM markets.
+pub fn is_short_heavy(long_position: u64, short_position: u64) -> Result<bool> {
+    let total_pool = long_position
+        .checked_add(short_position)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    if total_pool == 0 {
+        return Ok(false);
+    }
+    let short_share = (short_position as u128)
+        .checked_mul(10000)
+        .ok_or(ErrorCode::ArithmeticError)?
+        .checked_div(total_pool as u128)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    Ok(short_share >= HEAVY_SHORT_POOL_BASIS_POINTS as u128)
+}
+
+// Helper function to get the platform fee taken from a market's total pool at resolution.
+pub fn platform_fee(total_pool: u64, platform_fee_basis_points: u16) -> Result<u64> {
+    let fee = (total_pool as u128)
+        .checked_mul(platform_fee_basis_points as u128)
+        .ok_or(ErrorCode::ArithmeticError)?
+        .checked_div(10000)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    Ok(fee as u64)
+}
+
+// Helper function for the parimutuel payout of a winning stake: its pro-rata share of the pool
+// after the platform fee. Shared by claim_winnings and quote_bet so clients see the exact payout.
+pub fn parimutuel_payout(
+    stake: u64,
+    winning_side_total: u64,
+    total_pool: u64,
+    platform_fee_basis_points: u16,
+) -> Result<u64> {
+    if winning_side_total == 0 {
+        return Ok(0);
+    }
+    let net_pool = total_pool
+        .checked_sub(platform_fee(total_pool, platform_fee_basis_points)?)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    let payout = (stake as u128)
+        .checked_mul(net_pool as u128)
+        .ok_or(ErrorCode::ArithmeticError)?
+        .checked_div(winning_side_total as u128)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    u64::try_from(payout).map_err(|_| error!(ErrorCode::ArithmeticError))
+}
+
+// Helper function to get the market's implied probability that longs win: the long share of the pool.
+// An empty pool is quoted at 50%.
+pub fn implied_long_probability_basis_points(total_long_amount: u64, total_short_amount: u64) -> Result<u16> {
+    let total_pool = total_long_amount
+        .checked_add(total_short_amount)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    if total_pool == 0 {
+        return Ok(5000);
+    }
+    let probability = (total_long_amount as u128)
+        .checked_mul(10000)
+        .ok_or(ErrorCode::ArithmeticError)?
+        .checked_div(total_pool as u128)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    Ok(probability as u16)
+}
+
+// Helper function to scale the subject's locked deposit by a multiple in basis points, for the
+// pool and position caps.
+pub fn deposit_multiple(user_state: &UserState, multiple_basis_points: u64) -> Result<u64> {
+    let amount = (user_state.initial_deposit_amount as u128)
+        .checked_mul(multiple_basis_points as u128)
+        .ok_or(ErrorCode::ArithmeticError)?
+        .checked_div(10000)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    Ok(u64::try_from(amount).unwrap_or(u64::MAX))
+}
+
+// Helper function to cap a market's pool relative to the subject's locked deposit, so the stakes
+// stay proportional to what the subject risks. AMM pools count the liquidity as well as the stakes.
+pub fn check_pool_cap(program_config: &ProgramConfig, user_state_for_bet: &UserState, new_total_pool: u64) -> Result<()> {
+    let max_pool = deposit_multiple(user_state_for_bet, program_config.max_pool_deposit_multiple_basis_points)?;
+    if new_total_pool > max_pool {
+        return err!(ErrorCode::MarketPoolCapExceeded);
+    }
+    Ok(())
+}
+
+// Helper function applying the anti-manipulation rules to a new long/short position and
+// returning whether the bettor is a declared affiliate of the subject. `new_position` is the
+// bettor's stake on both sides including the new bet.
+pub fn check_bettor_limits(
+    program_config: &ProgramConfig,
+    subject_affiliates: &AccountInfo,
+    user_state_for_bet: &UserState,
+    bettor: Pubkey,
+    position_is_long: bool,
+    new_short_position: u64,
+    new_position: u64,
+) -> Result<bool> {
+    // Declared affiliates count as the subject's own exposure: they can back the subject but
+    // never bet against them, since the subject controls the outcome
+    let is_affiliate = load_affiliate_list(subject_affiliates)?
+        .is_some_and(|list| list.affiliates.contains(&bettor));
+    if is_affiliate && !position_is_long {
+        return err!(ErrorCode::AffiliateCannotShort);
+    }
+    // Cap any single bettor's short position relative to the subject's locked deposit, so a
+    // short position can't be worth more than the deposit the subject risks by missing
+    if !position_is_long {
+        let max_short_position = deposit_multiple(user_state_for_bet, MAX_SHORT_POSITION_BASIS_POINTS as u64)?;
+        if new_short_position > max_short_position {
+            return err!(ErrorCode::ShortPositionTooLarge);
+        }
+    }
+    // Cap any single bettor's total position, so no one wallet dominates a market
+    let max_position = deposit_multiple(user_state_for_bet, program_config.max_bettor_deposit_multiple_basis_points)?;
+    if new_position > max_position {
+        return err!(ErrorCode::BettorPositionTooLarge);
+    }
+    Ok(is_affiliate)
+}
+
+// Helper function for the smallest integer >= sqrt(value).
+pub fn ceil_sqrt(value: u128) -> u128 {
+    if value < 2 {
+        return value;
+    }
+    // Newton's method for floor(sqrt(value))
+    let mut x = value;
+    let mut y = x.div_ceil(2);
+    while y < x {
+        x = y;
+        y = (x + value / x) / 2;
+    }
+    if x * x < value { x + 1 } else { x }
+}
+
+// Helper function for a CPMM buy of outcome shares. The collateral (after fees) mints complete
+// sets (1 LONG + 1 SHORT per USDC unit) into the pool, which then pays out shares of the bought
+// side keeping reserve_long * reserve_short constant. Rounds in favour of the pool.
+// Returns (shares_out, new_side_reserve, new_other_reserve).
+pub fn amm_buy(side_reserve: u64, other_reserve: u64, collateral: u64) -> Result<(u64, u64, u64)> {
+    let invariant = (side_reserve as u128)
+        .checked_mul(other_reserve as u128)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    let new_other_reserve = other_reserve
+        .checked_add(collateral)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    let minted_side_reserve = side_reserve
+        .checked_add(collateral)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    let new_side_reserve = invariant.div_ceil(new_other_reserve as u128) as u64;
+    let shares_out = minted_side_reserve
+        .checked_sub(new_side_reserve)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    Ok((shares_out, new_side_reserve, new_other_reserve))
+}
+
+// Helper function for a CPMM sell of outcome shares: the shares go into the pool, which burns
+// complete sets for the largest collateral amount x keeping the invariant, i.e. the smaller root of
+// x^2 - (side + shares + other) x + shares * other = 0. Rounds in favour of the pool.
+// Returns (collateral_out, new_side_reserve, new_other_reserve).
+pub fn amm_sell(side_reserve: u64, other_reserve: u64, shares_in: u64) -> Result<(u64, u64, u64)> {
+    let sum = (side_reserve as u128)
+        .checked_add(shares_in as u128)
+        .and_then(|value| value.checked_add(other_reserve as u128))
+        .ok_or(ErrorCode::ArithmeticError)?;
+    let discriminant = sum
+        .checked_mul(sum)
+        .and_then(|square| {
+            (shares_in as u128)
+                .checked_mul(other_reserve as u128)
+                .and_then(|product| product.checked_mul(4))
+                .and_then(|product| square.checked_sub(product))
+        })
+        .ok_or(ErrorCode::ArithmeticError)?;
+    let collateral_out = (sum
+        .checked_sub(ceil_sqrt(discriminant))
+        .ok_or(ErrorCode::ArithmeticError)?
+        / 2) as u64;
+    let new_side_reserve = side_reserve
+        .checked_add(shares_in)
+        .and_then(|reserve| reserve.checked_sub(collateral_out))
+        .ok_or(ErrorCode::ArithmeticError)?;
+    let new_other_reserve = other_reserve
+        .checked_sub(collateral_out)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    Ok((collateral_out, new_side_reserve, new_other_reserve))
+}
+
+// Helper function to sanity check config parameters before storing them.
+pub fn validate_config_params(params: &ConfigParams) -> Result<()> {
+    if params.daily_task_cycle_seconds <= 0
+        || params.resolution_grace_period_seconds < 0
+        || params.default_platform_fee_basis_points > 10000
+        || params.early_exit_fee_basis_points > 10000
+        || params.auto_market_betting_window_seconds as i64 >= params.daily_task_cycle_seconds
+        || params.claim_period_seconds < 0
+        || params.min_platform_fee_basis_points > params.default_platform_fee_basis_points
+        || params.default_platform_fee_basis_points > params.max_platform_fee_basis_points
+        || params.max_fee_rebate_basis_points > 10000
+        || params.resolution_bounty_basis_points > 10000
+        || params.dispute_window_seconds < 0
+        // Disputes need someone to rule on them and a bond that makes frivolous ones costly
+        || (params.dispute_window_seconds > 0 && params.resolver_authority == Pubkey::default())
+        || (params.dispute_window_seconds > 0 && params.dispute_bond_amount == 0)
+        || params.max_bettor_deposit_multiple_basis_points == 0
+        || params.max_bettor_deposit_multiple_basis_points > params.max_pool_deposit_multiple_basis_points
+    {
+        return err!(ErrorCode::InvalidConfigParams);
+    }
+    Ok(())
+}
+
+// Helper function to get the penalty tier (in basis points) for a given miss count.
+// miss_count is the count *including* the miss being penalized, so the first miss is 1.
+pub fn miss_penalty_basis_points(miss_count: u64) -> u16 {
+    match miss_count {
+        0 => 0,
+        1..=3 => MISS_PENALTY_TIERS_BASIS_POINTS[(miss_count - 1) as usize],
+        _ => REPEAT_MISS_PENALTY_BASIS_POINTS,
+    }
+}
+
+// Helper function to get a user's current APR in basis points:
+// duration tier APR * streak multiplier, capped at max_apr, halved after the 4th+ miss.
+pub fn current_apr_basis_points(user_state: &UserState, params: &YieldParams) -> Result<u64> {
+    let lock_in_days = user_state.lock_in_end_timestamp
+        .checked_sub(user_state.deposit_timestamp)
+        .ok_or(ErrorCode::ArithmeticError)?
+        .checked_div(24 * 60 * 60)
+        .ok_or(ErrorCode::ArithmeticError)?;
+
+    // Highest duration tier the lock-in qualifies for
+    let mut base_apr: u64 = 0;
+    for (tier_days, tier_apr) in params.duration_tier_days.iter().zip(params.duration_tier_apr_basis_points.iter()) {
+        if lock_in_days >= 0 && lock_in_days as u64 >= *tier_days {
+            base_apr = *tier_apr as u64;
+        }
+    }
+
+    let streak_multiplier = user_state.current_streak
+        .saturating_mul(params.streak_bonus_per_day_basis_points as u64)
+        .saturating_add(10000)
+        .min(params.max_streak_multiplier_basis_points as u64);
+
+    let mut apr = base_apr
+        .checked_mul(streak_multiplier)
+        .ok_or(ErrorCode::ArithmeticError)?
+        .checked_div(10000)
+        .ok_or(ErrorCode::ArithmeticError)?
+        .min(params.max_apr_basis_points as u64);
+
+    if user_state.yield_rate_halved {
+        apr /= 2;
+    }
+    Ok(apr)
+}
+
+// Helper function to settle yield accrued on the locked deposit since the last accrual.
+// Yield accrues continuously up to the end of the lock-in at the user's current APR and is
+// recorded as a vault liability. Must be called before anything that changes the rate inputs.
+pub fn settle_yield(
+    user_state: &mut UserState,
+    vault_state: &mut VaultState,
+    params: &YieldParams,
+    now: i64,
+) -> Result<u64> {
+    if user_state.lock_in_end_timestamp == 0 || user_state.initial_deposit_amount == 0 {
+        user_state.last_yield_accrual_timestamp = now;
+        return Ok(0);
+    }
+
+    let accrual_start = user_state.last_yield_accrual_timestamp.max(user_state.deposit_timestamp);
+    let accrual_end = now.min(user_state.lock_in_end_timestamp);
+    if accrual_end <= accrual_start {
+        return Ok(0);
+    }
+    let elapsed = accrual_end - accrual_start;
+
+    let apr = current_apr_basis_points(user_state, params)?;
+    let yield_amount = (user_state.initial_deposit_amount as u128)
+        .checked_mul(apr as u128)
+        .ok_or(ErrorCode::ArithmeticError)?
+        .checked_mul(elapsed as u128)
+        .ok_or(ErrorCode::ArithmeticError)?
+        .checked_div(10000u128 * SECONDS_PER_YEAR as u128)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    let yield_amount = u64::try_from(yield_amount).map_err(|_| ErrorCode::ArithmeticError)?;
+
+    user_state.accrued_yield = user_state.accrued_yield
+        .checked_add(yield_amount)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    user_state.last_yield_accrual_timestamp = accrual_end;
+
+    // Yield is a liability of the vault until it is settled from the reserve in withdraw
+    vault_state.total_yield_liabilities = vault_state.total_yield_liabilities
+        .checked_add(yield_amount)
+        .ok_or(ErrorCode::ArithmeticError)?;
+
+    msg!("Settled {} yield over {}s at {} bps APR", yield_amount, elapsed, apr);
+    Ok(yield_amount)
+}
+
+// Helper function to sanity check yield parameters before storing them.
+pub fn validate_yield_params(params: &YieldParams) -> Result<()> {
+    let tiers_ascending = params.duration_tier_days.windows(2).all(|w| w[0] < w[1]);
+    let aprs_within_cap = params.duration_tier_apr_basis_points
+        .iter()
+        .all(|apr| *apr <= params.max_apr_basis_points);
+    if !tiers_ascending
+        || !aprs_within_cap
+        || params.max_apr_basis_points > 10000
+        || params.max_streak_multiplier_basis_points < 10000
+    {
+        return err!(ErrorCode::InvalidYieldParams);
+    }
+    Ok(())
+}
+
+// Helper function to build instruction data for the yield strategy interface.
+// Lending programs are called through a narrow, Anchor-compatible interface:
+//   deposit(amount)  accounts: [payer, owner, pool, pool_token_account, position, owner_token_account, token_program, system_program]
+//   withdraw(amount) accounts: [owner, pool, pool_token_account, position, owner_token_account, token_program]
+// where owner is the vault PDA and withdraw(u64::MAX) withdraws the full position.
+pub fn strategy_instruction_data(instruction_name: &str, amount: u64) -> Vec<u8> {
+    let preimage = format!("global:{}", instruction_name);
+    let mut data = hash(preimage.as_bytes()).to_bytes()[..8].to_vec();
+    data.extend_from_slice(&amount.to_le_bytes());
+    data
+}
+
+// Helper function to CPI `deposit` into the strategy's lending program, signed by the vault PDA.
+pub fn strategy_deposit<'info>(
+    accounts: &StrategyOperation<'info>,
+    amount: u64,
+    vault_signer: &[&[&[u8]]],
+) -> Result<()> {
+    let ix = Instruction {
+        program_id: accounts.lending_program.key(),
+        accounts: vec![
+            AccountMeta::new(accounts.caller.key(), true),
+            AccountMeta::new_readonly(accounts.vault.key(), true),
+            AccountMeta::new(accounts.lending_pool.key(), false),
+            AccountMeta::new(accounts.lending_pool_token_account.key(), false),
+            AccountMeta::new(accounts.lending_position.key(), false),
+            AccountMeta::new(accounts.vault_token_account.key(), false),
+            AccountMeta::new_readonly(accounts.token_program.key(), false),
+            AccountMeta::new_readonly(accounts.system_program.key(), false),
+        ],
+        data: strategy_instruction_data("deposit", amount),
+    };
+    invoke_signed(
+        &ix,
+        &[
+            accounts.caller.to_account_info(),
+            accounts.vault.to_account_info(),
+            accounts.lending_pool.to_account_info(),
+            accounts.lending_pool_token_account.to_account_info(),
+            accounts.lending_position.to_account_info(),
+            accounts.vault_token_account.to_account_info(),
+            accounts.token_program.to_account_info(),
+            accounts.system_program.to_account_info(),
+            accounts.lending_program.to_account_info(),
+        ],
+        vault_signer,
+    )?;
+    Ok(())
+}
+
+// Helper function to CPI `withdraw` from the strategy's lending program, signed by the vault PDA.
+pub fn strategy_withdraw<'info>(
+    accounts: &StrategyOperation<'info>,
+    amount: u64,
+    vault_signer: &[&[&[u8]]],
+) -> Result<()> {
+    let ix = Instruction {
+        program_id: accounts.lending_program.key(),
+        accounts: vec![
+            AccountMeta::new_readonly(accounts.vault.key(), true),
+            AccountMeta::new(accounts.lending_pool.key(), false),
+            AccountMeta::new(accounts.lending_pool_token_account.key(), false),
+            AccountMeta::new(accounts.lending_position.key(), false),
+            AccountMeta::new(accounts.vault_token_account.key(), false),
+            AccountMeta::new_readonly(accounts.token_program.key(), false),
+        ],
+        data: strategy_instruction_data("withdraw", amount),
+    };
+    invoke_signed(
+        &ix,
+        &[
+            accounts.vault.to_account_info(),
+            accounts.lending_pool.to_account_info(),
+            accounts.lending_pool_token_account.to_account_info(),
+            accounts.lending_position.to_account_info(),
+            accounts.vault_token_account.to_account_info(),
+            accounts.token_program.to_account_info(),
+            accounts.lending_program.to_account_info(),
+        ],
+        vault_signer,
+    )?;
+    Ok(())
+}
+
+// Helper function to get the deadline of the next task window that has not been penalized yet.
+// A window starts at the later of the last task and the last recorded miss deadline.
+pub fn next_miss_deadline(user_state: &UserState, daily_task_cycle_seconds: i64) -> Result<i64> {
+    let window_start = user_state.last_task_timestamp.max(user_state.last_miss_deadline_timestamp);
+    let deadline = window_start
+        .checked_add(daily_task_cycle_seconds)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    Ok(deadline)
+}
+
+// Helper function to get the index of the task cycle containing `timestamp`.
+// Cycles are fixed windows of the course's task_cycle_seconds counted from the course start (deposit_timestamp),
+// so cycle N is "day N" of the course for every client.
+pub fn cycle_index_at(user_state: &UserState, timestamp: i64, daily_task_cycle_seconds: i64) -> Result<u64> {
+    let elapsed = timestamp
+        .checked_sub(user_state.deposit_timestamp)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    if elapsed < 0 {
+        return err!(ErrorCode::ArithmeticError);
+    }
+    let cycle_index = elapsed
+        .checked_div(daily_task_cycle_seconds)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    Ok(cycle_index as u64)
+}
+
+// Helper function to get the (start, deadline) timestamps of a task cycle.
+pub fn cycle_window(user_state: &UserState, cycle_index: u64, daily_task_cycle_seconds: i64) -> Result<(i64, i64)> {
+    let cycle_start = i64::try_from(cycle_index)
+        .map_err(|_| ErrorCode::ArithmeticError)?
+        .checked_mul(daily_task_cycle_seconds)
+        .and_then(|offset| user_state.deposit_timestamp.checked_add(offset))
+        .ok_or(ErrorCode::ArithmeticError)?;
+    let cycle_deadline = cycle_start
+        .checked_add(daily_task_cycle_seconds)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    Ok((cycle_start, cycle_deadline))
+}
+
+// Helper function to mark a task cycle as completed in the user's rolling task history.
+// Bit k of task_cycle_history is set if a task was recorded in cycle (last_task_cycle_index - k).
+pub fn mark_cycle_completed(user_state: &mut UserState, cycle_index: u64) {
+    if cycle_index < user_state.last_task_cycle_index {
+        return;
+    }
+    let shift = cycle_index - user_state.last_task_cycle_index;
+    user_state.task_cycle_history = if shift >= TASK_CYCLE_HISTORY_LEN {
+        0
+    } else {
+        user_state.task_cycle_history << shift
+    };
+    user_state.task_cycle_history |= 1;
+    user_state.last_task_cycle_index = cycle_index;
+}
+
+// Helper function to check whether a task was recorded during the given cycle.
+// Cycles older than the tracked history are reported as not completed, see task_history_covers.
+pub fn cycle_completed(user_state: &UserState, cycle_index: u64) -> bool {
+    if cycle_index > user_state.last_task_cycle_index {
+        return false;
+    }
+    let age = user_state.last_task_cycle_index - cycle_index;
+    age < TASK_CYCLE_HISTORY_LEN && (user_state.task_cycle_history >> age) & 1 == 1
+}
+
+// Helper function to check whether the rolling task history still reaches back to the given cycle.
+pub fn task_history_covers(user_state: &UserState, cycle_index: u64) -> bool {
+    user_state.last_task_cycle_index.saturating_sub(cycle_index) < TASK_CYCLE_HISTORY_LEN
+}
+
+// Helper function to build the seed telling apart markets of one course that start at the same
+// cycle: market type and mode, then horizon and streak threshold (little-endian).
+pub fn market_terms_seed(streak_params: Option<StreakMarketParams>, market_mode: MarketMode) -> [u8; 18] {
+    let (market_type, params) = match streak_params {
+        Some(params) => (MarketType::StreakSurvival, params),
+        None => (MarketType::SingleCycle, StreakMarketParams { horizon_cycles: 1, streak_threshold: 1 }),
+    };
+    let mut seed = [0u8; 18];
+    seed[0] = market_type as u8;
+    seed[1] = market_mode as u8;
+    seed[2..10].copy_from_slice(&params.horizon_cycles.to_le_bytes());
+    seed[10..18].copy_from_slice(&params.streak_threshold.to_le_bytes());
+    seed
+}
+
+// Helper function to fill in a freshly created market starting at one task cycle of the subject's course.
+// `streak_params` is None for a single-cycle market. Callers set the account bumps and the escrow vault.
+#[allow(clippy::too_many_arguments)]
+pub fn initialize_market(
+    market_state: &mut Account<MarketState>,
+    market_creator: Pubkey,
+    user_state: &Account<UserState>,
+    cycle_index: u64,
+    streak_params: Option<StreakMarketParams>,
+    fee_params: Option<MarketFeeParams>,
+    betting_ends_timestamp: i64,
+    config: &ProgramConfig,
+    current_timestamp: i64,
+) -> Result<()> {
+    let (market_type, params) = match streak_params {
+        Some(params) => {
+            // The horizon must fit in the task history used to resolve the market
+            if params.horizon_cycles == 0
+                || params.horizon_cycles > TASK_CYCLE_HISTORY_LEN
+                || params.streak_threshold == 0
+                || params.streak_threshold > params.horizon_cycles
+            {
+                return err!(ErrorCode::InvalidStreakMarketParams);
+            }
+            (MarketType::StreakSurvival, params)
+        }
+        None => (MarketType::SingleCycle, StreakMarketParams { horizon_cycles: 1, streak_threshold: 1 }),
+    };
+    let last_cycle_index = cycle_index
+        .checked_add(params.horizon_cycles - 1)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    let (_, first_cycle_deadline) = cycle_window(user_state, cycle_index, user_state.task_cycle_seconds)?;
+    let (_, task_deadline_timestamp) = cycle_window(user_state, last_cycle_index, user_state.task_cycle_seconds)?;
+
+    // Markets only cover cycles that are still running and end within the lock-in period
+    if first_cycle_deadline <= current_timestamp {
+        return err!(ErrorCode::CycleAlreadyEnded);
+    }
+    if task_deadline_timestamp > user_state.lock_in_end_timestamp {
+        return err!(ErrorCode::CycleOutsideCourse);
+    }
+    // Ensure betting ends strictly before the first task deadline, before any cycle can be missed
+    if betting_ends_timestamp >= first_cycle_deadline {
+        return err!(ErrorCode::BettingWindowTooLong);
+    }
+    // Creator-chosen fee terms must stay within the admin-set bounds
+    let fee_params = fee_params.unwrap_or(MarketFeeParams {
+        platform_fee_basis_points: config.default_platform_fee_basis_points,
+        rebate_basis_points: 0,
+        rebate_to_subject: false,
+    });
+    if fee_params.platform_fee_basis_points < config.min_platform_fee_basis_points
+        || fee_params.platform_fee_basis_points > config.max_platform_fee_basis_points
+        || fee_params.rebate_basis_points > config.max_fee_rebate_basis_points
+    {
+        return err!(ErrorCode::InvalidMarketFeeParams);
+    }
+
+    market_state.market_creator = market_creator;
+    market_state.user_being_bet_on = user_state.user;
+    market_state.user_state_account_for_bet = user_state.key();
+    market_state.cycle_index = cycle_index;
+    market_state.course_start_timestamp = user_state.deposit_timestamp;
+    market_state.course_index = user_state.course_count;
+    market_state.market_type = market_type;
+    market_state.horizon_cycles = params.horizon_cycles;
+    market_state.streak_threshold = params.streak_threshold;
+    market_state.total_long_amount = 0;
+    market_state.total_short_amount = 0;
+    market_state.market_creation_timestamp = current_timestamp;
+    market_state.betting_ends_timestamp = betting_ends_timestamp;
+    market_state.task_deadline_timestamp = task_deadline_timestamp;
+    market_state.resolution_timestamp = task_deadline_timestamp
+        .checked_add(config.resolution_grace_period_seconds)
+        .ok_or(ErrorCode::ArithmeticError)?;
+    market_state.status = MarketStatus::Open;
+    market_state.platform_fee_basis_points = fee_params.platform_fee_basis_points;
+    market_state.fee_rebate_basis_points = fee_params.rebate_basis_points;
+    market_state.fee_rebate_recipient = if fee_params.rebate_to_subject {
+        user_state.user
+    } else {
+        market_creator
+    };
+    market_state.platform_fee_claimed = false;
+    market_state.bet_count = 0;
+    market_state.claimed_bet_count = 0;
+    market_state.total_claimed_amount = 0;
+    market_state.settled_timestamp = 0;
+    market_state.affiliate_long_amount = 0;
+    market_state.forfeited_amount = 0;
+    market_state.forfeit_eligible_long_tokens = 0;
+    market_state.forfeit_claimed_amount = 0;
+    market_state.implied_long_probability_basis_points = 5000; // No bets yet
+    market_state.market_mode = MarketMode::Parimutuel;
+    market_state.amm_long_reserve = 0;
+    market_state.amm_short_reserve = 0;
+    market_state.amm_fee_amount = 0;
+    market_state.amm_liquidity_redeemed = false;
+    market_state.amm_liquidity_amount = 0;
+    market_state.amm_stake_amount = 0;
+    market_state.amm_sold_beyond_stakes_amount = 0;
+    market_state.provisional_longs_win = false;
+    market_state.dispute_ends_timestamp = 0;
+    market_state.disputer = Pubkey::default();
+    market_state.dispute_bond_amount = 0;
+    market_state.ruling_deadline_timestamp = 0;
+
+    emit!(MarketCreated {
+        market: market_state.key(),
+        user_being_bet_on: market_state.user_being_bet_on,
+        cycle_index,
+        market_type: market_state.market_type.clone(),
+        horizon_cycles: market_state.horizon_cycles,
+        streak_threshold: market_state.streak_threshold,
+        task_deadline_timestamp: market_state.task_deadline_timestamp,
+        betting_ends_timestamp: market_state.betting_ends_timestamp,
+        platform_fee_basis_points: market_state.platform_fee_basis_points,
+        fee_rebate_basis_points: market_state.fee_rebate_basis_points,
+        fee_rebate_recipient: market_state.fee_rebate_recipient,
+    });
+    Ok(())
+}
+
+// Helper function to evaluate a market over its cycles [cycle_index, cycle_index + horizon_cycles).
+// Longs win if the subject completes streak_threshold consecutive cycles inside the horizon.
+// Returns None while the outcome is open, Some(false) as soon as the threshold can no longer
+// be reached and Some(longs_win) once every cycle of the horizon has ended.
+pub fn market_outcome(
+    user_state: &UserState,
+    market_state: &MarketState,
+    current_timestamp: i64,
+    daily_task_cycle_seconds: i64,
+) -> Result<Option<bool>> {
+    let last_cycle_index = market_state.horizon_cycles
+        .checked_sub(1)
+        .and_then(|offset| market_state.cycle_index.checked_add(offset))
+        .ok_or(ErrorCode::ArithmeticError)?;
+    let current_cycle_index = cycle_index_at(user_state, current_timestamp, daily_task_cycle_seconds)?;
+
+    // Only cycles that have ended can be judged
+    let mut longest_run: u64 = 0;
+    let mut current_run: u64 = 0;
+    let mut cycle = market_state.cycle_index;
+    while cycle <= last_cycle_index && cycle < current_cycle_index {
+        if cycle_completed(user_state, cycle) {
+            current_run += 1;
+            longest_run = longest_run.max(current_run);
+        } else {
+            current_run = 0;
+        }
+        cycle += 1;
+    }
+
+    if cycle > last_cycle_index {
+        return Ok(Some(longest_run >= market_state.streak_threshold));
+    }
+    // Best case: every remaining cycle is completed, extending the current run
+    let remaining_cycles = last_cycle_index - cycle + 1;
+    if longest_run.max(current_run + remaining_cycles) < market_state.streak_threshold {
+        return Ok(Some(false));
+    }
+    Ok(None)
 }
     
 #[program]
@@ -53,6 +1143,19 @@ pub mod skillstreak_program {
         user_state.last_task_timestamp = 0;
         user_state.lock_in_end_timestamp = 0; // No lock-in yet
         user_state.accrued_yield = 0; // Initialize yield to 0
+        user_state.last_miss_deadline_timestamp = 0; // No misses recorded yet
+        user_state.last_top_up_deadline_timestamp = 0; // No top-ups yet
+        user_state.task_cycle_seconds = 0; // Set when a course starts
+        user_state.yield_rate_halved = false;
+        user_state.streak_expires_at = 0; // No active streak
+        user_state.last_streak_increment_timestamp = 0;
+        user_state.longest_streak = 0;
+        user_state.last_yield_accrual_timestamp = 0;
+        user_state.last_task_cycle_index = 0;
+        user_state.task_cycle_history = 0; // No tasks recorded yet
+        user_state.course_exit_timestamp = 0;
+        user_state.carried_over_yield = 0;
+        user_state.course_count = 0; // No course started yet
 
         msg!("User state account created.");
         msg!(" User: {}", user_state.user);
@@ -86,7 +1189,14 @@ pub mod skillstreak_program {
         token::transfer(cpi_ctx, deposit_amount)?;
         msg!("Transferred {} USDC to vault.", deposit_amount);
 
-        // --- 2. Update User State ---
+        // --- 2. Update Vault Accounting ---
+        let vault_state = &mut ctx.accounts.vault_state;
+        vault_state.bump = ctx.bumps.vault_state;
+        vault_state.total_principal = vault_state.total_principal
+            .checked_add(deposit_amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+
+        // --- 3. Update User State ---
         let user_state = &mut ctx.accounts.user_state;
 
         // Add to the existing deposit amount
@@ -114,6 +1224,7 @@ pub mod skillstreak_program {
         msg!("Lock-in duration (days): {}", lock_in_duration_days);
         msg!("Lock amount: {}", lock_amount);
 
+        let config = &ctx.accounts.program_config;
         let user_state = &mut ctx.accounts.user_state;
         let clock = Clock::get()?;
         let current_timestamp = clock.unix_timestamp;
@@ -146,64 +1257,69 @@ pub mod skillstreak_program {
         user_state.last_task_timestamp = current_timestamp;
         user_state.current_streak = 0;
         user_state.miss_count = 0;
+        user_state.last_miss_deadline_timestamp = 0;
+        user_state.last_top_up_deadline_timestamp = 0;
+        user_state.yield_rate_halved = false;
+        // The first record_task starts the streak; longest_streak is kept across courses
+        user_state.streak_expires_at = 0;
+        user_state.last_streak_increment_timestamp = 0;
+        // Yield starts accruing on the locked amount from now
+        user_state.last_yield_accrual_timestamp = current_timestamp;
+        // Task cycles (and their markets) are counted from the new course start
+        user_state.last_task_cycle_index = 0;
+        user_state.task_cycle_history = 0;
+        user_state.course_exit_timestamp = 0;
+        // Later config changes don't move the cycles of a running course or of its markets
+        user_state.task_cycle_seconds = config.daily_task_cycle_seconds;
+        // Yield still outstanding from earlier courses is not at stake in this one
+        user_state.carried_over_yield = user_state.accrued_yield;
+        // Markets of this course get PDAs of their own, apart from the ones of earlier courses
+        user_state.course_count = user_state.course_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticError)?;
 
         msg!("Course started successfully for user state.");
         msg!("  Amount Locked: {}", user_state.initial_deposit_amount);
         msg!("  Lock-in Ends At: {}", user_state.lock_in_end_timestamp);
         msg!("  Streak Timer Started At: {}", user_state.last_task_timestamp);
 
+        // The subject pays for their resolution record, not whoever cranks their first market
+        let subject_stats = &mut ctx.accounts.subject_stats;
+        if subject_stats.user == Pubkey::default() {
+            subject_stats.user = ctx.accounts.user.key();
+            subject_stats.bump = ctx.bumps.subject_stats;
+        }
+
         // --- 2. Automatically Create Betting Market ---
         msg!("Automatically creating betting market for user: {}", ctx.accounts.user.key());
 
         let market_state = &mut ctx.accounts.market_state;
-        market_state.market_creator = ctx.accounts.user.key(); // User starting course is the creator
-        market_state.user_being_bet_on = ctx.accounts.user.key(); // Bet on the user starting course
-        market_state.user_state_account_for_bet = user_state.key(); // Use the existing user_state borrow to get the key
-        market_state.total_long_amount = 0;
-        market_state.total_short_amount = 0;
-        market_state.market_creation_timestamp = current_timestamp;
-
-        // Task deadline for the *first* task.
-        // user_state.last_task_timestamp was just set to current_timestamp.
-        // So, the first task cycle ends 24 hours from this point.
-        let first_task_deadline_timestamp = user_state.last_task_timestamp
-            .checked_add(DAILY_TASK_CYCLE_SECONDS)
-            .ok_or(ErrorCode::ArithmeticError)?;
-        market_state.task_deadline_timestamp = first_task_deadline_timestamp;
-
-        // Calculate betting ends timestamp
-        let proposed_betting_ends_timestamp = current_timestamp
-            .checked_add(DEFAULT_AUTO_MARKET_BETTING_WINDOW_SECONDS as i64)
-            .ok_or(ErrorCode::ArithmeticError)?;
 
-        // Ensure betting ends strictly before the first task deadline
-        if proposed_betting_ends_timestamp >= first_task_deadline_timestamp {
-            // This means DEFAULT_AUTO_MARKET_BETTING_WINDOW_SECONDS is too long.
-            return err!(ErrorCode::BettingWindowTooLong);
-        }
-        market_state.betting_ends_timestamp = proposed_betting_ends_timestamp;
-        
-        market_state.resolution_timestamp = market_state.task_deadline_timestamp
-            .checked_add(RESOLUTION_GRACE_PERIOD_SECONDS)
+        // Market for the *first* task cycle (cycle 0), which started now.
+        // Betting ends after the auto market window, strictly before the first task deadline.
+        let betting_ends_timestamp = current_timestamp
+            .checked_add(config.auto_market_betting_window_seconds as i64)
             .ok_or(ErrorCode::ArithmeticError)?;
-        
-        market_state.status = MarketStatus::Open;
-        market_state.platform_fee_basis_points = DEFAULT_PLATFORM_FEE_BASIS_POINTS;
-        market_state.platform_fee_claimed = false;
+        initialize_market(
+            market_state,
+            ctx.accounts.user.key(), // User starting course is the creator
+            user_state,
+            0,
+            None,
+            None,
+            betting_ends_timestamp,
+            config,
+            current_timestamp,
+        )?;
         market_state.bump = ctx.bumps.market_state;
+        market_state.long_mint = ctx.accounts.long_mint.key();
+        market_state.short_mint = ctx.accounts.short_mint.key();
 
         // Initialize market_escrow_vault state
         let market_escrow_vault = &mut ctx.accounts.market_escrow_vault;
         market_escrow_vault.market = market_state.key();
         market_escrow_vault.bump = ctx.bumps.market_escrow_vault;
 
-        emit!(MarketCreated {
-            market: market_state.key(),
-            user_being_bet_on: market_state.user_being_bet_on,
-            task_deadline_timestamp: market_state.task_deadline_timestamp,
-            betting_ends_timestamp: market_state.betting_ends_timestamp,
-        });
-
         msg!("Market {} automatically created for user {}.", market_state.key(), market_state.user_being_bet_on);
         msg!(" Betting ends at: {}", market_state.betting_ends_timestamp);
         msg!(" Task deadline (first task) at: {}", market_state.task_deadline_timestamp);
@@ -221,22 +1337,42 @@ pub mod skillstreak_program {
             return err!(ErrorCode::LockInPeriodNotEnded);
         }
 
-        // Calculate total amount to withdraw (locked deposit + yield)
-        let total_amount = user_state.initial_deposit_amount
-            .checked_add(user_state.accrued_yield)
+        // Settle yield up to the end of the lock-in
+        let vault_state = &mut ctx.accounts.vault_state;
+        settle_yield(user_state, vault_state, &ctx.accounts.yield_config.params, clock.unix_timestamp)?;
+
+        // The locked deposit is already part of deposit_amount, unlocking it just clears the lock.
+        // Yield is only credited to the balance as far as the funded yield reserve covers it;
+        // anything above that stays in accrued_yield until the reserve is topped up.
+        let settled_yield = user_state.accrued_yield.min(vault_state.yield_reserve);
+
+        // Move settled yield from the reserve into the user's principal
+        vault_state.yield_reserve = vault_state.yield_reserve
+            .checked_sub(settled_yield)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        vault_state.total_yield_liabilities = vault_state.total_yield_liabilities
+            .checked_sub(settled_yield)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        vault_state.total_principal = vault_state.total_principal
+            .checked_add(settled_yield)
             .ok_or(ErrorCode::ArithmeticError)?;
 
         // Instead of transferring to wallet, add to deposit_amount (balance)
         user_state.deposit_amount = user_state.deposit_amount
-            .checked_add(total_amount)
+            .checked_add(settled_yield)
             .ok_or(ErrorCode::ArithmeticError)?;
 
-        // Reset locked amount and yield
+        let unlocked_amount = user_state.initial_deposit_amount;
+
+        // Reset locked amount and settled yield
         user_state.initial_deposit_amount = 0;
-        user_state.accrued_yield = 0;
+        user_state.accrued_yield = user_state.accrued_yield
+            .checked_sub(settled_yield)
+            .ok_or(ErrorCode::ArithmeticError)?;
         user_state.lock_in_end_timestamp = 0;
 
-        msg!("Unlocked {} tokens to user's balance", total_amount);
+        msg!("Unlocked {} tokens to user's balance", unlocked_amount);
+        msg!("Settled {} yield from reserve, {} still outstanding", settled_yield, user_state.accrued_yield);
         Ok(())
     }
 
@@ -249,12 +1385,22 @@ pub mod skillstreak_program {
             return err!(ErrorCode::LockInPeriodEnded);
         }
 
-        // Calculate penalty (50% of locked deposit)
+        // Settle yield earned so far; it is forfeited below together with the exit fee
+        settle_yield(
+            user_state,
+            &mut ctx.accounts.vault_state,
+            &ctx.accounts.yield_config.params,
+            clock.unix_timestamp,
+        )?;
+
+        // Calculate penalty (early exit fee, 50% of locked deposit by default)
         let penalty_amount = user_state.initial_deposit_amount
-            .checked_div(2)
+            .checked_mul(ctx.accounts.program_config.early_exit_fee_basis_points as u64)
+            .ok_or(ErrorCode::ArithmeticError)?
+            .checked_div(10000)
             .ok_or(ErrorCode::ArithmeticError)?;
         
-        // Calculate amount to return (ONLY 50% of locked deposit)
+        // Calculate amount to return (locked deposit minus the exit fee)
         let return_amount = user_state.initial_deposit_amount
             .checked_sub(penalty_amount)
             .ok_or(ErrorCode::ArithmeticError)?;
@@ -273,6 +1419,22 @@ pub mod skillstreak_program {
             token::transfer(cpi_ctx, penalty_amount)?;
         }
 
+        // Penalty left the vault
+        let vault_state = &mut ctx.accounts.vault_state;
+        vault_state.total_principal = vault_state.total_principal
+            .checked_sub(penalty_amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+
+        // Yield accrued during this course is forfeited, otherwise withdraw would settle it
+        // right away (the lock is cleared below). Yield carried over from earlier courses is kept.
+        let forfeited_yield = user_state.accrued_yield.saturating_sub(user_state.carried_over_yield);
+        user_state.accrued_yield = user_state.accrued_yield
+            .checked_sub(forfeited_yield)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        vault_state.total_yield_liabilities = vault_state.total_yield_liabilities
+            .checked_sub(forfeited_yield)
+            .ok_or(ErrorCode::ArithmeticError)?;
+
         // Update user state - add return amount back to deposit_amount
         // First subtract the full locked amount
         user_state.deposit_amount = user_state.deposit_amount
@@ -284,77 +1446,93 @@ pub mod skillstreak_program {
             .checked_add(return_amount)
             .ok_or(ErrorCode::ArithmeticError)?;
         
-        // Reset locked amount but keep yield counter
+        // Reset locked amount
         user_state.initial_deposit_amount = 0;
         user_state.lock_in_end_timestamp = 0;
-        // Do NOT reset accrued_yield as it's just a counter now
-
-        // Close market accounts if they exist
-        let dest_starting_lamports = ctx.accounts.user.lamports();
-        let market_state_lamports = ctx.accounts.market_state.to_account_info().lamports();
-        let market_escrow_lamports = ctx.accounts.market_escrow_vault.to_account_info().lamports();
-        
-        **ctx.accounts.user.lamports.borrow_mut() = dest_starting_lamports
-            .checked_add(market_state_lamports)
-            .ok_or(ErrorCode::ArithmeticError)?
-            .checked_add(market_escrow_lamports)
-            .ok_or(ErrorCode::ArithmeticError)?;
-
-        // Zero out both accounts' lamports and data
-        **ctx.accounts.market_state.to_account_info().lamports.borrow_mut() = 0;
-        **ctx.accounts.market_escrow_vault.to_account_info().lamports.borrow_mut() = 0;
-        ctx.accounts.market_state.to_account_info().data.borrow_mut().fill(0);
-        ctx.accounts.market_escrow_vault.to_account_info().data.borrow_mut().fill(0);
+        // Markets for cycles ending after this point can no longer be resolved and get cancelled
+        user_state.course_exit_timestamp = clock.unix_timestamp;
+
+        // Cancel the user's unresolved market (if any) so bettors can reclaim their stakes
+        // with claim_refund; the accounts are closed later via close_market once the escrow is empty.
+        if let Some(market_state) = ctx.accounts.market_state.as_mut() {
+            if cancel_market(market_state)? {
+                msg!("  Market {} cancelled, bettors can claim refunds", market_state.key());
+            }
+        }
 
         msg!("Early withdrawal completed:");
         msg!("  Penalty sent to treasury: {}", penalty_amount);
         msg!("  Amount added back to balance: {}", return_amount);
-        msg!("  Market accounts closed successfully");
+        msg!("  Yield forfeited: {}", forfeited_yield);
         
         Ok(())
     }
 
     // Add new close_market instruction
+    // Closes the user's market once its escrow is empty. If bets are still in a market that is
+    // open for betting, the market is cancelled instead so bettors can claim refunds first.
+    // Once betting has closed the subject can no longer cancel, otherwise they could back out
+    // of a market they are about to lose (early_withdraw still cancels, at the exit penalty).
     pub fn close_market(ctx: Context<CloseMarket>) -> Result<()> {
+        let clock = Clock::get()?;
         // Verify the market belongs to the user
         if ctx.accounts.market_state.user_being_bet_on != ctx.accounts.user.key() {
             return err!(ErrorCode::MarketBelongsToAnotherUser);
         }
 
-        // Transfer lamports from market state back to the user
-        let dest_starting_lamports = ctx.accounts.user.lamports();
-        let market_state_lamports = ctx.accounts.market_state.to_account_info().lamports();
-        let market_escrow_lamports = ctx.accounts.market_escrow_vault.to_account_info().lamports();
-        
-        **ctx.accounts.user.lamports.borrow_mut() = dest_starting_lamports
-            .checked_add(market_state_lamports)
-            .ok_or(ErrorCode::ArithmeticError)?
-            .checked_add(market_escrow_lamports)
-            .ok_or(ErrorCode::ArithmeticError)?;
+        if ctx.accounts.market_escrow_token_account.amount > 0 {
+            let market_state = &mut ctx.accounts.market_state;
+            if market_state.status == MarketStatus::Open && clock.unix_timestamp < market_state.betting_ends_timestamp {
+                cancel_market(market_state)?;
+                msg!("Market {} cancelled. Close it again once all refunds are claimed.", market_state.key());
+                return Ok(());
+            }
+            return err!(ErrorCode::EscrowNotEmpty);
+        }
+
+        // Close the empty escrow token account, rent back to the user
+        let market_key = ctx.accounts.market_state.key();
+        let seeds = &[
+            MARKET_ESCROW_VAULT_SEED,
+            market_key.as_ref(),
+            &[ctx.accounts.market_escrow_vault.bump],
+        ];
+        let signer = &[&seeds[..]];
+        let cpi_accounts = CloseAccount {
+            account: ctx.accounts.market_escrow_token_account.to_account_info(),
+            destination: ctx.accounts.user.to_account_info(),
+            authority: ctx.accounts.market_escrow_vault.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        token::close_account(CpiContext::new_with_signer(cpi_program, cpi_accounts, signer))?;
 
-        // Zero out both accounts' lamports and data
-        **ctx.accounts.market_state.to_account_info().lamports.borrow_mut() = 0;
-        **ctx.accounts.market_escrow_vault.to_account_info().lamports.borrow_mut() = 0;
-        ctx.accounts.market_state.to_account_info().data.borrow_mut().fill(0);
-        ctx.accounts.market_escrow_vault.to_account_info().data.borrow_mut().fill(0);
+        // Transfer lamports from market state and escrow vault back to the user
+        close_market_accounts(
+            &ctx.accounts.market_state.to_account_info(),
+            &ctx.accounts.market_escrow_vault.to_account_info(),
+            &ctx.accounts.user.to_account_info(),
+        )?;
 
-        msg!("Market state and escrow vault accounts closed successfully");
+        msg!("Market state, escrow vault and escrow token accounts closed successfully");
         Ok(())
     }
 
     // --- Long/Short Streak Betting System ---
 
+    // Creates a market on whether the subject completes the task of `cycle_index` (day N of their course).
     pub fn create_market(
         ctx: Context<CreateMarket>,
+        cycle_index: u64,
         betting_window_duration_seconds: u64,
-        // platform_fee_basis_points: u16, // Using default for now
+        fee_params: Option<MarketFeeParams>, // None for the default fee without rebate
     ) -> Result<()> {
         let clock = Clock::get()?;
+        let config = &ctx.accounts.program_config;
         let market_creator = ctx.accounts.market_creator.key();
         let user_being_bet_on = ctx.accounts.user_being_bet_on.key();
         let user_state_for_bet = &ctx.accounts.user_state_for_bet;
 
-        msg!("Creating market for user: {}", user_being_bet_on);
+        msg!("Creating market for user: {}, cycle: {}", user_being_bet_on, cycle_index);
         msg!("Market creator: {}", market_creator);
 
         // Validate user_state_for_bet actually belongs to user_being_bet_on
@@ -365,48 +1543,34 @@ pub mod skillstreak_program {
         if user_state_for_bet.lock_in_end_timestamp == 0 || user_state_for_bet.deposit_timestamp == 0 {
             return err!(ErrorCode::UserCourseNotStarted);
         }
+        // Markets can be opened for the current or any later cycle, never for a past one
+        if cycle_index < cycle_index_at(user_state_for_bet, clock.unix_timestamp, user_state_for_bet.task_cycle_seconds)? {
+            return err!(ErrorCode::CycleAlreadyEnded);
+        }
 
-        let market_state = &mut ctx.accounts.market_state;
-        market_state.market_creator = market_creator;
-        market_state.user_being_bet_on = user_being_bet_on;
-        market_state.user_state_account_for_bet = user_state_for_bet.key();
-        market_state.total_long_amount = 0;
-        market_state.total_short_amount = 0;
-        market_state.market_creation_timestamp = clock.unix_timestamp;
-        market_state.betting_ends_timestamp = clock.unix_timestamp
+        let betting_ends_timestamp = clock.unix_timestamp
             .checked_add(betting_window_duration_seconds as i64)
             .ok_or(ErrorCode::ArithmeticError)?;
-        
-        // Market is for the current task cycle. Deadline is last_task_timestamp + 24h.
-        // If last_task_timestamp is deposit_timestamp (first task), this is correct.
-        market_state.task_deadline_timestamp = user_state_for_bet.last_task_timestamp
-            .checked_add(DAILY_TASK_CYCLE_SECONDS)
-            .ok_or(ErrorCode::ArithmeticError)?;
-
-        // Ensure betting ends before task deadline
-        if market_state.betting_ends_timestamp >= market_state.task_deadline_timestamp {
-            return err!(ErrorCode::BettingWindowTooLong);
-        }
 
-        market_state.resolution_timestamp = market_state.task_deadline_timestamp
-            .checked_add(RESOLUTION_GRACE_PERIOD_SECONDS)
-            .ok_or(ErrorCode::ArithmeticError)?;
-        
-        market_state.status = MarketStatus::Open;
-        market_state.platform_fee_basis_points = DEFAULT_PLATFORM_FEE_BASIS_POINTS;
-        market_state.platform_fee_claimed = false;
+        let market_state = &mut ctx.accounts.market_state;
+        initialize_market(
+            market_state,
+            market_creator,
+            user_state_for_bet,
+            cycle_index,
+            None,
+            fee_params,
+            betting_ends_timestamp,
+            config,
+            clock.unix_timestamp,
+        )?;
         market_state.bump = ctx.bumps.market_state;
+        market_state.long_mint = ctx.accounts.long_mint.key();
+        market_state.short_mint = ctx.accounts.short_mint.key();
 
         let market_escrow_vault = &mut ctx.accounts.market_escrow_vault;
         market_escrow_vault.market = market_state.key();
         market_escrow_vault.bump = ctx.bumps.market_escrow_vault;
-        
-        emit!(MarketCreated {
-            market: market_state.key(),
-            user_being_bet_on,
-            task_deadline_timestamp: market_state.task_deadline_timestamp,
-            betting_ends_timestamp: market_state.betting_ends_timestamp,
-        });
 
         msg!("Market {} created for user {}.", market_state.key(), user_being_bet_on);
         msg!(" Betting ends at: {}", market_state.betting_ends_timestamp);
@@ -415,19 +1579,207 @@ pub mod skillstreak_program {
         Ok(())
     }
 
-    pub fn place_bet(
-        ctx: Context<PlaceBet>,
-        amount: u64,
-        position_is_long: bool,
+    // Creates a multi-day market starting at `cycle_index`, e.g. "keeps the streak for the next 7 days"
+    // (horizon 7, threshold 7) or "reaches a 30-day streak before lock-in ends" (threshold 30).
+    pub fn create_streak_market(
+        ctx: Context<CreateStreakMarket>,
+        cycle_index: u64,
+        betting_window_duration_seconds: u64,
+        params: StreakMarketParams,
+        fee_params: Option<MarketFeeParams>,
     ) -> Result<()> {
         let clock = Clock::get()?;
-        let market_state = &mut ctx.accounts.market_state;
-        let bettor = ctx.accounts.bettor.key();
+        let config = &ctx.accounts.program_config;
+        let market_creator = ctx.accounts.market_creator.key();
+        let user_being_bet_on = ctx.accounts.user_being_bet_on.key();
+        let user_state_for_bet = &ctx.accounts.user_state_for_bet;
 
-        msg!("Placing bet for market: {}", market_state.key());
-        msg!(" Bettor: {}, Amount: {}, IsLong: {}", bettor, amount, position_is_long);
+        msg!("Creating streak market for user: {}, from cycle: {}", user_being_bet_on, cycle_index);
+        msg!(" Horizon: {} cycles, streak threshold: {}", params.horizon_cycles, params.streak_threshold);
 
-        // Validations
+        // Validate user_state_for_bet actually belongs to user_being_bet_on
+        if user_state_for_bet.user != user_being_bet_on {
+            return err!(ErrorCode::UserStateMismatch);
+        }
+        // Validate user has started the course
+        if user_state_for_bet.lock_in_end_timestamp == 0 || user_state_for_bet.deposit_timestamp == 0 {
+            return err!(ErrorCode::UserCourseNotStarted);
+        }
+        if cycle_index < cycle_index_at(user_state_for_bet, clock.unix_timestamp, user_state_for_bet.task_cycle_seconds)? {
+            return err!(ErrorCode::CycleAlreadyEnded);
+        }
+
+        let betting_ends_timestamp = clock.unix_timestamp
+            .checked_add(betting_window_duration_seconds as i64)
+            .ok_or(ErrorCode::ArithmeticError)?;
+
+        let market_state = &mut ctx.accounts.market_state;
+        initialize_market(
+            market_state,
+            market_creator,
+            user_state_for_bet,
+            cycle_index,
+            Some(params),
+            fee_params,
+            betting_ends_timestamp,
+            config,
+            clock.unix_timestamp,
+        )?;
+        market_state.bump = ctx.bumps.market_state;
+        market_state.long_mint = ctx.accounts.long_mint.key();
+        market_state.short_mint = ctx.accounts.short_mint.key();
+
+        let market_escrow_vault = &mut ctx.accounts.market_escrow_vault;
+        market_escrow_vault.market = market_state.key();
+        market_escrow_vault.bump = ctx.bumps.market_escrow_vault;
+
+        msg!("Streak market {} created for user {}.", market_state.key(), user_being_bet_on);
+        msg!(" Betting ends at: {}", market_state.betting_ends_timestamp);
+        msg!(" Last task deadline at: {}", market_state.task_deadline_timestamp);
+        Ok(())
+    }
+
+    // Creates an AMM market: the creator's liquidity mints `liquidity_amount` complete sets
+    // (1 LONG + 1 SHORT share per USDC unit) that seed a constant-product pool at 50/50.
+    pub fn create_amm_market(
+        ctx: Context<CreateAmmMarket>,
+        cycle_index: u64,
+        betting_window_duration_seconds: u64,
+        liquidity_amount: u64,
+        streak_params: Option<StreakMarketParams>,
+        fee_params: Option<MarketFeeParams>,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let config = &ctx.accounts.program_config;
+        let market_creator = ctx.accounts.market_creator.key();
+        let user_being_bet_on = ctx.accounts.user_being_bet_on.key();
+        let user_state_for_bet = &ctx.accounts.user_state_for_bet;
+
+        msg!("Creating AMM market for user: {}, cycle: {}", user_being_bet_on, cycle_index);
+        msg!(" Liquidity: {}", liquidity_amount);
+
+        // Validate user_state_for_bet actually belongs to user_being_bet_on
+        if user_state_for_bet.user != user_being_bet_on {
+            return err!(ErrorCode::UserStateMismatch);
+        }
+        // Validate user has started the course
+        if user_state_for_bet.lock_in_end_timestamp == 0 || user_state_for_bet.deposit_timestamp == 0 {
+            return err!(ErrorCode::UserCourseNotStarted);
+        }
+        if cycle_index < cycle_index_at(user_state_for_bet, clock.unix_timestamp, user_state_for_bet.task_cycle_seconds)? {
+            return err!(ErrorCode::CycleAlreadyEnded);
+        }
+        if liquidity_amount == 0 {
+            return err!(ErrorCode::ZeroLiquidityAmount);
+        }
+        check_pool_cap(config, user_state_for_bet, liquidity_amount)?;
+
+        let betting_ends_timestamp = clock.unix_timestamp
+            .checked_add(betting_window_duration_seconds as i64)
+            .ok_or(ErrorCode::ArithmeticError)?;
+
+        let market_state = &mut ctx.accounts.market_state;
+        initialize_market(
+            market_state,
+            market_creator,
+            user_state_for_bet,
+            cycle_index,
+            streak_params,
+            fee_params,
+            betting_ends_timestamp,
+            config,
+            clock.unix_timestamp,
+        )?;
+        market_state.market_mode = MarketMode::Amm;
+        market_state.amm_long_reserve = liquidity_amount;
+        market_state.amm_short_reserve = liquidity_amount;
+        market_state.amm_liquidity_amount = liquidity_amount;
+        market_state.bump = ctx.bumps.market_state;
+        market_state.long_mint = ctx.accounts.long_mint.key();
+        market_state.short_mint = ctx.accounts.short_mint.key();
+
+        let market_escrow_vault = &mut ctx.accounts.market_escrow_vault;
+        market_escrow_vault.market = market_state.key();
+        market_escrow_vault.bump = ctx.bumps.market_escrow_vault;
+
+        // Transfer the liquidity into the escrow
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.creator_token_account.to_account_info(),
+            to: ctx.accounts.market_escrow_token_account.to_account_info(),
+            authority: ctx.accounts.market_creator.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        token::transfer(CpiContext::new(cpi_program, cpi_accounts), liquidity_amount)?;
+
+        msg!("AMM market {} created for user {}.", market_state.key(), user_being_bet_on);
+        msg!(" Betting ends at: {}", market_state.betting_ends_timestamp);
+        msg!(" Task deadline at: {}", market_state.task_deadline_timestamp);
+        Ok(())
+    }
+
+    // --- Market Rollover ---
+    // Permissionless: opens the market for the subject's upcoming task cycle with the default
+    // betting window (measured from the cycle start), so every day of a course can get a market.
+    pub fn roll_over_market(ctx: Context<RollOverMarket>, cycle_index: u64) -> Result<()> {
+        let clock = Clock::get()?;
+        let config = &ctx.accounts.program_config;
+        let user_state_for_bet = &ctx.accounts.user_state_for_bet;
+
+        msg!("Rolling over market for user: {}, cycle: {}", user_state_for_bet.user, cycle_index);
+
+        // Validate the course is running
+        if user_state_for_bet.lock_in_end_timestamp == 0 || clock.unix_timestamp >= user_state_for_bet.lock_in_end_timestamp {
+            return err!(ErrorCode::CourseNotActive);
+        }
+        let current_cycle_index = cycle_index_at(user_state_for_bet, clock.unix_timestamp, user_state_for_bet.task_cycle_seconds)?;
+        if cycle_index != current_cycle_index.checked_add(1).ok_or(ErrorCode::ArithmeticError)? {
+            return err!(ErrorCode::NotUpcomingCycle);
+        }
+
+        let (cycle_start_timestamp, _) = cycle_window(user_state_for_bet, cycle_index, user_state_for_bet.task_cycle_seconds)?;
+        let betting_ends_timestamp = cycle_start_timestamp
+            .checked_add(config.auto_market_betting_window_seconds as i64)
+            .ok_or(ErrorCode::ArithmeticError)?;
+
+        let market_state = &mut ctx.accounts.market_state;
+        initialize_market(
+            market_state,
+            ctx.accounts.caller.key(),
+            user_state_for_bet,
+            cycle_index,
+            None,
+            None,
+            betting_ends_timestamp,
+            config,
+            clock.unix_timestamp,
+        )?;
+        market_state.bump = ctx.bumps.market_state;
+        market_state.long_mint = ctx.accounts.long_mint.key();
+        market_state.short_mint = ctx.accounts.short_mint.key();
+
+        let market_escrow_vault = &mut ctx.accounts.market_escrow_vault;
+        market_escrow_vault.market = market_state.key();
+        market_escrow_vault.bump = ctx.bumps.market_escrow_vault;
+
+        msg!("Market {} opened for cycle {}.", market_state.key(), cycle_index);
+        msg!(" Betting ends at: {}", market_state.betting_ends_timestamp);
+        msg!(" Task deadline at: {}", market_state.task_deadline_timestamp);
+        Ok(())
+    }
+
+    pub fn place_bet(
+        ctx: Context<PlaceBet>,
+        amount: u64,
+        position_is_long: bool,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let market_state = &mut ctx.accounts.market_state;
+        let bettor = ctx.accounts.bettor.key();
+
+        msg!("Placing bet for market: {}", market_state.key());
+        msg!(" Bettor: {}, Amount: {}, IsLong: {}", bettor, amount, position_is_long);
+
+        // Validations
         if market_state.status != MarketStatus::Open {
             return err!(ErrorCode::MarketNotOpenForBets);
         }
@@ -441,6 +1793,34 @@ pub mod skillstreak_program {
         if bettor == market_state.user_being_bet_on {
             return err!(ErrorCode::CannotBetOnSelf);
         }
+        if market_state.market_mode != MarketMode::Parimutuel {
+            return err!(ErrorCode::WrongMarketMode);
+        }
+        let new_short_amount = if position_is_long {
+            ctx.accounts.bet_state.short_amount
+        } else {
+            ctx.accounts.bet_state.short_amount
+                .checked_add(amount)
+                .ok_or(ErrorCode::ArithmeticError)?
+        };
+        let new_position = ctx.accounts.bet_state.long_amount
+            .checked_add(ctx.accounts.bet_state.short_amount)
+            .and_then(|position| position.checked_add(amount))
+            .ok_or(ErrorCode::ArithmeticError)?;
+        let is_affiliate = check_bettor_limits(
+            &ctx.accounts.program_config,
+            &ctx.accounts.subject_affiliates,
+            &ctx.accounts.user_state_for_bet,
+            bettor,
+            position_is_long,
+            new_short_amount,
+            new_position,
+        )?;
+        let new_total_pool = market_state.total_long_amount
+            .checked_add(market_state.total_short_amount)
+            .and_then(|total_pool| total_pool.checked_add(amount))
+            .ok_or(ErrorCode::ArithmeticError)?;
+        check_pool_cap(&ctx.accounts.program_config, &ctx.accounts.user_state_for_bet, new_total_pool)?;
 
         // Transfer funds
         let cpi_accounts = Transfer {
@@ -462,200 +1842,346 @@ pub mod skillstreak_program {
                 .ok_or(ErrorCode::ArithmeticError)?;
         }
 
-        // Initialize bet state
+        market_state.implied_long_probability_basis_points = implied_long_probability_basis_points(
+            market_state.total_long_amount,
+            market_state.total_short_amount,
+        )?;
+
+        // Start the bet state on the bettor's first bet, later bets add to the position
+        let market_key = market_state.key();
         let bet_state = &mut ctx.accounts.bet_state;
-        bet_state.market = market_state.key();
-        bet_state.bettor = bettor;
-        bet_state.amount = amount;
-        bet_state.position_is_long = position_is_long;
-        bet_state.winnings_claimed = false;
-        bet_state.bump = ctx.bumps.bet_state;
+        let new_market = start_bet_state(
+            bet_state,
+            market_state,
+            market_key,
+            bettor,
+            is_affiliate,
+            ctx.bumps.bet_state,
+        )?;
+        if position_is_long {
+            bet_state.long_amount = bet_state.long_amount
+                .checked_add(amount)
+                .ok_or(ErrorCode::ArithmeticError)?;
+            if is_affiliate {
+                market_state.affiliate_long_amount = market_state.affiliate_long_amount
+                    .checked_add(amount)
+                    .ok_or(ErrorCode::ArithmeticError)?;
+            }
+        } else {
+            bet_state.short_amount = bet_state.short_amount
+                .checked_add(amount)
+                .ok_or(ErrorCode::ArithmeticError)?;
+        }
+
+        let bettor_stats = &mut ctx.accounts.bettor_stats;
+        init_bettor_stats(bettor_stats, bettor, ctx.bumps.bettor_stats);
+        record_bettor_stake(bettor_stats, amount, new_market)?;
+
+        // Issue outcome tokens 1:1 with the stake, they are what claim_winnings redeems
+        issue_position_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.position_mint,
+            &ctx.accounts.bettor_position_token_account,
+            &ctx.accounts.bettor,
+            &ctx.accounts.market_escrow_vault,
+            amount,
+        )?;
 
         emit!(BetPlaced {
             market: market_state.key(),
             bettor,
             amount,
             position_is_long,
+            position_long_amount: bet_state.long_amount,
+            position_short_amount: bet_state.short_amount,
+            implied_long_probability_basis_points: market_state.implied_long_probability_basis_points,
         });
         msg!("Bet placed successfully.");
         Ok(())
     }
 
-    pub fn trigger_market_resolution(ctx: Context<TriggerMarketResolution>) -> Result<()> {
+    // Read-only view (call via simulation, the quote is returned as return data): the payout a
+    // hypothetical bet of `amount` on one side would receive if that side won, given the current pools.
+    // Excludes any forfeited-deposit share, which depends on the resolution.
+    pub fn quote_bet(ctx: Context<QuoteBet>, amount: u64, position_is_long: bool) -> Result<BetQuote> {
+        let market_state = &ctx.accounts.market_state;
+        if market_state.market_mode != MarketMode::Parimutuel {
+            return err!(ErrorCode::WrongMarketMode);
+        }
+        if amount == 0 {
+            return err!(ErrorCode::ZeroBetAmount);
+        }
+
+        let (total_long_amount, total_short_amount) = if position_is_long {
+            (market_state.total_long_amount.checked_add(amount).ok_or(ErrorCode::ArithmeticError)?, market_state.total_short_amount)
+        } else {
+            (market_state.total_long_amount, market_state.total_short_amount.checked_add(amount).ok_or(ErrorCode::ArithmeticError)?)
+        };
+        let total_pool = total_long_amount
+            .checked_add(total_short_amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        let winning_side_total = if position_is_long { total_long_amount } else { total_short_amount };
+
+        let payout_amount = parimutuel_payout(amount, winning_side_total, total_pool, market_state.platform_fee_basis_points)?;
+        let payout_multiplier_basis_points = (payout_amount as u128)
+            .checked_mul(10000)
+            .ok_or(ErrorCode::ArithmeticError)?
+            .checked_div(amount as u128)
+            .ok_or(ErrorCode::ArithmeticError)? as u64;
+
+        Ok(BetQuote {
+            payout_amount,
+            payout_multiplier_basis_points,
+            implied_long_probability_basis_points: implied_long_probability_basis_points(total_long_amount, total_short_amount)?,
+        })
+    }
+
+    // Buys outcome shares of an AMM market. The platform fee is taken from `amount`, the rest
+    // mints complete sets into the pool which pays out shares of the chosen side.
+    pub fn buy_shares(
+        ctx: Context<PlaceBet>,
+        amount: u64,
+        position_is_long: bool,
+        min_shares_out: u64,
+    ) -> Result<()> {
         let clock = Clock::get()?;
         let market_state = &mut ctx.accounts.market_state;
-        let user_state_for_bet = &ctx.accounts.user_state_for_bet;
-        
-        msg!("Attempting to resolve market: {}", market_state.key());
+        let bettor = ctx.accounts.bettor.key();
 
-        // Ensure user state account matches the one set at market creation
-        if user_state_for_bet.key() != market_state.user_state_account_for_bet {
-            return err!(ErrorCode::UserStateMismatch);
+        msg!("Buying shares on market: {}", market_state.key());
+        msg!(" Bettor: {}, Amount: {}, IsLong: {}", bettor, amount, position_is_long);
+
+        // Validations
+        if market_state.market_mode != MarketMode::Amm {
+            return err!(ErrorCode::WrongMarketMode);
         }
-         // Ensure user_state_for_bet.user is the one being bet on
-        if user_state_for_bet.user != market_state.user_being_bet_on {
-            return err!(ErrorCode::UserStateMismatch);
+        if market_state.status != MarketStatus::Open {
+            return err!(ErrorCode::MarketNotOpenForBets);
         }
-
-
-        if market_state.status == MarketStatus::Open && clock.unix_timestamp >= market_state.betting_ends_timestamp {
-            // Betting window has just closed, but resolution not yet possible
-            market_state.status = MarketStatus::AwaitingResolution;
-            msg!("Market status changed to AwaitingResolution.");
-            // No further action until resolution_timestamp is reached
-            return Ok(()); 
+        if clock.unix_timestamp >= market_state.betting_ends_timestamp {
+            return err!(ErrorCode::BettingWindowClosed);
         }
-        
-        if market_state.status != MarketStatus::AwaitingResolution {
-            if market_state.status == MarketStatus::ResolvedLongsWin || market_state.status == MarketStatus::ResolvedShortsWin {
-                 return err!(ErrorCode::MarketAlreadyResolved);
-            }
-             // If still Open but betting window not closed, or some other state
-            return err!(ErrorCode::MarketNotReadyForResolution);
+        if amount == 0 {
+            return err!(ErrorCode::ZeroBetAmount);
+        }
+        if bettor == market_state.user_being_bet_on {
+            return err!(ErrorCode::CannotBetOnSelf);
         }
 
-        // Now, status must be AwaitingResolution
-        if clock.unix_timestamp < market_state.resolution_timestamp {
-            return err!(ErrorCode::ResolutionGracePeriodNotOver);
+        let fee_amount = platform_fee(amount, market_state.platform_fee_basis_points)?;
+        let collateral = amount
+            .checked_sub(fee_amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        let (shares_out, new_long_reserve, new_short_reserve) = if position_is_long {
+            amm_buy(market_state.amm_long_reserve, market_state.amm_short_reserve, collateral)?
+        } else {
+            let (shares_out, new_short_reserve, new_long_reserve) =
+                amm_buy(market_state.amm_short_reserve, market_state.amm_long_reserve, collateral)?;
+            (shares_out, new_long_reserve, new_short_reserve)
+        };
+        if shares_out < min_shares_out {
+            return err!(ErrorCode::SlippageExceeded);
         }
-        
-        // Determine outcome
-        // The task was for the cycle ending at market_state.task_deadline_timestamp.
-        // The user needed to complete a task with timestamp:
-        // (task_deadline - 24h) < last_task_timestamp <= task_deadline
-        let relevant_task_window_start = market_state.task_deadline_timestamp
-            .checked_sub(DAILY_TASK_CYCLE_SECONDS)
+
+        let new_short_amount = if position_is_long {
+            ctx.accounts.bet_state.short_amount
+        } else {
+            ctx.accounts.bet_state.short_amount
+                .checked_add(amount)
+                .ok_or(ErrorCode::ArithmeticError)?
+        };
+        let new_position = ctx.accounts.bet_state.long_amount
+            .checked_add(ctx.accounts.bet_state.short_amount)
+            .and_then(|position| position.checked_add(amount))
+            .ok_or(ErrorCode::ArithmeticError)?;
+        let is_affiliate = check_bettor_limits(
+            &ctx.accounts.program_config,
+            &ctx.accounts.subject_affiliates,
+            &ctx.accounts.user_state_for_bet,
+            bettor,
+            position_is_long,
+            new_short_amount,
+            new_position,
+        )?;
+        let new_total_pool = market_state.amm_liquidity_amount
+            .checked_add(market_state.amm_stake_amount)
+            .and_then(|total_pool| total_pool.checked_add(amount))
             .ok_or(ErrorCode::ArithmeticError)?;
+        check_pool_cap(&ctx.accounts.program_config, &ctx.accounts.user_state_for_bet, new_total_pool)?;
 
-        let user_completed_task_in_time = user_state_for_bet.last_task_timestamp > relevant_task_window_start &&
-                                          user_state_for_bet.last_task_timestamp <= market_state.task_deadline_timestamp;
+        // Transfer funds
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.bettor_token_account.to_account_info(),
+            to: ctx.accounts.market_escrow_token_account.to_account_info(),
+            authority: ctx.accounts.bettor.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        token::transfer(CpiContext::new(cpi_program, cpi_accounts), amount)?;
 
-        if user_completed_task_in_time {
-            market_state.status = MarketStatus::ResolvedLongsWin;
-            msg!("Market resolved: Longs Win.");
+        // Update market state
+        market_state.amm_long_reserve = new_long_reserve;
+        market_state.amm_short_reserve = new_short_reserve;
+        market_state.amm_fee_amount = market_state.amm_fee_amount
+            .checked_add(fee_amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        market_state.amm_stake_amount = market_state.amm_stake_amount
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        // The price of a LONG share is the SHORT reserve's share of the pool
+        market_state.implied_long_probability_basis_points = implied_long_probability_basis_points(
+            new_short_reserve,
+            new_long_reserve,
+        )?;
+
+        // Start the bet state on the bettor's first trade, it records the USDC paid per side
+        // (net of sales), which is what a cancelled market refunds
+        let market_key = market_state.key();
+        let bet_state = &mut ctx.accounts.bet_state;
+        let new_market = start_bet_state(
+            bet_state,
+            market_state,
+            market_key,
+            bettor,
+            is_affiliate,
+            ctx.bumps.bet_state,
+        )?;
+        if position_is_long {
+            bet_state.long_amount = bet_state.long_amount
+                .checked_add(amount)
+                .ok_or(ErrorCode::ArithmeticError)?;
+            if is_affiliate {
+                market_state.affiliate_long_amount = market_state.affiliate_long_amount
+                    .checked_add(shares_out)
+                    .ok_or(ErrorCode::ArithmeticError)?;
+            }
         } else {
-            market_state.status = MarketStatus::ResolvedShortsWin;
-            msg!("Market resolved: Shorts Win.");
+            bet_state.short_amount = new_short_amount;
         }
 
-        // Platform Fee Transfer (only if not already claimed and there's a pool)
-        if !market_state.platform_fee_claimed {
-            let total_pool = market_state.total_long_amount
-                .checked_add(market_state.total_short_amount)
-                .ok_or(ErrorCode::ArithmeticError)?;
-
-            if total_pool > 0 {
-                let platform_fee = total_pool
-                    .checked_mul(market_state.platform_fee_basis_points as u64)
-                    .ok_or(ErrorCode::ArithmeticError)?
-                    .checked_div(10000)
-                    .ok_or(ErrorCode::ArithmeticError)?;
+        let bettor_stats = &mut ctx.accounts.bettor_stats;
+        init_bettor_stats(bettor_stats, bettor, ctx.bumps.bettor_stats);
+        record_bettor_stake(bettor_stats, amount, new_market)?;
 
-                if platform_fee > 0 {
-                    msg!("Transferring platform fee: {}", platform_fee);
-                    let market_key = market_state.key();
-                    let seeds = &[
-                        MARKET_ESCROW_VAULT_SEED,
-                        market_key.as_ref(),
-                        &[ctx.accounts.market_escrow_vault.bump],
-                    ];
-                    let signer = &[&seeds[..]];
+        issue_position_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.position_mint,
+            &ctx.accounts.bettor_position_token_account,
+            &ctx.accounts.bettor,
+            &ctx.accounts.market_escrow_vault,
+            shares_out,
+        )?;
 
-                    let cpi_accounts = Transfer {
-                        from: ctx.accounts.market_escrow_token_account.to_account_info(),
-                        to: ctx.accounts.treasury_token_account.to_account_info(),
-                        authority: ctx.accounts.market_escrow_vault.to_account_info(),
-                    };
-                    let cpi_program = ctx.accounts.token_program.to_account_info();
-                    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-                    token::transfer(cpi_ctx, platform_fee)?;
-                    msg!("Platform fee {} transferred to treasury.", platform_fee);
-                }
-            }
-            market_state.platform_fee_claimed = true;
-        }
-        
-        emit!(MarketResolved {
+        emit!(SharesTraded {
             market: market_state.key(),
-            status: market_state.status.clone(),
-            total_long_amount: market_state.total_long_amount,
-            total_short_amount: market_state.total_short_amount,
+            bettor,
+            position_is_long,
+            is_buy: true,
+            usdc_amount: amount,
+            shares: shares_out,
+            fee_amount,
+            implied_long_probability_basis_points: market_state.implied_long_probability_basis_points,
         });
-
+        msg!("Bought {} shares.", shares_out);
         Ok(())
     }
 
-    pub fn claim_winnings(ctx: Context<ClaimWinnings>) -> Result<()> {
-        let market_state = &ctx.accounts.market_state;
-        let bet_state = &mut ctx.accounts.bet_state;
+    // Sells outcome shares of an AMM market back to the pool. The platform fee is taken from the
+    // USDC returned. The USDC received comes off the seller's stake on that side, which is what a
+    // cancelled market refunds them.
+    pub fn sell_shares(
+        ctx: Context<SellShares>,
+        shares: u64,
+        position_is_long: bool,
+        min_usdc_out: u64,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let market_state = &mut ctx.accounts.market_state;
         let bettor = ctx.accounts.bettor.key();
 
-        msg!("Attempting to claim winnings for bettor {} on market {}", bettor, market_state.key());
+        msg!("Selling shares on market: {}", market_state.key());
+        msg!(" Bettor: {}, Shares: {}, IsLong: {}", bettor, shares, position_is_long);
 
         // Validations
-        if bet_state.bettor != bettor {
-            return err!(ErrorCode::BetBelongsToAnotherUser);
+        if market_state.market_mode != MarketMode::Amm {
+            return err!(ErrorCode::WrongMarketMode);
         }
-        if bet_state.winnings_claimed {
-            return err!(ErrorCode::WinningsAlreadyClaimed);
+        if market_state.status != MarketStatus::Open {
+            return err!(ErrorCode::MarketNotOpenForBets);
         }
-        if !market_state.platform_fee_claimed {
-             // Safety check, should be claimed during resolution
-            return err!(ErrorCode::PlatformFeeNotClaimed);
+        if clock.unix_timestamp >= market_state.betting_ends_timestamp {
+            return err!(ErrorCode::BettingWindowClosed);
+        }
+        if shares == 0 {
+            return err!(ErrorCode::ZeroBetAmount);
+        }
+        // Shares are the outcome tokens held, however they were acquired
+        if shares > ctx.accounts.bettor_position_token_account.amount {
+            return err!(ErrorCode::InsufficientShares);
         }
 
-        let mut payout_amount: u64 = 0;
-        let total_pool = market_state.total_long_amount
-            .checked_add(market_state.total_short_amount)
-            .ok_or(ErrorCode::ArithmeticError)?;
-        
-        // Recalculate platform fee taken to find net pool (as it might be 0 if total_pool was small)
-        let platform_fee_actually_taken = if total_pool > 0 {
-            total_pool
-                .checked_mul(market_state.platform_fee_basis_points as u64)
-                .ok_or(ErrorCode::ArithmeticError)?
-                .checked_div(10000)
-                .ok_or(ErrorCode::ArithmeticError)?
+        let (collateral_out, new_long_reserve, new_short_reserve) = if position_is_long {
+            amm_sell(market_state.amm_long_reserve, market_state.amm_short_reserve, shares)?
         } else {
-            0
+            let (collateral_out, new_short_reserve, new_long_reserve) =
+                amm_sell(market_state.amm_short_reserve, market_state.amm_long_reserve, shares)?;
+            (collateral_out, new_long_reserve, new_short_reserve)
         };
+        let fee_amount = platform_fee(collateral_out, market_state.platform_fee_basis_points)?;
+        let usdc_out = collateral_out
+            .checked_sub(fee_amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        if usdc_out < min_usdc_out {
+            return err!(ErrorCode::SlippageExceeded);
+        }
+
+        burn_outcome_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.position_mint,
+            &ctx.accounts.bettor_position_token_account,
+            &ctx.accounts.bettor,
+            shares,
+        )?;
 
-        let net_pool_for_distribution = total_pool
-            .checked_sub(platform_fee_actually_taken)
+        // Update market state
+        market_state.amm_long_reserve = new_long_reserve;
+        market_state.amm_short_reserve = new_short_reserve;
+        market_state.amm_fee_amount = market_state.amm_fee_amount
+            .checked_add(fee_amount)
             .ok_or(ErrorCode::ArithmeticError)?;
+        market_state.implied_long_probability_basis_points = implied_long_probability_basis_points(
+            new_short_reserve,
+            new_long_reserve,
+        )?;
 
-        if market_state.status == MarketStatus::ResolvedLongsWin && bet_state.position_is_long {
-            if market_state.total_long_amount > 0 {
-                payout_amount = bet_state.amount
-                    .checked_mul(net_pool_for_distribution)
-                    .ok_or(ErrorCode::ArithmeticError)?
-                    .checked_div(market_state.total_long_amount)
-                    .ok_or(ErrorCode::ArithmeticError)?;
-                msg!("Longs won. Payout calculated: {}", payout_amount);
-            } else {
-                msg!("Longs won, but no longs placed. No payout for this bet.");
-            }
-        } else if market_state.status == MarketStatus::ResolvedShortsWin && !bet_state.position_is_long {
-            if market_state.total_short_amount > 0 {
-                payout_amount = bet_state.amount
-                    .checked_mul(net_pool_for_distribution)
-                    .ok_or(ErrorCode::ArithmeticError)?
-                    .checked_div(market_state.total_short_amount)
-                    .ok_or(ErrorCode::ArithmeticError)?;
-                msg!("Shorts won. Payout calculated: {}", payout_amount);
-            } else {
-                 msg!("Shorts won, but no shorts placed. No payout for this bet.");
-            }
-        } else {
-            // Bet did not win
-            msg!("Bet did not win. No payout.");
-            // Mark as claimed even if no payout to prevent re-attempts
-            bet_state.winnings_claimed = true; 
-            return Ok(());
+        // Shares may have been bought by another wallet, the seller's bet state then starts here
+        let is_affiliate = is_subject_or_affiliate(market_state, &ctx.accounts.subject_affiliates, &bettor)?;
+        let bet_state = &mut ctx.accounts.bet_state;
+        let market_key = market_state.key();
+        start_bet_state(
+            bet_state,
+            market_state,
+            market_key,
+            bettor,
+            is_affiliate,
+            ctx.bumps.bet_state,
+        )?;
+        let side_stake = if position_is_long { &mut bet_state.long_amount } else { &mut bet_state.short_amount };
+        let staked_amount = usdc_out.min(*side_stake);
+        *side_stake = side_stake
+            .checked_sub(staked_amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        market_state.amm_stake_amount = market_state.amm_stake_amount
+            .checked_sub(staked_amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        market_state.amm_sold_beyond_stakes_amount = market_state.amm_sold_beyond_stakes_amount
+            .checked_add(usdc_out - staked_amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        // Burned affiliate LONG tokens no longer count against the tokens sharing a forfeit
+        if position_is_long && bet_state.is_affiliate {
+            market_state.affiliate_long_amount = market_state.affiliate_long_amount.saturating_sub(shares);
         }
 
-        if payout_amount > 0 {
-            msg!("Transferring winnings: {}", payout_amount);
+        if usdc_out > 0 {
             let market_key = market_state.key();
             let seeds = &[
                 MARKET_ESCROW_VAULT_SEED,
@@ -671,275 +2197,3699 @@ pub mod skillstreak_program {
             };
             let cpi_program = ctx.accounts.token_program.to_account_info();
             let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-            token::transfer(cpi_ctx, payout_amount)?;
-            msg!("Winnings {} transferred to bettor.", payout_amount);
-        } else {
-            msg!("Calculated payout is zero.");
+            token::transfer(cpi_ctx, usdc_out)?;
         }
-        
-        bet_state.winnings_claimed = true;
 
-        emit!(WinningsClaimed {
+        let bettor_stats = &mut ctx.accounts.bettor_stats;
+        init_bettor_stats(bettor_stats, bettor, ctx.bumps.bettor_stats);
+        record_bettor_payout(bettor_stats, 0, usdc_out, None)?;
+
+        emit!(SharesTraded {
             market: market_state.key(),
             bettor,
-            amount_claimed: payout_amount,
+            position_is_long,
+            is_buy: false,
+            usdc_amount: usdc_out,
+            shares,
+            fee_amount,
+            implied_long_probability_basis_points: market_state.implied_long_probability_basis_points,
         });
+        msg!("Sold {} shares for {}.", shares, usdc_out);
         Ok(())
     }
 
-    pub fn withdraw_unlocked(ctx: Context<WithdrawUnlocked>) -> Result<()> {
-        let user_state = &mut ctx.accounts.user_state;
-        
-        // Calculate unlocked amount (total deposit minus locked amount)
-        let unlocked_amount = user_state.deposit_amount
-            .checked_sub(user_state.initial_deposit_amount)
-            .ok_or(ErrorCode::ArithmeticError)?;
-
-        if unlocked_amount == 0 {
-            return err!(ErrorCode::NoUnlockedBalance);
+    // Declares wallets controlled by the subject. Declared affiliates can't short the subject's
+    // markets and don't share in forfeited deposits. The list is append-only.
+    pub fn declare_affiliates(ctx: Context<DeclareAffiliates>, affiliates: Vec<Pubkey>) -> Result<()> {
+        let affiliate_list = &mut ctx.accounts.affiliate_list;
+        if affiliate_list.user == Pubkey::default() {
+            affiliate_list.user = ctx.accounts.user.key();
+            affiliate_list.bump = ctx.bumps.affiliate_list;
         }
 
-        // Transfer unlocked tokens from vault to user
-        let seeds = &[VAULT_SEED, &[ctx.bumps.vault]];
-        let signer = &[&seeds[..]];
-        
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.vault_token_account.to_account_info(),
-            to: ctx.accounts.user_token_account.to_account_info(),
-            authority: ctx.accounts.vault.to_account_info(),
-        };
-        let cpi_program = ctx.accounts.token_program.to_account_info();
-        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-        token::transfer(cpi_ctx, unlocked_amount)?;
-
-        // Update user state - reduce deposit_amount by unlocked_amount
-        user_state.deposit_amount = user_state.deposit_amount
-            .checked_sub(unlocked_amount)
-            .ok_or(ErrorCode::ArithmeticError)?;
+        for affiliate in affiliates {
+            if affiliate == affiliate_list.user || affiliate_list.affiliates.contains(&affiliate) {
+                continue;
+            }
+            if affiliate_list.affiliates.len() >= MAX_AFFILIATES {
+                return err!(ErrorCode::TooManyAffiliates);
+            }
+            affiliate_list.affiliates.push(affiliate);
+        }
 
-        msg!("Withdrawn {} unlocked tokens to user", unlocked_amount);
+        msg!("Affiliates declared for {}: {}", affiliate_list.user, affiliate_list.affiliates.len());
         Ok(())
     }
 
-    // Add record_task instruction
-    pub fn record_task(ctx: Context<RecordTask>) -> Result<()> {
-        let user_state = &mut ctx.accounts.user_state;
+    // Permissionless crank: closes betting and, once the grace period is over, resolves the market
+    // in the same call. With a dispute window configured, the outcome is first recorded as
+    // provisional and a call after the window finalizes it. The caller of the finalizing call
+    // receives a bounty out of the platform fee.
+    pub fn trigger_market_resolution(ctx: Context<TriggerMarketResolution>) -> Result<()> {
         let clock = Clock::get()?;
-        let current_timestamp = clock.unix_timestamp;
+        let market_state = &mut ctx.accounts.market_state;
+        let user_state_for_bet = &ctx.accounts.user_state_for_bet;
+        
+        msg!("Attempting to resolve market: {}", market_state.key());
 
-        // Validate lock-in is active
-        if user_state.lock_in_end_timestamp == 0 || current_timestamp >= user_state.lock_in_end_timestamp {
-            return err!(ErrorCode::CourseNotActive);
+        // Ensure user state account matches the one set at market creation
+        if user_state_for_bet.key() != market_state.user_state_account_for_bet {
+            return err!(ErrorCode::UserStateMismatch);
+        }
+         // Ensure user_state_for_bet.user is the one being bet on
+        if user_state_for_bet.user != market_state.user_being_bet_on {
+            return err!(ErrorCode::UserStateMismatch);
         }
 
-        // 1. Calculate how many 24-hour periods have passed since deposit
-        let days_since_deposit = (current_timestamp - user_state.deposit_timestamp)
-            .checked_div(DAILY_TASK_CYCLE_SECONDS)
-            .ok_or(ErrorCode::ArithmeticError)?;
-        
-        // Update streak based on days passed (this is the automatic incrementer)
-        user_state.current_streak = days_since_deposit as u64;
+        // A market can't be resolved if its course was exited before the task deadline
+        // or has since been replaced by a new course; it is cancelled so bettors get refunds.
+        let course_exited_before_deadline = user_state_for_bet.course_exit_timestamp != 0
+            && user_state_for_bet.course_exit_timestamp < market_state.task_deadline_timestamp;
+        if (course_exited_before_deadline || user_state_for_bet.deposit_timestamp != market_state.course_start_timestamp)
+            && cancel_market(market_state)?
+        {
+            msg!("Subject's course ended before the task deadline. Market cancelled.");
+            return Ok(());
+        }
+        // Once the market's first cycle has aged out of the task history its outcome can't be
+        // judged anymore (old cycles would read as missed), so it is cancelled as well.
+        if !task_history_covers(user_state_for_bet, market_state.cycle_index) && cancel_market(market_state)? {
+            msg!("Task history no longer covers the market's cycles. Market cancelled.");
+            return Ok(());
+        }
 
-        // 2. Check if this task completion is within 24 hours of last activity
-        let last_valid_completion = user_state.last_task_timestamp
-            .checked_add(DAILY_TASK_CYCLE_SECONDS)
-            .ok_or(ErrorCode::ArithmeticError)?;
 
-        if current_timestamp > last_valid_completion {
-            // Missed the window between activities, reset streak
-            user_state.current_streak = 0;
-            user_state.miss_count = user_state.miss_count
-                .checked_add(1)
-                .ok_or(ErrorCode::ArithmeticError)?;
-            msg!("Too much time between activities. Streak reset. Miss count: {}", user_state.miss_count);
-        } else {
-            msg!("Activity recorded successfully. Current streak: {}", user_state.current_streak);
+        // Streak markets resolve for shorts as soon as a missed cycle makes the threshold unreachable
+        let daily_task_cycle_seconds = user_state_for_bet.task_cycle_seconds;
+        let unresolved = market_state.status == MarketStatus::Open || market_state.status == MarketStatus::AwaitingResolution;
+        let shorts_win_early = market_state.market_type == MarketType::StreakSurvival
+            && unresolved
+            && clock.unix_timestamp >= market_state.betting_ends_timestamp
+            && market_outcome(user_state_for_bet, market_state, clock.unix_timestamp, daily_task_cycle_seconds)? == Some(false);
+        if shorts_win_early {
+            msg!("Streak threshold can no longer be reached. Resolving early.");
+            market_state.status = MarketStatus::AwaitingResolution;
         }
 
-        // Update last task timestamp
-        user_state.last_task_timestamp = current_timestamp;
+        if market_state.status == MarketStatus::Open && clock.unix_timestamp >= market_state.betting_ends_timestamp {
+            market_state.status = MarketStatus::AwaitingResolution;
+            msg!("Market status changed to AwaitingResolution.");
+            // Resolve in the same call if the grace period is already over, otherwise
+            // no further action until resolution_timestamp is reached
+            if clock.unix_timestamp < market_state.resolution_timestamp {
+                return Ok(());
+            }
+        }
+        
+        let user_completed_task_in_time = if market_state.status == MarketStatus::ProvisionallyResolved {
+            // The provisional outcome (after any resolver ruling) becomes final once the
+            // dispute window has closed with no dispute open
+            if clock.unix_timestamp < market_state.dispute_ends_timestamp {
+                return err!(ErrorCode::DisputeWindowOpen);
+            }
+            if market_state.disputer != Pubkey::default() {
+                if clock.unix_timestamp < market_state.ruling_deadline_timestamp {
+                    return err!(ErrorCode::DisputePending);
+                }
+                // The resolver missed its deadline: the provisional outcome stands, and the
+                // disputer, who had no ruling, gets the bond back
+                let bond_amount = market_state.dispute_bond_amount;
+                let disputer_token_account = ctx.accounts.disputer_token_account
+                    .as_ref()
+                    .ok_or(ErrorCode::InvalidDisputerAccount)?;
+                let market_key = market_state.key();
+                let seeds = &[
+                    MARKET_ESCROW_VAULT_SEED,
+                    market_key.as_ref(),
+                    &[ctx.accounts.market_escrow_vault.bump],
+                ];
+                let signer = &[&seeds[..]];
+
+                let cpi_accounts = Transfer {
+                    from: ctx.accounts.market_escrow_token_account.to_account_info(),
+                    to: disputer_token_account.to_account_info(),
+                    authority: ctx.accounts.market_escrow_vault.to_account_info(),
+                };
+                let cpi_program = ctx.accounts.token_program.to_account_info();
+                token::transfer(CpiContext::new_with_signer(cpi_program, cpi_accounts, signer), bond_amount)?;
+                msg!("Resolver did not rule in time. Dispute bond {} returned to {}.", bond_amount, market_state.disputer);
+                market_state.disputer = Pubkey::default();
+                market_state.dispute_bond_amount = 0;
+            }
+            market_state.provisional_longs_win
+        } else {
+            if market_state.status != MarketStatus::AwaitingResolution {
+                if market_state.status == MarketStatus::ResolvedLongsWin
+                    || market_state.status == MarketStatus::ResolvedShortsWin
+                    || market_state.status == MarketStatus::Refunding
+                {
+                     return err!(ErrorCode::MarketAlreadyResolved);
+                }
+                if market_state.status == MarketStatus::Cancelled {
+                    return err!(ErrorCode::MarketCancelled);
+                }
+                 // If still Open but betting window not closed, or some other state
+                return err!(ErrorCode::MarketNotReadyForResolution);
+            }
+
+            // Now, status must be AwaitingResolution
+            if !shorts_win_early && clock.unix_timestamp < market_state.resolution_timestamp {
+                return err!(ErrorCode::ResolutionGracePeriodNotOver);
+            }
 
-        // For MVP: Add a small fixed yield amount based on streak
-        let yield_amount = match user_state.current_streak {
-            0..=4 => 1_000, // 0.001 USDC (1000 lamports)
-            5..=9 => 2_000, // 0.002 USDC
-            _ => 5_000,     // 0.005 USDC
+            // Determine outcome
+            // The user needed to record tasks during the market's cycles. Tasks recorded in later
+            // cycles (e.g. during the grace period) don't affect the outcome.
+            let outcome = market_outcome(user_state_for_bet, market_state, clock.unix_timestamp, daily_task_cycle_seconds)?
+                .ok_or(ErrorCode::MarketNotReadyForResolution)?;
+
+            // With a dispute window, the outcome is provisional until a later call finalizes it
+            let dispute_window_seconds = ctx.accounts.program_config.dispute_window_seconds;
+            if dispute_window_seconds > 0 {
+                market_state.status = MarketStatus::ProvisionallyResolved;
+                market_state.provisional_longs_win = outcome;
+                market_state.dispute_ends_timestamp = clock.unix_timestamp
+                    .checked_add(dispute_window_seconds)
+                    .ok_or(ErrorCode::ArithmeticError)?;
+                emit!(MarketProvisionallyResolved {
+                    market: market_state.key(),
+                    longs_win: outcome,
+                    dispute_ends_timestamp: market_state.dispute_ends_timestamp,
+                });
+                msg!("Market provisionally resolved (longs win: {}). Disputable until {}.", outcome, market_state.dispute_ends_timestamp);
+                return Ok(());
+            }
+            outcome
         };
 
-        user_state.accrued_yield = user_state.accrued_yield
-            .checked_add(yield_amount)
-            .ok_or(ErrorCode::ArithmeticError)?;
+        let winning_side_total = if user_completed_task_in_time {
+            market_state.total_long_amount
+        } else {
+            market_state.total_short_amount
+        };
 
-        msg!("Task recorded. Timestamp: {}", current_timestamp);
-        msg!("Added yield: {} lamports", yield_amount);
-        
-        Ok(())
-    }
-}
+        // AMM markets always have the pool's shares on both sides, so they never refund
+        if market_state.market_mode == MarketMode::Parimutuel && winning_side_total == 0 {
+            // Nobody bet on the winning side (or nobody bet at all): there is no one to pay the
+            // pool to, so every bettor reclaims their full stake and no platform fee is taken.
+            market_state.status = MarketStatus::Refunding;
+            market_state.platform_fee_claimed = true;
+            msg!("Market resolved with an empty winning side. Bets are refunded.");
+        } else if user_completed_task_in_time {
+            market_state.status = MarketStatus::ResolvedLongsWin;
+            msg!("Market resolved: Longs Win.");
+        } else {
+            market_state.status = MarketStatus::ResolvedShortsWin;
+            msg!("Market resolved: Shorts Win.");
+        }
+        market_state.settled_timestamp = clock.unix_timestamp;
 
-#[derive(Accounts)]
-#[instruction(deposit_amount: u64)]
-pub struct Deposit<'info> {
-    #[account(mut)]
-    pub user: Signer<'info>,
+        record_subject_resolution(&mut ctx.accounts.subject_stats, &market_state.status)?;
 
-    #[account(
-        mut,
-        constraint = user_token_account.mint == usdc_mint.key(),
-        constraint = user_token_account.owner == user.key()
-    )]
-    pub user_token_account: Account<'info, TokenAccount>,
+        // Platform Fee Transfer (only if not already claimed and there's a pool)
+        if !market_state.platform_fee_claimed {
+            let total_pool = market_state.total_long_amount
+                .checked_add(market_state.total_short_amount)
+                .ok_or(ErrorCode::ArithmeticError)?;
 
-    #[account(
-        mut,
-        seeds = [USER_SEED, user.key().as_ref()],
-        bump,
-    )]
-    pub user_state: Account<'info, UserState>,
+            // AMM markets collect their fee on every trade instead of from the pool
+            let platform_fee = if market_state.market_mode == MarketMode::Amm {
+                market_state.amm_fee_amount
+            } else {
+                platform_fee(total_pool, market_state.platform_fee_basis_points)?
+            };
 
-    // Use init_if_needed for the vault's token account
-    #[account(
-        init_if_needed, // Initialize if it doesn't exist
-        payer = user,    // The user pays for initialization
-        associated_token::mint = usdc_mint,
-        associated_token::authority = vault, // Vault PDA is the authority
-    )]
-    pub vault_token_account: Account<'info, TokenAccount>,
+            if platform_fee > 0 {
+                // The caller gets a bounty out of the fee for cranking the resolution. Part of the
+                // rest is rebated to the market's creator or subject, the remainder goes to the treasury.
+                let bounty_amount = (platform_fee as u128)
+                    .checked_mul(ctx.accounts.program_config.resolution_bounty_basis_points as u128)
+                    .ok_or(ErrorCode::ArithmeticError)?
+                    .checked_div(10000)
+                    .ok_or(ErrorCode::ArithmeticError)? as u64;
+                let fee_after_bounty = platform_fee
+                    .checked_sub(bounty_amount)
+                    .ok_or(ErrorCode::ArithmeticError)?;
+                let rebate_amount = (fee_after_bounty as u128)
+                    .checked_mul(market_state.fee_rebate_basis_points as u128)
+                    .ok_or(ErrorCode::ArithmeticError)?
+                    .checked_div(10000)
+                    .ok_or(ErrorCode::ArithmeticError)? as u64;
+                let treasury_amount = fee_after_bounty
+                    .checked_sub(rebate_amount)
+                    .ok_or(ErrorCode::ArithmeticError)?;
+                msg!("Transferring platform fee: {} (bounty: {}, rebate: {})", platform_fee, bounty_amount, rebate_amount);
+
+                let market_key = market_state.key();
+                let seeds = &[
+                    MARKET_ESCROW_VAULT_SEED,
+                    market_key.as_ref(),
+                    &[ctx.accounts.market_escrow_vault.bump],
+                ];
+                let signer = &[&seeds[..]];
+
+                if treasury_amount > 0 {
+                    create_fee_token_account(
+                        &ctx.accounts.caller,
+                        &ctx.accounts.treasury_token_account,
+                        &ctx.accounts.treasury_wallet_account,
+                        &ctx.accounts.usdc_mint,
+                        &ctx.accounts.system_program,
+                        &ctx.accounts.token_program,
+                        &ctx.accounts.associated_token_program,
+                    )?;
+                    let cpi_accounts = Transfer {
+                        from: ctx.accounts.market_escrow_token_account.to_account_info(),
+                        to: ctx.accounts.treasury_token_account.to_account_info(),
+                        authority: ctx.accounts.market_escrow_vault.to_account_info(),
+                    };
+                    let cpi_program = ctx.accounts.token_program.to_account_info();
+                    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+                    token::transfer(cpi_ctx, treasury_amount)?;
+                    msg!("Platform fee {} transferred to treasury.", treasury_amount);
+                }
+                if rebate_amount > 0 {
+                    create_fee_token_account(
+                        &ctx.accounts.caller,
+                        &ctx.accounts.fee_rebate_token_account,
+                        &ctx.accounts.fee_rebate_recipient,
+                        &ctx.accounts.usdc_mint,
+                        &ctx.accounts.system_program,
+                        &ctx.accounts.token_program,
+                        &ctx.accounts.associated_token_program,
+                    )?;
+                    let cpi_accounts = Transfer {
+                        from: ctx.accounts.market_escrow_token_account.to_account_info(),
+                        to: ctx.accounts.fee_rebate_token_account.to_account_info(),
+                        authority: ctx.accounts.market_escrow_vault.to_account_info(),
+                    };
+                    let cpi_program = ctx.accounts.token_program.to_account_info();
+                    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+                    token::transfer(cpi_ctx, rebate_amount)?;
+                    msg!("Fee rebate {} transferred to {}.", rebate_amount, market_state.fee_rebate_recipient);
+                }
+                if bounty_amount > 0 {
+                    let cpi_accounts = Transfer {
+                        from: ctx.accounts.market_escrow_token_account.to_account_info(),
+                        to: ctx.accounts.caller_token_account.to_account_info(),
+                        authority: ctx.accounts.market_escrow_vault.to_account_info(),
+                    };
+                    let cpi_program = ctx.accounts.token_program.to_account_info();
+                    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+                    token::transfer(cpi_ctx, bounty_amount)?;
+                    msg!("Resolution bounty {} transferred to caller.", bounty_amount);
+                }
 
-    /// CHECK: Vault PDA authority, seeds checked implicitly by ATA derivation + constraints
-    #[account(
-        seeds = [VAULT_SEED],
-        bump
-    )]
-    pub vault: AccountInfo<'info>,
+                emit!(PlatformFeeCollected {
+                    market: market_key,
+                    treasury_amount,
+                    rebate_amount,
+                    fee_rebate_recipient: market_state.fee_rebate_recipient,
+                    resolution_bounty_amount: bounty_amount,
+                    caller: ctx.accounts.caller.key(),
+                });
+            }
+            market_state.platform_fee_claimed = true;
+        }
 
-    pub usdc_mint: Account<'info, Mint>,
+        // Anti-manipulation: a subject who misses while the short side is heavy forfeits part of
+        // their locked deposit to the (non-affiliate) long holders. Positions are the outcome
+        // tokens outstanding now, which follow AMM trades, sales and transfers.
+        let long_supply = ctx.accounts.long_mint.supply;
+        let eligible_long_amount = long_supply.saturating_sub(market_state.affiliate_long_amount);
+        // The subject's current course must still be the market's (it may have been replaced
+        // during the dispute window)
+        if market_state.status == MarketStatus::ResolvedShortsWin
+            && eligible_long_amount > 0
+            && ctx.accounts.user_state_for_bet.deposit_timestamp == market_state.course_start_timestamp
+            && is_short_heavy(long_supply, ctx.accounts.short_mint.supply)?
+        {
+            let user_state = &mut ctx.accounts.user_state_for_bet;
+            settle_yield(
+                user_state,
+                &mut ctx.accounts.vault_state,
+                &ctx.accounts.yield_config.params,
+                clock.unix_timestamp,
+            )?;
+
+            // Longs are compensated at most one USDC unit per eligible LONG token, and only from
+            // funds in the vault. That is their stake in parimutuel markets (tokens are minted 1:1),
+            // and what the shares would have paid had longs won in AMM markets.
+            let forfeited_amount = user_state.initial_deposit_amount
+                .checked_mul(SHORT_HEAVY_FORFEIT_BASIS_POINTS as u64)
+                .ok_or(ErrorCode::ArithmeticError)?
+                .checked_div(10000)
+                .ok_or(ErrorCode::ArithmeticError)?
+                .min(eligible_long_amount)
+                .min(ctx.accounts.vault_token_account.amount);
+
+            if forfeited_amount > 0 {
+                let vault_seeds = &[VAULT_SEED, &[ctx.bumps.vault]];
+                let vault_signer = &[&vault_seeds[..]];
+                let cpi_accounts = Transfer {
+                    from: ctx.accounts.vault_token_account.to_account_info(),
+                    to: ctx.accounts.market_escrow_token_account.to_account_info(),
+                    authority: ctx.accounts.vault.to_account_info(),
+                };
+                let cpi_program = ctx.accounts.token_program.to_account_info();
+                let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, vault_signer);
+                token::transfer(cpi_ctx, forfeited_amount)?;
+
+                user_state.initial_deposit_amount = user_state.initial_deposit_amount
+                    .checked_sub(forfeited_amount)
+                    .ok_or(ErrorCode::ArithmeticError)?;
+                user_state.deposit_amount = user_state.deposit_amount
+                    .checked_sub(forfeited_amount)
+                    .ok_or(ErrorCode::ArithmeticError)?;
+                let vault_state = &mut ctx.accounts.vault_state;
+                vault_state.total_principal = vault_state.total_principal
+                    .checked_sub(forfeited_amount)
+                    .ok_or(ErrorCode::ArithmeticError)?;
 
-    // Required programs
-    pub system_program: Program<'info, System>, // Needed for init_if_needed
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>, // Needed for init_if_needed
-    pub rent: Sysvar<'info, Rent>, // Needed for init_if_needed
-}
+                market_state.forfeited_amount = forfeited_amount;
+                market_state.forfeit_eligible_long_tokens = eligible_long_amount;
 
-#[account]
-#[derive(InitSpace)]
-pub struct UserState {
-    pub user: Pubkey,
-    pub deposit_amount: u64,
-    pub initial_deposit_amount: u64,
-    pub current_streak: u64,
-    pub miss_count: u64,
-    pub deposit_timestamp: i64,
-    pub last_task_timestamp: i64,
-    pub lock_in_end_timestamp: i64,
-    pub accrued_yield: u64,  // Added for yield tracking
-}
+                emit!(SubjectDepositForfeited {
+                    market: market_state.key(),
+                    user: user_state.user,
+                    forfeited_amount,
+                    remaining_locked_amount: user_state.initial_deposit_amount,
+                });
+                msg!("Short-heavy market missed. {} of the subject's deposit forfeited to longs.", forfeited_amount);
+            }
+        }
+        
+        emit!(MarketResolved {
+            market: market_state.key(),
+            status: market_state.status.clone(),
+            total_long_amount: market_state.total_long_amount,
+            total_short_amount: market_state.total_short_amount,
+        });
 
-// --- Create User State Accounts Struct ---
-// Defines accounts needed ONLY to create the UserState PDA.
-#[derive(Accounts)]
-pub struct CreateUserState<'info> {
-    // The user who is initializing their state.
-    #[account(mut)]
-    pub user: Signer<'info>,
+        Ok(())
+    }
 
-    // The UserState account being created.
-    #[account(
-        init, // This instruction initializes the account
-        payer = user, // The user pays for the account creation
-        space = 8 + UserState::INIT_SPACE, // Calculate space automatically
-        seeds = [USER_SEED, user.key().as_ref()], // Derive PDA using user seed and user pubkey
-        bump // Store the bump seed
-    )]
-    pub user_state: Account<'info, UserState>,
+    // Disputes a provisional outcome by posting the configured bond. Any bettor of the market can
+    // dispute within the dispute window; one dispute can be open at a time. The resolver has
+    // another dispute window's length after the window closes to rule on it.
+    pub fn dispute_outcome(ctx: Context<DisputeOutcome>) -> Result<()> {
+        let clock = Clock::get()?;
+        let market_state = &mut ctx.accounts.market_state;
+        let disputer = ctx.accounts.disputer.key();
 
-    // The Solana system program, required for account creation.
-    pub system_program: Program<'info, System>,
-}
+        msg!("Dispute by {} on market {}", disputer, market_state.key());
+
+        if market_state.status != MarketStatus::ProvisionallyResolved {
+            return err!(ErrorCode::MarketNotProvisionallyResolved);
+        }
+        if clock.unix_timestamp >= market_state.dispute_ends_timestamp {
+            return err!(ErrorCode::DisputeWindowClosed);
+        }
+        if market_state.disputer != Pubkey::default() {
+            return err!(ErrorCode::DisputePending);
+        }
+
+        // The bond is held in the market escrow until the resolver rules
+        let bond_amount = ctx.accounts.program_config.dispute_bond_amount;
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.disputer_token_account.to_account_info(),
+            to: ctx.accounts.market_escrow_token_account.to_account_info(),
+            authority: ctx.accounts.disputer.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        token::transfer(CpiContext::new(cpi_program, cpi_accounts), bond_amount)?;
+        market_state.disputer = disputer;
+        market_state.dispute_bond_amount = bond_amount;
+        market_state.ruling_deadline_timestamp = market_state.dispute_ends_timestamp
+            .checked_add(ctx.accounts.program_config.dispute_window_seconds)
+            .ok_or(ErrorCode::ArithmeticError)?;
+
+        emit!(OutcomeDisputed {
+            market: market_state.key(),
+            disputer,
+            bond_amount,
+            ruling_deadline_timestamp: market_state.ruling_deadline_timestamp,
+        });
+        msg!("Dispute opened with a bond of {}.", bond_amount);
+        Ok(())
+    }
+
+    // Resolver authority ruling on a provisional outcome, with or without an open dispute. The
+    // ruling closes the dispute window. An open dispute's bond is returned if the outcome is flipped,
+    // otherwise it is slashed to the treasury.
+    pub fn resolve_dispute(ctx: Context<ResolveDispute>, longs_win: bool) -> Result<()> {
+        let clock = Clock::get()?;
+        let market_state = &mut ctx.accounts.market_state;
+
+        msg!("Resolver ruling on market {}: longs win: {}", market_state.key(), longs_win);
+
+        if market_state.status != MarketStatus::ProvisionallyResolved {
+            return err!(ErrorCode::MarketNotProvisionallyResolved);
+        }
+        let disputer = market_state.disputer;
+        // Without a dispute the resolver can only correct the outcome while the window is open,
+        // so it can't overturn an undisputed outcome before the finalizing crank
+        if disputer == Pubkey::default() && clock.unix_timestamp >= market_state.dispute_ends_timestamp {
+            return err!(ErrorCode::DisputeWindowClosed);
+        }
+        if disputer != Pubkey::default() && clock.unix_timestamp >= market_state.ruling_deadline_timestamp {
+            return err!(ErrorCode::RulingDeadlinePassed);
+        }
+        let flipped = longs_win != market_state.provisional_longs_win;
+        let bond_amount = market_state.dispute_bond_amount;
+
+        if bond_amount > 0 {
+            let bond_destination = if flipped {
+                let disputer_token_account = ctx.accounts.disputer_token_account
+                    .as_ref()
+                    .ok_or(ErrorCode::InvalidDisputerAccount)?;
+                disputer_token_account.to_account_info()
+            } else {
+                ctx.accounts.treasury_token_account.to_account_info()
+            };
+            let market_key = market_state.key();
+            let seeds = &[
+                MARKET_ESCROW_VAULT_SEED,
+                market_key.as_ref(),
+                &[ctx.accounts.market_escrow_vault.bump],
+            ];
+            let signer = &[&seeds[..]];
+
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.market_escrow_token_account.to_account_info(),
+                to: bond_destination,
+                authority: ctx.accounts.market_escrow_vault.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            token::transfer(CpiContext::new_with_signer(cpi_program, cpi_accounts, signer), bond_amount)?;
+            msg!("Dispute bond {} {}.", bond_amount, if flipped { "returned to disputer" } else { "slashed to treasury" });
+        }
+
+        market_state.provisional_longs_win = longs_win;
+        market_state.disputer = Pubkey::default();
+        market_state.dispute_bond_amount = 0;
+        // The ruling is final, the next trigger_market_resolution call settles the market
+        market_state.dispute_ends_timestamp = market_state.dispute_ends_timestamp.min(clock.unix_timestamp);
+
+        emit!(DisputeResolved {
+            market: market_state.key(),
+            longs_win,
+            flipped,
+            disputer,
+            bond_amount,
+        });
+        Ok(())
+    }
+
+    pub fn claim_winnings(ctx: Context<ClaimWinnings>) -> Result<()> {
+        let market_state = &mut ctx.accounts.market_state;
+        let bettor = ctx.accounts.bettor.key();
+
+        msg!("Attempting to claim winnings for bettor {} on market {}", bettor, market_state.key());
+
+        // Validations
+        if market_state.status == MarketStatus::ProvisionallyResolved {
+            return err!(ErrorCode::DisputeWindowOpen);
+        }
+        if market_state.status.refunds_stakes() {
+            // Stakes of cancelled and refunding markets are returned via claim_refund
+            return err!(ErrorCode::MarketCancelled);
+        }
+        if !market_state.platform_fee_claimed {
+             // Safety check, should be claimed during resolution
+            return err!(ErrorCode::PlatformFeeNotClaimed);
+        }
+
+        // Burn every winning outcome token the bettor holds; losing tokens are worthless
+        let longs_win = market_state.status == MarketStatus::ResolvedLongsWin;
+        let (winning_mint, winning_token_account) = if longs_win {
+            (&ctx.accounts.long_mint, &ctx.accounts.bettor_long_token_account)
+        } else {
+            (&ctx.accounts.short_mint, &ctx.accounts.bettor_short_token_account)
+        };
+        let redeemed_tokens = winning_token_account.as_ref().map_or(0, |account| account.amount);
+        // The subject controls the outcome, so neither they nor their affiliates may profit from a
+        // miss, also not through SHORT tokens received by transfer
+        let is_insider = is_subject_or_affiliate(market_state, &ctx.accounts.subject_affiliates, &bettor)?;
+        if !longs_win && redeemed_tokens > 0 && is_insider {
+            return err!(ErrorCode::InsiderCannotRedeemShort);
+        }
+        if let Some(token_account) = winning_token_account.as_ref().filter(|_| redeemed_tokens > 0) {
+            burn_outcome_tokens(&ctx.accounts.token_program, winning_mint, token_account, &ctx.accounts.bettor, redeemed_tokens)?;
+        }
+
+        let mut payout_amount = if longs_win {
+            redemption_amount(market_state, redeemed_tokens, 0)?
+        } else {
+            redemption_amount(market_state, 0, redeemed_tokens)?
+        };
+        msg!("Winning tokens redeemed: {}, payout: {}", redeemed_tokens, payout_amount);
+
+        // LONG tokens of a short-heavy market the subject missed share the forfeited deposit,
+        // whoever holds them now, except the subject and their declared affiliates
+        let forfeit_tokens = if market_state.forfeited_amount > 0 && !is_insider {
+            ctx.accounts.bettor_long_token_account.as_ref().map_or(0, |account| account.amount)
+        } else {
+            0
+        };
+        if let Some(token_account) = ctx.accounts.bettor_long_token_account.as_ref().filter(|_| forfeit_tokens > 0) {
+            burn_outcome_tokens(&ctx.accounts.token_program, &ctx.accounts.long_mint, token_account, &ctx.accounts.bettor, forfeit_tokens)?;
+            let forfeit_share = forfeit_share(market_state, forfeit_tokens)?;
+            market_state.forfeit_claimed_amount = market_state.forfeit_claimed_amount
+                .checked_add(forfeit_share)
+                .ok_or(ErrorCode::ArithmeticError)?;
+            payout_amount = payout_amount
+                .checked_add(forfeit_share)
+                .ok_or(ErrorCode::ArithmeticError)?;
+            msg!("LONG tokens redeemed: {}, forfeited deposit share: {}", forfeit_tokens, forfeit_share);
+        }
+
+        // The bettor's own position, if passed, is settled once
+        let mut bet_settled = false;
+        let mut settled_bet_won = None;
+        if let Some(bet_state) = ctx.accounts.bet_state.as_mut().filter(|bet_state| !bet_state.winnings_claimed) {
+            bet_state.winnings_claimed = true;
+            bet_settled = true;
+            settled_bet_won = bet_position_won(market_state, bet_state)?;
+        }
+        if redeemed_tokens == 0 && forfeit_tokens == 0 && !bet_settled {
+            return err!(ErrorCode::NothingToClaim);
+        }
+
+        if payout_amount > 0 {
+            msg!("Transferring winnings: {}", payout_amount);
+            let market_key = market_state.key();
+            let seeds = &[
+                MARKET_ESCROW_VAULT_SEED,
+                market_key.as_ref(),
+                &[ctx.accounts.market_escrow_vault.bump],
+            ];
+            let signer = &[&seeds[..]];
+
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.market_escrow_token_account.to_account_info(),
+                to: ctx.accounts.bettor_token_account.to_account_info(),
+                authority: ctx.accounts.market_escrow_vault.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token::transfer(cpi_ctx, payout_amount)?;
+            msg!("Winnings {} transferred to bettor.", payout_amount);
+        } else {
+            msg!("Bet did not win. No payout.");
+        }
+
+        record_claim(market_state, payout_amount, bet_settled)?;
+
+        let bettor_stats = &mut ctx.accounts.bettor_stats;
+        init_bettor_stats(bettor_stats, bettor, ctx.bumps.bettor_stats);
+        record_bettor_payout(bettor_stats, payout_amount, 0, settled_bet_won)?;
+
+        emit!(WinningsClaimed {
+            market: market_state.key(),
+            bettor,
+            amount_claimed: payout_amount,
+        });
+        Ok(())
+    }
+
+    // Returns the stakes of a cancelled or refunding market by burning the bettor's outcome tokens
+    // of both sides at 1 USDC unit each. No platform fee is taken. For AMM markets, the shares are
+    // burned and the bettor's stakes net of sales are refunded from their bet state.
+    pub fn claim_refund(ctx: Context<ClaimWinnings>) -> Result<()> {
+        let market_state = &mut ctx.accounts.market_state;
+        let bettor = ctx.accounts.bettor.key();
+
+        msg!("Attempting to claim refund for bettor {} on market {}", bettor, market_state.key());
+
+        if !market_state.status.refunds_stakes() {
+            return err!(ErrorCode::MarketNotCancelled);
+        }
+
+        // Burn the outcome tokens of both sides the bettor holds
+        let mut returned_tokens = [0u64; 2]; // LONG, SHORT
+        for (side, (mint, token_account)) in [
+            (&ctx.accounts.long_mint, &ctx.accounts.bettor_long_token_account),
+            (&ctx.accounts.short_mint, &ctx.accounts.bettor_short_token_account),
+        ].into_iter().enumerate() {
+            if let Some(token_account) = token_account.as_ref().filter(|account| account.amount > 0) {
+                burn_outcome_tokens(&ctx.accounts.token_program, mint, token_account, &ctx.accounts.bettor, token_account.amount)?;
+                returned_tokens[side] = token_account.amount;
+            }
+        }
+
+        let mut refund_amount = redemption_amount(market_state, returned_tokens[0], returned_tokens[1])?;
+        let mut bet_settled = false;
+        if let Some(bet_state) = ctx.accounts.bet_state.as_mut().filter(|bet_state| !bet_state.winnings_claimed) {
+            bet_state.winnings_claimed = true;
+            bet_settled = true;
+            refund_amount = refund_amount
+                .checked_add(refund_amm_stake(market_state, bet_state)?)
+                .ok_or(ErrorCode::ArithmeticError)?;
+        }
+        if returned_tokens == [0, 0] && !bet_settled {
+            return err!(ErrorCode::NothingToClaim);
+        }
+
+        if refund_amount > 0 {
+            let market_key = market_state.key();
+            let seeds = &[
+                MARKET_ESCROW_VAULT_SEED,
+                market_key.as_ref(),
+                &[ctx.accounts.market_escrow_vault.bump],
+            ];
+            let signer = &[&seeds[..]];
+
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.market_escrow_token_account.to_account_info(),
+                to: ctx.accounts.bettor_token_account.to_account_info(),
+                authority: ctx.accounts.market_escrow_vault.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token::transfer(cpi_ctx, refund_amount)?;
+        }
+
+        record_claim(market_state, refund_amount, bet_settled)?;
+
+        let bettor_stats = &mut ctx.accounts.bettor_stats;
+        init_bettor_stats(bettor_stats, bettor, ctx.bumps.bettor_stats);
+        record_bettor_payout(bettor_stats, 0, refund_amount, None)?;
+
+        emit!(RefundClaimed {
+            market: market_state.key(),
+            bettor,
+            amount_refunded: refund_amount,
+        });
+        msg!("Refund {} transferred to bettor.", refund_amount);
+        Ok(())
+    }
+
+    // Permissionless batch settlement of a resolved or refunding market. remaining_accounts holds
+    // SETTLE_BET_ACCOUNTS per bet (see the constant). For each bet, the bettor's redeemable outcome
+    // tokens delegated to the escrow vault are burned and paid out together with any forfeited
+    // deposit share, and the BetState is closed with its rent returned to the bettor.
+    pub fn settle_bets<'info>(ctx: Context<'_, '_, 'info, 'info, SettleBets<'info>>) -> Result<()> {
+        let market_state = &mut ctx.accounts.market_state;
+        let market_key = market_state.key();
+        let escrow_vault_key = ctx.accounts.market_escrow_vault.key();
+
+        msg!("Settling bets of market {}", market_key);
+
+        let settled = market_state.status == MarketStatus::ResolvedLongsWin
+            || market_state.status == MarketStatus::ResolvedShortsWin
+            || market_state.status.refunds_stakes();
+        if !settled {
+            return err!(ErrorCode::MarketNotSettled);
+        }
+        if ctx.remaining_accounts.is_empty() || !ctx.remaining_accounts.chunks_exact(SETTLE_BET_ACCOUNTS).remainder().is_empty() {
+            return err!(ErrorCode::InvalidSettleAccounts);
+        }
+        // Outcome tokens that can still be redeemed on this market
+        let redeems_long = market_state.status != MarketStatus::ResolvedShortsWin;
+        let redeems_short = market_state.status != MarketStatus::ResolvedLongsWin;
+
+        let seeds = &[
+            MARKET_ESCROW_VAULT_SEED,
+            market_key.as_ref(),
+            &[ctx.accounts.market_escrow_vault.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let mut settled_bet_count: u64 = 0;
+        let mut total_paid_amount: u64 = 0;
+        for accounts in ctx.remaining_accounts.chunks(SETTLE_BET_ACCOUNTS) {
+            let [bet_state_info, bettor_info, bettor_token_info, long_token_info, short_token_info, bettor_stats_info] = accounts else {
+                return err!(ErrorCode::InvalidSettleAccounts);
+            };
+            // A BetState listed twice was closed by its first settlement and is no longer ours
+            if bet_state_info.owner != &crate::ID {
+                return err!(ErrorCode::InvalidSettleAccounts);
+            }
+            let mut bet_state = Account::<BetState>::try_from(bet_state_info)?;
+            if bet_state.market != market_key
+                || bet_state.market_creation_timestamp != market_state.market_creation_timestamp
+                || bet_state.bettor != bettor_info.key()
+            {
+                return err!(ErrorCode::InvalidSettleAccounts);
+            }
+            let bettor_token_account = Account::<TokenAccount>::try_from(bettor_token_info)?;
+            if bettor_token_account.mint != ctx.accounts.usdc_mint.key() || bettor_token_account.owner != bet_state.bettor {
+                return err!(ErrorCode::InvalidSettleAccounts);
+            }
+
+            // Burn the redeemable outcome tokens the bettor delegated to the escrow vault. LONG tokens
+            // of a missed short-heavy market redeem for a forfeited deposit share. The subject and
+            // their affiliates get neither that share nor SHORT payouts; their SHORT tokens are
+            // left unburned rather than failing the batch.
+            let is_insider = is_subject_or_affiliate(market_state, &ctx.accounts.subject_affiliates, &bet_state.bettor)?;
+            let redeems_forfeit = market_state.forfeited_amount > 0 && !is_insider;
+            let redeems_own_short = redeems_short
+                && !(is_insider && market_state.status == MarketStatus::ResolvedShortsWin);
+            let mut burned_tokens = [0u64; 2]; // LONG, SHORT
+            for (side, (mint, token_account_info, redeemable)) in [
+                (&ctx.accounts.long_mint, long_token_info, redeems_long || redeems_forfeit),
+                (&ctx.accounts.short_mint, short_token_info, redeems_own_short),
+            ].into_iter().enumerate() {
+                let delegated = delegated_outcome_tokens(token_account_info, &bet_state.bettor, &mint.key(), &escrow_vault_key)?;
+                if let Some((token_account, burnable)) = delegated.filter(|(_, burnable)| redeemable && *burnable > 0) {
+                    let cpi_accounts = Burn {
+                        mint: mint.to_account_info(),
+                        from: token_account.to_account_info(),
+                        authority: ctx.accounts.market_escrow_vault.to_account_info(),
+                    };
+                    let cpi_program = ctx.accounts.token_program.to_account_info();
+                    token::burn(CpiContext::new_with_signer(cpi_program, cpi_accounts, signer), burnable)?;
+                    burned_tokens[side] = burnable;
+                }
+            }
+
+            let forfeit_share = forfeit_share(market_state, burned_tokens[0])?;
+            market_state.forfeit_claimed_amount = market_state.forfeit_claimed_amount
+                .checked_add(forfeit_share)
+                .ok_or(ErrorCode::ArithmeticError)?;
+            let mut payout_amount = redemption_amount(market_state, burned_tokens[0], burned_tokens[1])?
+                .checked_add(forfeit_share)
+                .ok_or(ErrorCode::ArithmeticError)?;
+            let bet_settled = !bet_state.winnings_claimed;
+            if bet_settled {
+                payout_amount = payout_amount
+                    .checked_add(refund_amm_stake(market_state, &bet_state)?)
+                    .ok_or(ErrorCode::ArithmeticError)?;
+            }
+            bet_state.winnings_claimed = true;
+
+            if payout_amount > 0 {
+                let cpi_accounts = Transfer {
+                    from: ctx.accounts.market_escrow_token_account.to_account_info(),
+                    to: bettor_token_account.to_account_info(),
+                    authority: ctx.accounts.market_escrow_vault.to_account_info(),
+                };
+                let cpi_program = ctx.accounts.token_program.to_account_info();
+                token::transfer(CpiContext::new_with_signer(cpi_program, cpi_accounts, signer), payout_amount)?;
+            }
+            record_claim(market_state, payout_amount, bet_settled)?;
+
+            // Every bettor has stats from their first bet
+            let mut bettor_stats = Account::<BettorStats>::try_from(bettor_stats_info)?;
+            if bettor_stats.bettor != bet_state.bettor {
+                return err!(ErrorCode::InvalidSettleAccounts);
+            }
+            if market_state.status.refunds_stakes() {
+                record_bettor_payout(&mut bettor_stats, 0, payout_amount, None)?;
+            } else {
+                // Decided by the bet's own position, a winner whose tokens weren't burnable here
+                // still counts as a winner
+                let settled_bet_won = if bet_settled { bet_position_won(market_state, &bet_state)? } else { None };
+                record_bettor_payout(&mut bettor_stats, payout_amount, 0, settled_bet_won)?;
+            }
+            bettor_stats.exit(ctx.program_id)?;
+
+            // Return the BetState rent to the bettor
+            bet_state.close(bettor_info.clone())?;
+
+            settled_bet_count = settled_bet_count
+                .checked_add(1)
+                .ok_or(ErrorCode::ArithmeticError)?;
+            total_paid_amount = total_paid_amount
+                .checked_add(payout_amount)
+                .ok_or(ErrorCode::ArithmeticError)?;
+        }
+
+        emit!(BetsSettled {
+            market: market_key,
+            settled_bet_count,
+            total_paid_amount,
+        });
+        msg!("Settled {} bets, paid {}.", settled_bet_count, total_paid_amount);
+        Ok(())
+    }
+
+    // Pays the AMM pool's shares out to the market creator once the market is settled: the
+    // winning side's reserve at 1 USDC unit per share. A cancelled market returns the liquidity,
+    // less what traders received selling shares beyond their stakes.
+    pub fn redeem_liquidity(ctx: Context<RedeemLiquidity>) -> Result<()> {
+        let market_state = &mut ctx.accounts.market_state;
+
+        msg!("Redeeming AMM liquidity of market {}", market_state.key());
+
+        if market_state.market_mode != MarketMode::Amm {
+            return err!(ErrorCode::WrongMarketMode);
+        }
+        if market_state.amm_liquidity_redeemed {
+            return err!(ErrorCode::LiquidityAlreadyRedeemed);
+        }
+        let redeem_amount = match market_state.status {
+            MarketStatus::ResolvedLongsWin => market_state.amm_long_reserve,
+            MarketStatus::ResolvedShortsWin => market_state.amm_short_reserve,
+            MarketStatus::Cancelled => market_state.amm_liquidity_amount
+                .saturating_sub(market_state.amm_sold_beyond_stakes_amount),
+            _ => return err!(ErrorCode::MarketNotSettled),
+        };
+
+        if redeem_amount > 0 {
+            let market_key = market_state.key();
+            let seeds = &[
+                MARKET_ESCROW_VAULT_SEED,
+                market_key.as_ref(),
+                &[ctx.accounts.market_escrow_vault.bump],
+            ];
+            let signer = &[&seeds[..]];
+
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.market_escrow_token_account.to_account_info(),
+                to: ctx.accounts.creator_token_account.to_account_info(),
+                authority: ctx.accounts.market_escrow_vault.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token::transfer(cpi_ctx, redeem_amount)?;
+        }
+
+        market_state.amm_liquidity_redeemed = true;
+        market_state.total_claimed_amount = market_state.total_claimed_amount
+            .checked_add(redeem_amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+
+        emit!(LiquidityRedeemed {
+            market: market_state.key(),
+            market_creator: ctx.accounts.market_creator.key(),
+            amount: redeem_amount,
+        });
+        msg!("AMM liquidity {} redeemed to market creator.", redeem_amount);
+        Ok(())
+    }
+
+    // Permissionless: once every bet on a settled market is claimed (or the claim period is over),
+    // sweeps the rounding dust and any unclaimed funds to the treasury and closes the market,
+    // returning all rent to the market creator.
+    pub fn finalize_market(ctx: Context<FinalizeMarket>) -> Result<()> {
+        let clock = Clock::get()?;
+        let market_state = &ctx.accounts.market_state;
+
+        msg!("Attempting to finalize market: {}", market_state.key());
+
+        let settled = market_state.status == MarketStatus::ResolvedLongsWin
+            || market_state.status == MarketStatus::ResolvedShortsWin
+            || market_state.status.refunds_stakes();
+        if !settled {
+            return err!(ErrorCode::MarketNotSettled);
+        }
+        let claim_deadline = market_state.settled_timestamp
+            .checked_add(ctx.accounts.program_config.claim_period_seconds)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        // Claims are outstanding while redeemable outcome tokens exist (LONG tokens too while a
+        // forfeited deposit is left to share), a cancelled AMM market has stakes left to refund,
+        // or the AMM pool's shares are unredeemed
+        let forfeit_outstanding = market_state.forfeit_claimed_amount < market_state.forfeited_amount;
+        let is_amm = market_state.market_mode == MarketMode::Amm;
+        let redeemable_supply = match market_state.status {
+            MarketStatus::Cancelled if is_amm => market_state.amm_stake_amount,
+            MarketStatus::ResolvedLongsWin => ctx.accounts.long_mint.supply,
+            MarketStatus::ResolvedShortsWin if !forfeit_outstanding => ctx.accounts.short_mint.supply,
+            _ => ctx.accounts.long_mint.supply
+                .checked_add(ctx.accounts.short_mint.supply)
+                .ok_or(ErrorCode::ArithmeticError)?,
+        };
+        let liquidity_outstanding = is_amm && !market_state.amm_liquidity_redeemed;
+        if (redeemable_supply > 0 || liquidity_outstanding)
+            && clock.unix_timestamp < claim_deadline
+        {
+            return err!(ErrorCode::ClaimsOutstanding);
+        }
+
+        let market_key = market_state.key();
+        let seeds = &[
+            MARKET_ESCROW_VAULT_SEED,
+            market_key.as_ref(),
+            &[ctx.accounts.market_escrow_vault.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        // Sweep rounding dust and unclaimed funds to the treasury
+        let swept_amount = ctx.accounts.market_escrow_token_account.amount;
+        if swept_amount > 0 {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.market_escrow_token_account.to_account_info(),
+                to: ctx.accounts.treasury_token_account.to_account_info(),
+                authority: ctx.accounts.market_escrow_vault.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            token::transfer(CpiContext::new_with_signer(cpi_program, cpi_accounts, signer), swept_amount)?;
+        }
+
+        // Close the escrow token account, rent back to the market creator
+        let cpi_accounts = CloseAccount {
+            account: ctx.accounts.market_escrow_token_account.to_account_info(),
+            destination: ctx.accounts.market_creator.to_account_info(),
+            authority: ctx.accounts.market_escrow_vault.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        token::close_account(CpiContext::new_with_signer(cpi_program, cpi_accounts, signer))?;
+
+        emit!(MarketFinalized {
+            market: market_key,
+            claimed_bet_count: market_state.claimed_bet_count,
+            bet_count: market_state.bet_count,
+            total_claimed_amount: market_state.total_claimed_amount,
+            swept_amount,
+        });
+
+        close_market_accounts(
+            &ctx.accounts.market_state.to_account_info(),
+            &ctx.accounts.market_escrow_vault.to_account_info(),
+            &ctx.accounts.market_creator.to_account_info(),
+        )?;
+
+        msg!("Market finalized. Swept {} to treasury, rent returned to creator.", swept_amount);
+        Ok(())
+    }
+
+    pub fn withdraw_unlocked(ctx: Context<WithdrawUnlocked>) -> Result<()> {
+        let user_state = &mut ctx.accounts.user_state;
+        
+        // Calculate unlocked amount (total deposit minus locked amount)
+        let unlocked_amount = user_state.deposit_amount
+            .checked_sub(user_state.initial_deposit_amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+
+        if unlocked_amount == 0 {
+            return err!(ErrorCode::NoUnlockedBalance);
+        }
+
+        // Hard check: the vault (idle balance plus funds deployed to strategies) must still cover
+        // every depositor's principal plus the yield reserve, so this payout can never be funded
+        // by other users' deposits.
+        let vault_state = &mut ctx.accounts.vault_state;
+        let required_backing = vault_state.total_principal
+            .checked_add(vault_state.yield_reserve)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        let vault_backing = ctx.accounts.vault_token_account.amount
+            .checked_add(vault_state.total_deployed)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        if unlocked_amount > vault_state.total_principal || vault_backing < required_backing {
+            return err!(ErrorCode::VaultUndercollateralized);
+        }
+        vault_state.total_principal = vault_state.total_principal
+            .checked_sub(unlocked_amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+
+        // Transfer unlocked tokens from vault to user
+        let seeds = &[VAULT_SEED, &[ctx.bumps.vault]];
+        let signer = &[&seeds[..]];
+        
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.vault_token_account.to_account_info(),
+            to: ctx.accounts.user_token_account.to_account_info(),
+            authority: ctx.accounts.vault.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::transfer(cpi_ctx, unlocked_amount)?;
+
+        // Update user state - reduce deposit_amount by unlocked_amount
+        user_state.deposit_amount = user_state.deposit_amount
+            .checked_sub(unlocked_amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+
+        msg!("Withdrawn {} unlocked tokens to user", unlocked_amount);
+        Ok(())
+    }
+
+    // Add record_task instruction
+    pub fn record_task(ctx: Context<RecordTask>) -> Result<()> {
+        let user_state = &mut ctx.accounts.user_state;
+        let daily_task_cycle_seconds = user_state.task_cycle_seconds;
+        let clock = Clock::get()?;
+        let current_timestamp = clock.unix_timestamp;
+
+        // Validate lock-in is active
+        if user_state.lock_in_end_timestamp == 0 || current_timestamp >= user_state.lock_in_end_timestamp {
+            return err!(ErrorCode::CourseNotActive);
+        }
+
+        // Every elapsed window must be penalized via record_miss before a late task is accepted,
+        // otherwise the late task would move last_task_timestamp forward and erase the miss.
+        if current_timestamp > next_miss_deadline(user_state, daily_task_cycle_seconds)? {
+            return err!(ErrorCode::UnrecordedMiss);
+        }
+
+        // Settle yield at the streak the user held until now
+        let yield_amount = settle_yield(
+            user_state,
+            &mut ctx.accounts.vault_state,
+            &ctx.accounts.yield_config.params,
+            current_timestamp,
+        )?;
+
+        // 1. Update the streak (rolling 24h timer, at most one increment per cycle)
+        let mut streak_reset = false;
+        let missed_window_topped_up = user_state.last_miss_deadline_timestamp != 0
+            && user_state.last_top_up_deadline_timestamp == user_state.last_miss_deadline_timestamp;
+
+        if user_state.streak_expires_at == 0 {
+            // First task of a new streak
+            user_state.current_streak = 1;
+            user_state.last_streak_increment_timestamp = current_timestamp;
+            msg!("Streak started. Current streak: {}", user_state.current_streak);
+        } else if current_timestamp > user_state.streak_expires_at && !missed_window_topped_up {
+            // Streak expired, this task starts a new one
+            user_state.current_streak = 1;
+            user_state.last_streak_increment_timestamp = current_timestamp;
+            streak_reset = true;
+            msg!("Streak expired. Streak reset. Miss count: {}", user_state.miss_count);
+        } else if current_timestamp >= user_state.last_streak_increment_timestamp
+            .checked_add(daily_task_cycle_seconds)
+            .ok_or(ErrorCode::ArithmeticError)?
+        {
+            // A full cycle has passed since the last increment (a topped-up miss keeps the streak alive)
+            user_state.current_streak = user_state.current_streak
+                .checked_add(1)
+                .ok_or(ErrorCode::ArithmeticError)?;
+            user_state.last_streak_increment_timestamp = current_timestamp;
+            msg!("Streak incremented. Current streak: {}", user_state.current_streak);
+        } else {
+            msg!("Activity recorded. Streak already counted for this cycle: {}", user_state.current_streak);
+        }
+
+        if user_state.current_streak > user_state.longest_streak {
+            user_state.longest_streak = user_state.current_streak;
+        }
+
+        // Mark this task cycle as completed for market resolution
+        let cycle_index = cycle_index_at(user_state, current_timestamp, daily_task_cycle_seconds)?;
+        mark_cycle_completed(user_state, cycle_index);
+
+        // Update last task timestamp and reset the streak timer
+        user_state.last_task_timestamp = current_timestamp;
+        user_state.streak_expires_at = current_timestamp
+            .checked_add(daily_task_cycle_seconds)
+            .ok_or(ErrorCode::ArithmeticError)?;
+
+        emit!(StreakUpdated {
+            user: user_state.user,
+            current_streak: user_state.current_streak,
+            longest_streak: user_state.longest_streak,
+            last_streak_increment_timestamp: user_state.last_streak_increment_timestamp,
+            streak_expires_at: user_state.streak_expires_at,
+            streak_reset,
+        });
+
+        msg!("Task recorded. Timestamp: {}", current_timestamp);
+        msg!("Added yield: {} lamports", yield_amount);
+        
+        Ok(())
+    }
+
+    // --- Record Miss Instruction (Phase 3) ---
+    // Permissionless crank: anyone can call this once a user's 24h task window has elapsed.
+    // Each call penalizes exactly one missed window, so a keeper catching up on several
+    // missed days calls it once per window.
+    pub fn record_miss(ctx: Context<RecordMiss>) -> Result<()> {
+        let user_state = &mut ctx.accounts.user_state;
+        let daily_task_cycle_seconds = user_state.task_cycle_seconds;
+        let clock = Clock::get()?;
+        let current_timestamp = clock.unix_timestamp;
+
+        // Validate lock-in has started
+        if user_state.lock_in_end_timestamp == 0 {
+            return err!(ErrorCode::CourseNotActive);
+        }
+
+        // The missed window must have fully elapsed
+        let missed_deadline = next_miss_deadline(user_state, daily_task_cycle_seconds)?;
+        if current_timestamp <= missed_deadline {
+            return err!(ErrorCode::MissWindowNotElapsed);
+        }
+        // Windows that end after the lock-in period are not penalized
+        if missed_deadline >= user_state.lock_in_end_timestamp {
+            return err!(ErrorCode::CourseNotActive);
+        }
+        // While a top-up is still possible, the user gets the chance to buy the miss back first
+        let next_miss_count = user_state.miss_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        if next_miss_count < REPEAT_MISS_THRESHOLD {
+            let top_up_deadline = missed_deadline
+                .checked_add(TOP_UP_WINDOW_SECONDS)
+                .ok_or(ErrorCode::ArithmeticError)?;
+            if current_timestamp <= top_up_deadline {
+                return err!(ErrorCode::TopUpWindowStillOpen);
+            }
+        }
+
+        // Settle yield before the streak reset and deduction change the rate inputs
+        settle_yield(
+            user_state,
+            &mut ctx.accounts.vault_state,
+            &ctx.accounts.yield_config.params,
+            current_timestamp,
+        )?;
+
+        // 1. Escalate miss count and reset streak
+        user_state.miss_count = user_state.miss_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        user_state.current_streak = 0;
+        user_state.streak_expires_at = 0; // Next record_task starts a new streak
+        user_state.last_miss_deadline_timestamp = missed_deadline;
+        if user_state.miss_count >= REPEAT_MISS_THRESHOLD {
+            user_state.yield_rate_halved = true;
+        }
+
+        // 2. Calculate deduction from the locked deposit
+        let penalty_basis_points = miss_penalty_basis_points(user_state.miss_count);
+        let penalty_amount = user_state.initial_deposit_amount
+            .checked_mul(penalty_basis_points as u64)
+            .ok_or(ErrorCode::ArithmeticError)?
+            .checked_div(10000)
+            .ok_or(ErrorCode::ArithmeticError)?;
+
+        // 3. Move the deduction from the shared vault to the treasury
+        if penalty_amount > 0 {
+            let vault_seeds = &[VAULT_SEED, &[ctx.bumps.vault]];
+            let vault_signer = &[&vault_seeds[..]];
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.vault_token_account.to_account_info(),
+                to: ctx.accounts.treasury_token_account.to_account_info(),
+                authority: ctx.accounts.vault.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, vault_signer);
+            token::transfer(cpi_ctx, penalty_amount)?;
+
+            user_state.initial_deposit_amount = user_state.initial_deposit_amount
+                .checked_sub(penalty_amount)
+                .ok_or(ErrorCode::ArithmeticError)?;
+            user_state.deposit_amount = user_state.deposit_amount
+                .checked_sub(penalty_amount)
+                .ok_or(ErrorCode::ArithmeticError)?;
+
+            let vault_state = &mut ctx.accounts.vault_state;
+            vault_state.total_principal = vault_state.total_principal
+                .checked_sub(penalty_amount)
+                .ok_or(ErrorCode::ArithmeticError)?;
+        }
+
+        emit!(MissRecorded {
+            user: user_state.user,
+            miss_count: user_state.miss_count,
+            missed_deadline_timestamp: missed_deadline,
+            penalty_basis_points,
+            penalty_amount,
+            remaining_locked_amount: user_state.initial_deposit_amount,
+            yield_rate_halved: user_state.yield_rate_halved,
+        });
+
+        msg!("Miss recorded for user: {}", user_state.user);
+        msg!("  Miss count: {}", user_state.miss_count);
+        msg!("  Penalty sent to treasury: {}", penalty_amount);
+        msg!("  Remaining locked amount: {}", user_state.initial_deposit_amount);
+
+        Ok(())
+    }
+
+    // --- Top Up After Miss Instruction (Phase 3) ---
+    // Lets a user buy back a missed window instead of taking the hard deduction from record_miss.
+    // The top-up is a percentage of the locked deposit based on the miss tier; half goes to the
+    // treasury and half is credited to the user's own (unlocked) balance. The streak is kept.
+    pub fn top_up_after_miss(ctx: Context<TopUpAfterMiss>) -> Result<()> {
+        let user_state = &mut ctx.accounts.user_state;
+        let daily_task_cycle_seconds = user_state.task_cycle_seconds;
+        let clock = Clock::get()?;
+        let current_timestamp = clock.unix_timestamp;
+
+        // Validate lock-in has started
+        if user_state.lock_in_end_timestamp == 0 {
+            return err!(ErrorCode::CourseNotActive);
+        }
+
+        // There must be a missed window that has not been recorded yet
+        let missed_deadline = next_miss_deadline(user_state, daily_task_cycle_seconds)?;
+        if current_timestamp <= missed_deadline {
+            return err!(ErrorCode::MissWindowNotElapsed);
+        }
+        if missed_deadline >= user_state.lock_in_end_timestamp {
+            return err!(ErrorCode::CourseNotActive);
+        }
+
+        // Top-ups are only offered for the first misses, and only before the top-up deadline
+        let next_miss_count = user_state.miss_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        if next_miss_count >= REPEAT_MISS_THRESHOLD {
+            return err!(ErrorCode::TopUpNotAvailable);
+        }
+        let top_up_deadline = missed_deadline
+            .checked_add(TOP_UP_WINDOW_SECONDS)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        if current_timestamp > top_up_deadline {
+            return err!(ErrorCode::TopUpWindowExpired);
+        }
+
+        settle_yield(
+            user_state,
+            &mut ctx.accounts.vault_state,
+            &ctx.accounts.yield_config.params,
+            current_timestamp,
+        )?;
+
+        // 1. Calculate top-up and the treasury / user balance split
+        let top_up_basis_points = miss_penalty_basis_points(next_miss_count);
+        let top_up_amount = user_state.initial_deposit_amount
+            .checked_mul(top_up_basis_points as u64)
+            .ok_or(ErrorCode::ArithmeticError)?
+            .checked_div(10000)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        let treasury_amount = top_up_amount
+            .checked_mul(TOP_UP_TREASURY_SHARE_BASIS_POINTS as u64)
+            .ok_or(ErrorCode::ArithmeticError)?
+            .checked_div(10000)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        let credited_amount = top_up_amount
+            .checked_sub(treasury_amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+
+        // 2. Transfer the treasury share from the user
+        if treasury_amount > 0 {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.user_token_account.to_account_info(),
+                to: ctx.accounts.treasury_token_account.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            token::transfer(CpiContext::new(cpi_program, cpi_accounts), treasury_amount)?;
+        }
+
+        // 3. Transfer the user's share into the vault and credit their balance
+        if credited_amount > 0 {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.user_token_account.to_account_info(),
+                to: ctx.accounts.vault_token_account.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            token::transfer(CpiContext::new(cpi_program, cpi_accounts), credited_amount)?;
+
+            user_state.deposit_amount = user_state.deposit_amount
+                .checked_add(credited_amount)
+                .ok_or(ErrorCode::ArithmeticError)?;
+
+            let vault_state = &mut ctx.accounts.vault_state;
+            vault_state.total_principal = vault_state.total_principal
+                .checked_add(credited_amount)
+                .ok_or(ErrorCode::ArithmeticError)?;
+        }
+
+        // 4. The miss still counts towards the tier, but the streak is kept
+        user_state.miss_count = next_miss_count;
+        user_state.last_miss_deadline_timestamp = missed_deadline;
+        user_state.last_top_up_deadline_timestamp = missed_deadline;
+
+        emit!(MissToppedUp {
+            user: user_state.user,
+            miss_count: user_state.miss_count,
+            missed_deadline_timestamp: missed_deadline,
+            top_up_basis_points,
+            top_up_amount,
+            treasury_amount,
+            credited_amount,
+            current_streak: user_state.current_streak,
+        });
+
+        msg!("Top-up after miss completed for user: {}", user_state.user);
+        msg!("  Miss count: {}", user_state.miss_count);
+        msg!("  Top-up sent to treasury: {}", treasury_amount);
+        msg!("  Top-up added to balance: {}", credited_amount);
+        msg!("  Streak kept at: {}", user_state.current_streak);
+
+        Ok(())
+    }
+
+    // --- Fund Yield Reserve Instruction (Phase 4) ---
+    // Adds USDC to the vault that is earmarked for paying out accrued_yield.
+    // Anyone can fund the reserve; it is the only source yield is ever paid from.
+    pub fn fund_yield_reserve(ctx: Context<FundYieldReserve>, amount: u64) -> Result<()> {
+        if amount == 0 {
+            return err!(ErrorCode::ZeroDepositAmount);
+        }
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.funder_token_account.to_account_info(),
+            to: ctx.accounts.vault_token_account.to_account_info(),
+            authority: ctx.accounts.funder.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        token::transfer(CpiContext::new(cpi_program, cpi_accounts), amount)?;
+
+        let vault_state = &mut ctx.accounts.vault_state;
+        vault_state.bump = ctx.bumps.vault_state;
+        vault_state.yield_reserve = vault_state.yield_reserve
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+
+        emit!(YieldReserveFunded {
+            funder: ctx.accounts.funder.key(),
+            amount,
+            yield_reserve: vault_state.yield_reserve,
+            total_yield_liabilities: vault_state.total_yield_liabilities,
+        });
+
+        msg!("Yield reserve funded with {}. Reserve: {}, Liabilities: {}",
+            amount, vault_state.yield_reserve, vault_state.total_yield_liabilities);
+        Ok(())
+    }
+
+    // --- Program Config Instructions ---
+    // Creates the singleton ProgramConfig. Only the program's upgrade authority can call this,
+    // and becomes the config admin.
+    pub fn initialize_config(ctx: Context<InitializeConfig>, params: ConfigParams) -> Result<()> {
+        validate_config_params(&params)?;
+
+        let program_config = &mut ctx.accounts.program_config;
+        program_config.admin = ctx.accounts.admin.key();
+        program_config.pending_admin = Pubkey::default();
+        program_config.treasury_wallet = params.treasury_wallet;
+        program_config.usdc_mint = params.usdc_mint;
+        program_config.daily_task_cycle_seconds = params.daily_task_cycle_seconds;
+        program_config.resolution_grace_period_seconds = params.resolution_grace_period_seconds;
+        program_config.default_platform_fee_basis_points = params.default_platform_fee_basis_points;
+        program_config.auto_market_betting_window_seconds = params.auto_market_betting_window_seconds;
+        program_config.early_exit_fee_basis_points = params.early_exit_fee_basis_points;
+        program_config.claim_period_seconds = params.claim_period_seconds;
+        program_config.min_platform_fee_basis_points = params.min_platform_fee_basis_points;
+        program_config.max_platform_fee_basis_points = params.max_platform_fee_basis_points;
+        program_config.max_fee_rebate_basis_points = params.max_fee_rebate_basis_points;
+        program_config.resolution_bounty_basis_points = params.resolution_bounty_basis_points;
+        program_config.resolver_authority = params.resolver_authority;
+        program_config.dispute_window_seconds = params.dispute_window_seconds;
+        program_config.dispute_bond_amount = params.dispute_bond_amount;
+        program_config.max_pool_deposit_multiple_basis_points = params.max_pool_deposit_multiple_basis_points;
+        program_config.max_bettor_deposit_multiple_basis_points = params.max_bettor_deposit_multiple_basis_points;
+        program_config.bump = ctx.bumps.program_config;
+
+        msg!("Program config initialized. Admin: {}", program_config.admin);
+        msg!("  Treasury wallet: {}", program_config.treasury_wallet);
+        msg!("  USDC mint: {}", program_config.usdc_mint);
+        Ok(())
+    }
+
+    // Replaces every tunable parameter except the USDC mint. Admin only.
+    pub fn update_config(ctx: Context<UpdateConfig>, params: ConfigParams) -> Result<()> {
+        validate_config_params(&params)?;
+
+        let program_config = &mut ctx.accounts.program_config;
+        // The accepted mint is pinned at initialization, existing balances are denominated in it
+        if params.usdc_mint != program_config.usdc_mint {
+            return err!(ErrorCode::UsdcMintImmutable);
+        }
+        program_config.treasury_wallet = params.treasury_wallet;
+        program_config.daily_task_cycle_seconds = params.daily_task_cycle_seconds;
+        program_config.resolution_grace_period_seconds = params.resolution_grace_period_seconds;
+        program_config.default_platform_fee_basis_points = params.default_platform_fee_basis_points;
+        program_config.auto_market_betting_window_seconds = params.auto_market_betting_window_seconds;
+        program_config.early_exit_fee_basis_points = params.early_exit_fee_basis_points;
+        program_config.claim_period_seconds = params.claim_period_seconds;
+        program_config.min_platform_fee_basis_points = params.min_platform_fee_basis_points;
+        program_config.max_platform_fee_basis_points = params.max_platform_fee_basis_points;
+        program_config.max_fee_rebate_basis_points = params.max_fee_rebate_basis_points;
+        program_config.resolution_bounty_basis_points = params.resolution_bounty_basis_points;
+        program_config.resolver_authority = params.resolver_authority;
+        program_config.dispute_window_seconds = params.dispute_window_seconds;
+        program_config.dispute_bond_amount = params.dispute_bond_amount;
+        program_config.max_pool_deposit_multiple_basis_points = params.max_pool_deposit_multiple_basis_points;
+        program_config.max_bettor_deposit_multiple_basis_points = params.max_bettor_deposit_multiple_basis_points;
+
+        msg!("Program config updated.");
+        Ok(())
+    }
+
+    // Proposes a new config admin, who takes over by calling accept_config_admin. Until then
+    // the current admin stays in charge and can propose another key. Admin only.
+    pub fn transfer_config_admin(ctx: Context<UpdateConfig>, new_admin: Pubkey) -> Result<()> {
+        if new_admin == Pubkey::default() {
+            return err!(ErrorCode::InvalidConfigAdmin);
+        }
+        let program_config = &mut ctx.accounts.program_config;
+        program_config.pending_admin = new_admin;
+
+        msg!("Config admin transfer to {} proposed", new_admin);
+        Ok(())
+    }
+
+    // Completes a transfer_config_admin. Only the proposed admin can call this, so the role
+    // can't be handed to a key nobody controls.
+    pub fn accept_config_admin(ctx: Context<AcceptConfigAdmin>) -> Result<()> {
+        let program_config = &mut ctx.accounts.program_config;
+        program_config.admin = ctx.accounts.new_admin.key();
+        program_config.pending_admin = Pubkey::default();
+
+        msg!("Config admin transferred to {}", program_config.admin);
+        Ok(())
+    }
+
+    // --- Yield Config Instructions (Phase 4) ---
+    // Creates the singleton yield configuration. Admin only.
+    pub fn initialize_yield_config(ctx: Context<InitializeYieldConfig>, params: YieldParams) -> Result<()> {
+        validate_yield_params(&params)?;
+
+        let yield_config = &mut ctx.accounts.yield_config;
+        yield_config.params = params;
+        yield_config.bump = ctx.bumps.yield_config;
+
+        msg!("Yield config initialized.");
+        Ok(())
+    }
+
+    // Replaces the yield parameters. Already-settled yield is unaffected; yield accruing
+    // since each user's last settlement uses the new parameters. Admin only.
+    pub fn update_yield_config(ctx: Context<UpdateYieldConfig>, params: YieldParams) -> Result<()> {
+        validate_yield_params(&params)?;
+
+        let yield_config = &mut ctx.accounts.yield_config;
+        yield_config.params = params;
+
+        msg!("Yield config updated.");
+        Ok(())
+    }
+
+    // --- Yield Strategy Instructions (Phase 4) ---
+    // Registers an external lending program/pool that vault funds can be deployed to.
+    // The lending program must implement the strategy interface (see strategy_instruction_data).
+    pub fn initialize_strategy(ctx: Context<InitializeStrategy>) -> Result<()> {
+        let yield_strategy = &mut ctx.accounts.yield_strategy;
+        yield_strategy.lending_program = ctx.accounts.lending_program.key();
+        yield_strategy.lending_pool = ctx.accounts.lending_pool.key();
+        yield_strategy.lending_pool_token_account = ctx.accounts.lending_pool_token_account.key();
+        yield_strategy.lending_position = ctx.accounts.lending_position.key();
+        yield_strategy.deployed_amount = 0;
+        yield_strategy.total_harvested = 0;
+        yield_strategy.last_harvest_timestamp = 0;
+        yield_strategy.bump = ctx.bumps.yield_strategy;
+
+        msg!("Yield strategy {} registered.", yield_strategy.key());
+        msg!("  Lending program: {}", yield_strategy.lending_program);
+        msg!("  Lending pool: {}", yield_strategy.lending_pool);
+        Ok(())
+    }
+
+    // Moves idle USDC from the vault into the strategy. Admin only.
+    pub fn deploy_to_strategy(ctx: Context<StrategyOperation>, amount: u64) -> Result<()> {
+        if ctx.accounts.caller.key() != ctx.accounts.program_config.admin {
+            return err!(ErrorCode::UnauthorizedStrategyAuthority);
+        }
+        if amount == 0 {
+            return err!(ErrorCode::ZeroDepositAmount);
+        }
+        if amount > ctx.accounts.vault_token_account.amount {
+            return err!(ErrorCode::InsufficientBalance);
+        }
+
+        let vault_seeds = &[VAULT_SEED, &[ctx.bumps.vault]];
+        strategy_deposit(ctx.accounts, amount, &[&vault_seeds[..]])?;
+
+        let yield_strategy = &mut ctx.accounts.yield_strategy;
+        yield_strategy.deployed_amount = yield_strategy.deployed_amount
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        let vault_state = &mut ctx.accounts.vault_state;
+        vault_state.total_deployed = vault_state.total_deployed
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+
+        emit!(StrategyFundsMoved {
+            strategy: yield_strategy.key(),
+            amount,
+            deployed: true,
+            strategy_deployed_amount: yield_strategy.deployed_amount,
+        });
+
+        msg!("Deployed {} to strategy. Strategy total: {}", amount, yield_strategy.deployed_amount);
+        Ok(())
+    }
+
+    // Pulls deployed principal back into the vault. Admin only.
+    pub fn recall_from_strategy(ctx: Context<StrategyOperation>, amount: u64) -> Result<()> {
+        if ctx.accounts.caller.key() != ctx.accounts.program_config.admin {
+            return err!(ErrorCode::UnauthorizedStrategyAuthority);
+        }
+        if amount == 0 || amount > ctx.accounts.yield_strategy.deployed_amount {
+            return err!(ErrorCode::InvalidStrategyAmount);
+        }
+
+        let balance_before = ctx.accounts.vault_token_account.amount;
+        let vault_seeds = &[VAULT_SEED, &[ctx.bumps.vault]];
+        strategy_withdraw(ctx.accounts, amount, &[&vault_seeds[..]])?;
+        ctx.accounts.vault_token_account.reload()?;
+        let received = ctx.accounts.vault_token_account.amount
+            .checked_sub(balance_before)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        if received != amount {
+            return err!(ErrorCode::StrategyTransferMismatch);
+        }
+
+        let yield_strategy = &mut ctx.accounts.yield_strategy;
+        yield_strategy.deployed_amount = yield_strategy.deployed_amount
+            .checked_sub(amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        let vault_state = &mut ctx.accounts.vault_state;
+        vault_state.total_deployed = vault_state.total_deployed
+            .checked_sub(amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+
+        emit!(StrategyFundsMoved {
+            strategy: yield_strategy.key(),
+            amount,
+            deployed: false,
+            strategy_deployed_amount: yield_strategy.deployed_amount,
+        });
+
+        msg!("Recalled {} from strategy. Strategy total: {}", amount, yield_strategy.deployed_amount);
+        Ok(())
+    }
+
+    // Permissionless crank: withdraws the whole position, keeps anything above the deployed
+    // principal as yield in the reserve, and redeploys the principal.
+    pub fn harvest(ctx: Context<StrategyOperation>) -> Result<()> {
+        let clock = Clock::get()?;
+        let deployed_amount = ctx.accounts.yield_strategy.deployed_amount;
+        if deployed_amount == 0 {
+            return err!(ErrorCode::InvalidStrategyAmount);
+        }
+
+        let balance_before = ctx.accounts.vault_token_account.amount;
+        let vault_seeds = &[VAULT_SEED, &[ctx.bumps.vault]];
+        strategy_withdraw(ctx.accounts, u64::MAX, &[&vault_seeds[..]])?;
+        ctx.accounts.vault_token_account.reload()?;
+        let received = ctx.accounts.vault_token_account.amount
+            .checked_sub(balance_before)
+            .ok_or(ErrorCode::ArithmeticError)?;
+
+        // Anything above principal is yield; a shortfall is a strategy loss
+        let yield_amount = received.saturating_sub(deployed_amount);
+        let loss_amount = deployed_amount.saturating_sub(received);
+        let redeploy_amount = deployed_amount
+            .checked_sub(loss_amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        if redeploy_amount > 0 {
+            strategy_deposit(ctx.accounts, redeploy_amount, &[&vault_seeds[..]])?;
+        }
+
+        let yield_strategy = &mut ctx.accounts.yield_strategy;
+        yield_strategy.deployed_amount = redeploy_amount;
+        yield_strategy.total_harvested = yield_strategy.total_harvested
+            .checked_add(yield_amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        yield_strategy.last_harvest_timestamp = clock.unix_timestamp;
+
+        let vault_state = &mut ctx.accounts.vault_state;
+        vault_state.total_deployed = vault_state.total_deployed
+            .checked_sub(loss_amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+        vault_state.yield_reserve = vault_state.yield_reserve
+            .checked_add(yield_amount)
+            .ok_or(ErrorCode::ArithmeticError)?;
+
+        emit!(StrategyHarvested {
+            strategy: yield_strategy.key(),
+            yield_amount,
+            loss_amount,
+            yield_reserve: vault_state.yield_reserve,
+        });
+
+        msg!("Harvested {} yield into reserve (loss: {}).", yield_amount, loss_amount);
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(deposit_amount: u64)]
+pub struct Deposit<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        constraint = user_token_account.mint == usdc_mint.key() @ ErrorCode::TokenAccountMintMismatch,
+        constraint = user_token_account.owner == user.key()
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [USER_SEED, user.key().as_ref()],
+        bump,
+    )]
+    pub user_state: Account<'info, UserState>,
+
+    // Use init_if_needed for the vault's token account
+    #[account(
+        init_if_needed, // Initialize if it doesn't exist
+        payer = user,    // The user pays for initialization
+        associated_token::mint = usdc_mint,
+        associated_token::authority = vault, // Vault PDA is the authority
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Vault PDA authority, seeds checked implicitly by ATA derivation + constraints
+    #[account(
+        seeds = [VAULT_SEED],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+
+    // Shared vault accounting, created by the first deposit
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + VaultState::INIT_SPACE,
+        seeds = [VAULT_STATE_SEED],
+        bump
+    )]
+    pub vault_state: Account<'info, VaultState>,
+
+    #[account(address = program_config.usdc_mint @ ErrorCode::InvalidUsdcMint)]
+    pub usdc_mint: Account<'info, Mint>,
+
+    // Required programs
+    pub system_program: Program<'info, System>, // Needed for init_if_needed
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>, // Needed for init_if_needed
+    pub rent: Sysvar<'info, Rent>, // Needed for init_if_needed
+}
+
+#[account]
+#[derive(InitSpace, Default)]
+pub struct UserState {
+    pub user: Pubkey,
+    pub deposit_amount: u64,
+    pub initial_deposit_amount: u64,
+    pub current_streak: u64,
+    pub miss_count: u64,
+    pub deposit_timestamp: i64,
+    pub last_task_timestamp: i64,
+    pub lock_in_end_timestamp: i64,
+    pub accrued_yield: u64,  // Added for yield tracking
+    pub last_miss_deadline_timestamp: i64, // Deadline of the last window penalized by record_miss
+    pub last_top_up_deadline_timestamp: i64, // Deadline of the last missed window bought back via top_up_after_miss
+    pub yield_rate_halved: bool,           // Set on the 4th+ miss, halves yield for the rest of the course
+    pub streak_expires_at: i64,            // Streak is lost if no task is recorded before this (0 = no active streak)
+    pub last_streak_increment_timestamp: i64, // Streak increments at most once per task cycle
+    pub longest_streak: u64,               // High-water mark of current_streak
+    pub last_yield_accrual_timestamp: i64, // Yield on the locked deposit is settled up to this point
+    pub last_task_cycle_index: u64,        // Latest task cycle (day of the course) with a recorded task
+    pub task_cycle_history: u64,           // Bit k set = task recorded in cycle last_task_cycle_index - k
+    pub course_exit_timestamp: i64,        // When the course was exited via early_withdraw (0 = not exited)
+    pub carried_over_yield: u64,           // accrued_yield outstanding from earlier courses when this one started
+    pub course_count: u64,                 // Courses started so far, numbers the markets of each course
+    pub task_cycle_seconds: i64,           // daily_task_cycle_seconds when the course started, fixes its cycles for its whole length
+}
+
+// --- Create User State Accounts Struct ---
+// Defines accounts needed ONLY to create the UserState PDA.
+#[derive(Accounts)]
+pub struct CreateUserState<'info> {
+    // The user who is initializing their state.
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    // The UserState account being created.
+    #[account(
+        init, // This instruction initializes the account
+        payer = user, // The user pays for the account creation
+        space = 8 + UserState::INIT_SPACE, // Calculate space automatically
+        seeds = [USER_SEED, user.key().as_ref()], // Derive PDA using user seed and user pubkey
+        bump // Store the bump seed
+    )]
+    pub user_state: Account<'info, UserState>,
+
+    // The Solana system program, required for account creation.
+    pub system_program: Program<'info, System>,
+}
+
+// Tunable program parameters, see ProgramConfig
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace)]
+pub struct ConfigParams {
+    pub treasury_wallet: Pubkey,                 // Receives penalties, exit fees and platform fees
+    pub usdc_mint: Pubkey,                       // The only accepted deposit/betting mint
+    pub daily_task_cycle_seconds: i64,           // Task window length, e.g. 24h (courses keep the length they started with)
+    pub resolution_grace_period_seconds: i64,    // Delay after a task deadline before markets resolve
+    pub default_platform_fee_basis_points: u16,  // Market fee, e.g. 200 = 2.00%
+    pub auto_market_betting_window_seconds: u64, // Betting window for markets created by start_course
+    pub early_exit_fee_basis_points: u16,        // early_withdraw penalty, e.g. 5000 = 50%
+    pub claim_period_seconds: i64,               // After a market settles, unclaimed funds can be swept after this
+    pub min_platform_fee_basis_points: u16,      // Bounds for the fee a market creator chooses (default must be within)
+    pub max_platform_fee_basis_points: u16,
+    pub max_fee_rebate_basis_points: u16,        // Max share of a market's fee rebated to its creator or subject
+    pub resolution_bounty_basis_points: u16,     // Share of a market's fee paid to the trigger_market_resolution caller
+    pub resolver_authority: Pubkey,              // Confirms or flips disputed market outcomes
+    pub dispute_window_seconds: i64,             // Time to dispute a provisional outcome, 0 resolves markets immediately
+    pub dispute_bond_amount: u64,                // USDC a bettor posts to dispute, returned if the outcome is flipped
+    pub max_pool_deposit_multiple_basis_points: u64,   // Cap on a market's total pool, relative to the subject's locked deposit
+    pub max_bettor_deposit_multiple_basis_points: u64, // Cap on one bettor's position (both sides), relative to the locked deposit
+}
+
+// Singleton program configuration, every instruction reads its parameters from here
+#[account]
+#[derive(InitSpace)]
+pub struct ProgramConfig {
+    pub admin: Pubkey, // Can update the config, yield config and move vault funds into strategies
+    pub pending_admin: Pubkey, // Proposed by transfer_config_admin, becomes admin once it signs accept_config_admin
+    pub treasury_wallet: Pubkey,
+    pub usdc_mint: Pubkey,
+    pub daily_task_cycle_seconds: i64,
+    pub resolution_grace_period_seconds: i64,
+    pub default_platform_fee_basis_points: u16,
+    pub auto_market_betting_window_seconds: u64,
+    pub early_exit_fee_basis_points: u16,
+    pub claim_period_seconds: i64,
+    pub min_platform_fee_basis_points: u16,
+    pub max_platform_fee_basis_points: u16,
+    pub max_fee_rebate_basis_points: u16,
+    pub resolution_bounty_basis_points: u16,
+    pub resolver_authority: Pubkey,
+    pub dispute_window_seconds: i64,
+    pub dispute_bond_amount: u64,
+    pub max_pool_deposit_multiple_basis_points: u64,
+    pub max_bettor_deposit_multiple_basis_points: u64,
+    pub bump: u8,
+}
+
+// Singleton accounting for the shared VAULT_SEED token account.
+// Invariant: vault token balance >= total_principal + yield_reserve.
+#[account]
+#[derive(InitSpace)]
+pub struct VaultState {
+    pub total_principal: u64,         // Sum of every user's deposit_amount held in the vault
+    pub total_yield_liabilities: u64, // Sum of accrued_yield credited to users but not yet settled
+    pub yield_reserve: u64,           // USDC funded specifically for paying out yield
+    pub total_deployed: u64,          // Principal currently deployed to yield strategies
+    pub bump: u8,
+}
+
+// Yield rate parameters, see current_apr_basis_points
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace)]
+pub struct YieldParams {
+    pub duration_tier_days: [u64; 4],             // Minimum lock-in days for each tier, ascending
+    pub duration_tier_apr_basis_points: [u16; 4], // Base APR for each tier, e.g. 1000 = 10%
+    pub streak_bonus_per_day_basis_points: u16,   // Multiplier added per streak day, e.g. 200 = +2%
+    pub max_streak_multiplier_basis_points: u16,  // Multiplier cap, e.g. 15000 = 1.5x
+    pub max_apr_basis_points: u16,                // Hard APR cap, e.g. 3000 = 30%
+}
+
+// Singleton yield configuration
+#[account]
+#[derive(InitSpace)]
+pub struct YieldConfig {
+    pub params: YieldParams,
+    pub bump: u8,
+}
+
+// An external lending program/pool the vault can deploy idle USDC into.
+#[account]
+#[derive(InitSpace)]
+pub struct YieldStrategy {
+    pub lending_program: Pubkey,            // Program implementing the strategy interface
+    pub lending_pool: Pubkey,               // Pool account inside the lending program
+    pub lending_pool_token_account: Pubkey, // Pool's USDC token account
+    pub lending_position: Pubkey,           // The vault's position account inside the lending program
+    pub deployed_amount: u64,               // Principal currently deployed to this strategy
+    pub total_harvested: u64,               // Lifetime yield moved into the reserve
+    pub last_harvest_timestamp: i64,
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct TreasuryState {
+    pub authority: Pubkey,  // Authority that can withdraw from treasury
+    pub total_fees_collected: u64,  // Track total fees collected
+}
+
+// Optional: Define Custom Errors
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Deposit amount cannot be zero.")]
+    ZeroDepositAmount,
+    #[msg("Lock-in period has not ended yet.")]
+    LockInPeriodNotEnded,
+    #[msg("Lock-in period has already ended. Use normal withdraw.")]
+    LockInPeriodEnded,
+    #[msg("An arithmetic operation failed (overflow/underflow/divide by zero).")]
+    ArithmeticError,
+    #[msg("Cannot start a course that has already begun.")]
+    CourseAlreadyStarted,
+    #[msg("Cannot start a course without an initial deposit.")]
+    NoDepositToStartCourse,
+    #[msg("Lock amount cannot be zero.")]
+    ZeroLockAmount,
+    #[msg("Insufficient balance for requested lock amount.")]
+    InsufficientBalance,
+    #[msg("No unlocked balance available for withdrawal.")]
+    NoUnlockedBalance,
+    // Add other potential errors here
+
+    // Betting System Errors
+    #[msg("Market is not open for betting.")]
+    MarketNotOpenForBets,
+    #[msg("Betting window for this market has closed.")]
+    BettingWindowClosed,
+    #[msg("Bet amount cannot be zero.")]
+    ZeroBetAmount,
+    #[msg("User cannot bet on their own streak.")]
+    CannotBetOnSelf,
+    #[msg("Market is not ready for resolution.")]
+    MarketNotReadyForResolution,
+    #[msg("Resolution grace period for this market is not over yet.")]
+    ResolutionGracePeriodNotOver,
+    #[msg("Market has already been resolved.")]
+    MarketAlreadyResolved,
+    #[msg("This bet belongs to another user.")]
+    BetBelongsToAnotherUser,
+    #[msg("Winnings for this bet have already been claimed.")]
+    WinningsAlreadyClaimed,
+    #[msg("Platform fee has not been claimed yet; market resolution might be incomplete.")]
+    PlatformFeeNotClaimed,
+    #[msg("The UserState account provided does not match the one associated with the market or user.")]
+    UserStateMismatch,
+    #[msg("The user being bet on has not started their course yet.")]
+    UserCourseNotStarted,
+    #[msg("The betting window duration extends beyond or too close to the task deadline.")]
+    BettingWindowTooLong,
+    #[msg("This market belongs to another user.")]
+    MarketBelongsToAnotherUser,
+    #[msg("Course is not active. Either it hasn't started or has already ended.")]
+    CourseNotActive,
+
+    // Miss Penalty Errors
+    #[msg("The current task window has not elapsed yet; no miss to record.")]
+    MissWindowNotElapsed,
+    #[msg("A missed task window must be recorded with record_miss before recording a new task.")]
+    UnrecordedMiss,
+    #[msg("The top-up window for this miss is still open; the hard deduction cannot be applied yet.")]
+    TopUpWindowStillOpen,
+    #[msg("The top-up window for this miss has expired.")]
+    TopUpWindowExpired,
+    #[msg("Top-ups are not available from the 4th miss onwards.")]
+    TopUpNotAvailable,
+
+    // Vault Accounting Errors
+    #[msg("Payout would exceed the yield reserve plus the user's own principal.")]
+    VaultUndercollateralized,
+
+    // Yield Strategy Errors
+    #[msg("Only the config admin can move vault funds.")]
+    UnauthorizedStrategyAuthority,
+    #[msg("Amount is zero or exceeds the strategy's deployed principal.")]
+    InvalidStrategyAmount,
+    #[msg("The lending program did not return the requested amount.")]
+    StrategyTransferMismatch,
+
+    // Yield Config Errors
+    #[msg("Only the config admin can perform this action.")]
+    UnauthorizedConfigAuthority,
+    #[msg("Yield parameters are invalid (tiers must ascend and APRs stay within the cap).")]
+    InvalidYieldParams,
+    #[msg("Config parameters are invalid (fees above 100% or betting window not shorter than the task cycle).")]
+    InvalidConfigParams,
+    #[msg("Only the program upgrade authority can initialize the config.")]
+    NotUpgradeAuthority,
+    #[msg("The config admin cannot be transferred to the default public key.")]
+    InvalidConfigAdmin,
+    #[msg("Only the proposed config admin can accept the transfer.")]
+    NotPendingConfigAdmin,
+
+    // Mint Validation Errors
+    #[msg("The provided mint is not the accepted USDC mint.")]
+    InvalidUsdcMint,
+    #[msg("Token account mint does not match the accepted USDC mint.")]
+    TokenAccountMintMismatch,
+    #[msg("The accepted USDC mint cannot be changed after the config is initialized.")]
+    UsdcMintImmutable,
+    #[msg("The provided treasury account does not match the configured treasury wallet.")]
+    InvalidTreasuryAccount,
+
+    // Market Cancellation Errors
+    #[msg("Market has been cancelled or is refunding bets; use claim_refund.")]
+    MarketCancelled,
+    #[msg("Market is not cancelled or refunding; refunds are only available for those markets.")]
+    MarketNotCancelled,
+    #[msg("Market escrow still holds funds and cannot be closed.")]
+    EscrowNotEmpty,
+
+    // Per-Cycle Market Errors
+    #[msg("The task cycle for this market has already ended.")]
+    CycleAlreadyEnded,
+    #[msg("The task cycle ends after the subject's lock-in period.")]
+    CycleOutsideCourse,
+    #[msg("Markets can only be rolled over to the upcoming task cycle.")]
+    NotUpcomingCycle,
+    #[msg("Invalid streak market terms: horizon must be 1-64 cycles and the threshold within the horizon.")]
+    InvalidStreakMarketParams,
+    #[msg("Market fee or rebate is outside the bounds set in the program config.")]
+    InvalidMarketFeeParams,
+    #[msg("Fee rebate recipient does not match the market.")]
+    InvalidFeeRebateRecipient,
+
+    // Market Finalization Errors
+    #[msg("Market has not been resolved or cancelled yet.")]
+    MarketNotSettled,
+    #[msg("Some bets are still unclaimed and the claim period has not ended.")]
+    ClaimsOutstanding,
+    #[msg("settle_bets expects BetState, bettor, bettor USDC account and bettor LONG/SHORT ATAs for each bet of this market.")]
+    InvalidSettleAccounts,
+    #[msg("Bet state belongs to an earlier market at this address.")]
+    StaleBetState,
+
+    // Dispute Errors
+    #[msg("Market outcome is provisional until the dispute window closes.")]
+    DisputeWindowOpen,
+    #[msg("The dispute window for this market has closed.")]
+    DisputeWindowClosed,
+    #[msg("A dispute is open on this market; the resolver must rule first.")]
+    DisputePending,
+    #[msg("Market is not provisionally resolved.")]
+    MarketNotProvisionallyResolved,
+    #[msg("Only the resolver authority can rule on market outcomes.")]
+    NotResolverAuthority,
+    #[msg("Disputer token account is missing or does not belong to the disputer.")]
+    InvalidDisputerAccount,
+    #[msg("The resolver's deadline to rule on this dispute has passed.")]
+    RulingDeadlinePassed,
+
+    // Anti-Manipulation Errors
+    #[msg("Declared affiliates of the subject cannot bet short.")]
+    AffiliateCannotShort,
+    #[msg("The subject and their declared affiliates cannot redeem SHORT tokens.")]
+    InsiderCannotRedeemShort,
+    #[msg("Bet would grow the bettor's position beyond its cap relative to the subject's locked deposit.")]
+    BettorPositionTooLarge,
+    #[msg("Bet would grow the market's pool beyond its cap relative to the subject's locked deposit.")]
+    MarketPoolCapExceeded,
+    #[msg("Short position exceeds the cap relative to the subject's locked deposit.")]
+    ShortPositionTooLarge,
+    #[msg("Affiliate list is full.")]
+    TooManyAffiliates,
+
+    // AMM Market Errors
+    #[msg("Instruction is not available for this market's mode.")]
+    WrongMarketMode,
+    #[msg("AMM markets need a non-zero liquidity amount.")]
+    ZeroLiquidityAmount,
+    #[msg("Trade would return less than the requested minimum.")]
+    SlippageExceeded,
+    #[msg("Not enough outcome shares.")]
+    InsufficientShares,
+    #[msg("Only the market creator can redeem the AMM liquidity.")]
+    NotMarketCreator,
+    #[msg("AMM liquidity has already been redeemed.")]
+    LiquidityAlreadyRedeemed,
+
+    // Outcome Token Errors
+    #[msg("Outcome token mint does not belong to this market.")]
+    InvalidOutcomeMint,
+    #[msg("No outcome tokens or unsettled bet to claim.")]
+    NothingToClaim,
+}
+
+#[derive(Accounts)]
+pub struct Withdraw<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [USER_SEED, user.key().as_ref()],
+        bump,
+        constraint = user_state.user == user.key(),
+    )]
+    pub user_state: Account<'info, UserState>,
+
+    #[account(
+        mut,
+        seeds = [VAULT_STATE_SEED],
+        bump = vault_state.bump
+    )]
+    pub vault_state: Account<'info, VaultState>,
+
+    #[account(
+        seeds = [YIELD_CONFIG_SEED],
+        bump = yield_config.bump
+    )]
+    pub yield_config: Account<'info, YieldConfig>,
+}
+
+#[derive(Accounts)]
+pub struct EarlyWithdraw<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [USER_SEED, user.key().as_ref()],
+        bump,
+        constraint = user_state.user == user.key(),
+    )]
+    pub user_state: Account<'info, UserState>,
+
+    #[account(
+        mut,
+        constraint = user_token_account.mint == usdc_mint.key() @ ErrorCode::TokenAccountMintMismatch,
+        constraint = user_token_account.owner == user.key()
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Vault PDA
+    #[account(
+        seeds = [VAULT_SEED],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = vault,
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [VAULT_STATE_SEED],
+        bump = vault_state.bump
+    )]
+    pub vault_state: Account<'info, VaultState>,
+
+    #[account(
+        seeds = [YIELD_CONFIG_SEED],
+        bump = yield_config.bump
+    )]
+    pub yield_config: Account<'info, YieldConfig>,
+
+    // Initialize treasury ATA if it doesn't exist
+    #[account(
+        init_if_needed,
+        payer = user, // User pays for initialization
+        associated_token::mint = usdc_mint,
+        associated_token::authority = treasury_wallet_account, // Use the actual treasury wallet AccountInfo as authority
+    )]
+    pub treasury_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Treasury wallet pubkey, constrained by treasury_wallet()
+    #[account(address = treasury_wallet(&program_config) @ ErrorCode::InvalidTreasuryAccount)]
+    pub treasury_wallet_account: AccountInfo<'info>,
+
+    // The user's market, cancelled if still unresolved. Optional since it may already be closed.
+    #[account(
+        mut,
+        seeds = [
+            MARKET_SEED,
+            user.key().as_ref(),
+            user_state.key().as_ref(),
+            market_state.course_index.to_le_bytes().as_ref(),
+            market_state.cycle_index.to_le_bytes().as_ref(),
+            market_state.terms_seed().as_ref()
+        ],
+        bump,
+        constraint = market_state.user_being_bet_on == user.key()
+    )]
+    pub market_state: Option<Account<'info, MarketState>>,
+
+    #[account(address = program_config.usdc_mint @ ErrorCode::InvalidUsdcMint)]
+    pub usdc_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+// --- Start Course Accounts Struct (Phase 1) ---
+// Defines the accounts needed for the start_course instruction.
+// This now also includes accounts for automatic market creation.
+#[derive(Accounts)]
+#[instruction(lock_in_duration_days: u64, lock_amount: u64)] // Added lock_amount parameter
+pub struct StartCourse<'info> {
+    // The user starting the course.
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+
+    // The user's state account, which will be modified.
+    #[account(
+        mut,
+        seeds = [USER_SEED, user.key().as_ref()],
+        bump,
+        constraint = user_state.user == user.key() // Ensure the state belongs to the signer
+    )]
+    pub user_state: Account<'info, UserState>,
+
+    // --- Accounts for automatic market creation ---
+    #[account(
+        init,
+        payer = user, // User pays for market state creation
+        space = 8 + MarketState::INIT_SPACE,
+        seeds = [
+            MARKET_SEED,
+            user.key().as_ref(),      // user_being_bet_on is the one starting the course (user)
+            user_state.key().as_ref(), // user_state_account_for_bet is their user_state
+            (user_state.course_count + 1).to_le_bytes().as_ref(), // the course being started
+            0u64.to_le_bytes().as_ref(), // the first task cycle
+            market_terms_seed(None, MarketMode::Parimutuel).as_ref() // a single-cycle market
+        ],
+        bump
+    )]
+    pub market_state: Account<'info, MarketState>,
+
+    #[account(
+        init_if_needed,
+        payer = user, // User pays for market escrow vault creation
+        space = 8 + MarketEscrowVault::INIT_SPACE,
+        seeds = [MARKET_ESCROW_VAULT_SEED, market_state.key().as_ref()], // market_state.key() is now available
+        bump
+    )]
+    pub market_escrow_vault: Account<'info, MarketEscrowVault>,
+
+    #[account(
+        init_if_needed,
+        payer = user, // User pays for ATA creation
+        associated_token::mint = usdc_mint,
+        associated_token::authority = market_escrow_vault, // market_escrow_vault PDA is the authority
+    )]
+    pub market_escrow_token_account: Account<'info, TokenAccount>,
+
+    // Outcome token mints (fresh keypairs), the market escrow vault PDA is their mint authority
+    #[account(
+        init,
+        payer = user, // User pays for the outcome mints
+        mint::decimals = usdc_mint.decimals,
+        mint::authority = market_escrow_vault,
+    )]
+    pub long_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init,
+        payer = user,
+        mint::decimals = usdc_mint.decimals,
+        mint::authority = market_escrow_vault,
+    )]
+    pub short_mint: Box<Account<'info, Mint>>,
+
+    // Subject's resolution record, kept by trigger_market_resolution for the markets on them
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + SubjectStats::INIT_SPACE,
+        seeds = [SUBJECT_STATS_SEED, user.key().as_ref()],
+        bump
+    )]
+    pub subject_stats: Box<Account<'info, SubjectStats>>,
+
+    // Common accounts needed for initialization of market-related accounts
+    #[account(mut, address = program_config.usdc_mint @ ErrorCode::InvalidUsdcMint)] // Marked as mut for consistency with CreateMarket, ATA init might require it.
+    pub usdc_mint: Account<'info, Mint>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+// --- Betting System Structs and Enums ---
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace, Default)]
+pub enum MarketStatus {
+    #[default]
+    Open,              // Betting is active
+    AwaitingResolution,// Betting closed, outcome not yet determined by contract
+    ResolvedLongsWin,  // User maintained streak, longs win
+    ResolvedShortsWin, // User broke streak, shorts win
+    Cancelled,         // Voided (e.g. subject exited early), every bet is refunded via claim_refund
+    Refunding,         // Resolved with nobody on the winning side, every bet is refunded via claim_refund
+    ProvisionallyResolved, // Outcome recorded but can be disputed until dispute_ends_timestamp, claims are blocked
+}
+
+impl MarketStatus {
+    // Statuses in which bettors get their stakes back via claim_refund instead of claim_winnings
+    pub fn refunds_stakes(&self) -> bool {
+        *self == MarketStatus::Cancelled || *self == MarketStatus::Refunding
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace, Default)]
+pub enum MarketType {
+    #[default]
+    SingleCycle,       // Does a task land in one task cycle
+    StreakSurvival,    // Does the subject complete streak_threshold consecutive cycles within the horizon
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace, Default)]
+pub enum MarketMode {
+    #[default]
+    Parimutuel,        // Bets share the pool of the losing side (place_bet / claim_winnings)
+    Amm,               // Outcome shares traded against a CPMM pool, winning shares redeem at 1 USDC unit
+}
+
+// Fee terms chosen by a market's creator, within the bounds set in ProgramConfig
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct MarketFeeParams {
+    pub platform_fee_basis_points: u16, // Fee taken from the pool at resolution
+    pub rebate_basis_points: u16,       // Share of that fee paid to the rebate recipient instead of the treasury
+    pub rebate_to_subject: bool,        // Rebate goes to the user being bet on instead of the market creator
+}
+
+// Terms of a multi-day streak market
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub struct StreakMarketParams {
+    pub horizon_cycles: u64,   // Number of task cycles covered, starting at the market's cycle_index
+    pub streak_threshold: u64, // Consecutive completed cycles needed for longs to win (= horizon for "every day")
+}
+
+#[account]
+#[derive(InitSpace, Default)]
+pub struct MarketState {
+    pub market_creator: Pubkey,          // Who initiated this market
+    pub user_being_bet_on: Pubkey,       // The user whose streak is the subject of the bet
+    pub user_state_account_for_bet: Pubkey, // PDA of UserState for user_being_bet_on (to check outcome)
+    pub cycle_index: u64,                // Task cycle (day of the course, from 0) this market is on
+    pub course_start_timestamp: i64,     // deposit_timestamp of the course the cycle belongs to
+    pub course_index: u64,               // Subject's course_count for that course, part of the market's seeds
+    pub market_type: MarketType,
+    pub horizon_cycles: u64,             // Cycles covered from cycle_index (1 for single-cycle markets)
+    pub streak_threshold: u64,           // Consecutive completed cycles needed for longs to win
+    
+    pub total_long_amount: u64,          // Total USDC staked on "long"
+    pub total_short_amount: u64,         // Total USDC staked on "short"
+    
+    pub market_creation_timestamp: i64,  // When this market was created
+    pub betting_ends_timestamp: i64,     // Betting stops
+    pub task_deadline_timestamp: i64,    // Deadline of the last task cycle this market refers to
+    pub resolution_timestamp: i64,       // When outcome can be checked (task_deadline_timestamp + grace period)
+    
+    pub status: MarketStatus,
+    pub platform_fee_basis_points: u16,  // e.g., 500 for 5.00% (500 / 10000)
+    pub platform_fee_claimed: bool,      // Has the platform fee been transferred to treasury?
+    pub fee_rebate_basis_points: u16,    // Share of the platform fee rebated to fee_rebate_recipient
+    pub fee_rebate_recipient: Pubkey,    // Market creator or user_being_bet_on
+
+    pub bet_count: u64,                  // Number of BetState accounts (bettors) on this market
+    pub claimed_bet_count: u64,          // BetStates settled via claim_winnings/claim_refund
+    pub total_claimed_amount: u64,       // USDC paid out of the escrow to bettors
+    pub settled_timestamp: i64,          // When the market was resolved or cancelled (starts the claim period)
+
+    pub affiliate_long_amount: u64,      // LONG tokens issued to the subject's declared affiliates
+    pub forfeited_amount: u64,           // Subject's deposit moved into the escrow for longs (short-heavy miss)
+    pub forfeit_eligible_long_tokens: u64, // LONG tokens outstanding at resolution outside affiliate_long_amount, split forfeited_amount
+    pub forfeit_claimed_amount: u64,     // Part of forfeited_amount paid out so far
+    pub implied_long_probability_basis_points: u16, // Long share of the pool (AMM: long share price), updated on every trade
+
+    pub long_mint: Pubkey,               // LONG outcome token, minted 1:1 per USDC bet (AMM: per share)
+    pub short_mint: Pubkey,              // SHORT outcome token
+
+    pub market_mode: MarketMode,
+    pub amm_long_reserve: u64,           // LONG shares held by the AMM pool
+    pub amm_short_reserve: u64,          // SHORT shares held by the AMM pool
+    pub amm_fee_amount: u64,             // Trading fees collected in the escrow, sent to the treasury at resolution
+    pub amm_liquidity_redeemed: bool,    // Has the creator redeemed the pool's shares?
+    pub amm_liquidity_amount: u64,       // USDC the creator seeded the pool with, returned if cancelled
+    pub amm_stake_amount: u64,           // Traders' USDC stakes net of sales, refunded from their bet states if cancelled
+    pub amm_sold_beyond_stakes_amount: u64, // USDC paid for shares sold beyond the seller's stake, comes out of the liquidity if cancelled
+
+    pub provisional_longs_win: bool,     // Outcome while ProvisionallyResolved (after any resolver ruling)
+    pub dispute_ends_timestamp: i64,     // End of the dispute window, the next trigger call finalizes the outcome
+    pub disputer: Pubkey,                // Bettor with an open dispute (default if none)
+    pub dispute_bond_amount: u64,        // Bond held in the escrow for the open dispute
+    pub ruling_deadline_timestamp: i64,  // The resolver must rule on the open dispute before this, else the provisional outcome stands
+
+    pub bump: u8,
+}
+
+impl MarketState {
+    // Market terms part of the market's PDA seeds, see market_terms_seed
+    pub fn terms_seed(&self) -> [u8; 18] {
+        let streak_params = (self.market_type == MarketType::StreakSurvival).then_some(StreakMarketParams {
+            horizon_cycles: self.horizon_cycles,
+            streak_threshold: self.streak_threshold,
+        });
+        market_terms_seed(streak_params, self.market_mode.clone())
+    }
+}
 
 #[account]
 #[derive(InitSpace)]
-pub struct TreasuryState {
-    pub authority: Pubkey,  // Authority that can withdraw from treasury
-    pub total_fees_collected: u64,  // Track total fees collected
+pub struct BetState {
+    pub market: Pubkey,             // PDA of the MarketState this bet belongs to
+    pub market_creation_timestamp: i64, // market_creation_timestamp of that market, tells apart a market re-created at the same PDA
+    pub bettor: Pubkey,             // User who placed the bet
+    pub long_amount: u64,           // Total USDC bet on "long" (AMM: paid for LONG shares, net of sales)
+    pub short_amount: u64,          // Total USDC bet on "short" (a bettor may hedge on both sides)
+    pub winnings_claimed: bool,     // True once settled via claim_winnings/claim_refund (payouts follow the outcome tokens)
+    pub is_affiliate: bool,         // Bettor is a declared affiliate of the subject
+    pub bump: u8,
+}
+
+// Per-wallet betting record for leaderboards. Positions are only counted as won or lost once
+// they are settled (claim_winnings or settle_bets). Losing positions have nothing to claim, so
+// losses of bettors who neither claim nor delegate to settle_bets go unrecorded and their
+// win_rate_basis_points overstates their record; leaderboards should weigh it by markets_entered.
+#[account]
+#[derive(InitSpace, Default)]
+pub struct BettorStats {
+    pub bettor: Pubkey,
+    pub total_staked: u64,          // USDC bet or spent buying AMM shares (open positions included)
+    pub total_won: u64,             // USDC paid out by resolved markets, forfeited deposit shares included
+    pub total_returned: u64,        // USDC refunded or received from AMM share sales
+    pub markets_entered: u64,       // Markets the bettor placed a bet or trade in
+    pub markets_won: u64,           // Settled positions on resolved markets that came out ahead, see bet_position_won
+    pub markets_lost: u64,          // Settled positions on resolved markets that didn't (unsettled losses are missing)
+    pub win_rate_basis_points: u16, // markets_won / (markets_won + markets_lost), an upper bound, see above
+    pub net_pnl: i64,               // total_won + total_returned - total_staked
+    pub bump: u8,
+}
+
+// Per-subject record of how markets on them resolved
+#[account]
+#[derive(InitSpace, Default)]
+pub struct SubjectStats {
+    pub user: Pubkey,
+    pub markets_resolved_long: u64,   // Markets where the subject completed their task
+    pub markets_resolved_short: u64,  // Markets where the subject missed
+    pub markets_refunded: u64,        // Markets resolved with nobody on the winning side
+    pub long_rate_basis_points: u16,  // markets_resolved_long / (markets_resolved_long + markets_resolved_short)
+    pub bump: u8,
+}
+
+// Return data of quote_bet
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct BetQuote {
+    pub payout_amount: u64,                         // Total payout (stake included) if the side wins
+    pub payout_multiplier_basis_points: u64,        // payout_amount / amount, e.g. 18000 = 1.8x
+    pub implied_long_probability_basis_points: u16, // Long share of the pool after the bet
+}
+
+// Wallets a subject declares as their own, see declare_affiliates
+#[account]
+#[derive(InitSpace)]
+pub struct AffiliateList {
+    pub user: Pubkey,
+    #[max_len(MAX_AFFILIATES)]
+    pub affiliates: Vec<Pubkey>,
+    pub bump: u8,
+}
+
+// PDA to act as authority for the market's escrow token account
+#[account]
+#[derive(InitSpace)]
+pub struct MarketEscrowVault {
+    pub market: Pubkey, // Links vault to the MarketState PDA
+    pub bump: u8,
+}
+
+
+// --- Betting System Account Contexts ---
+
+#[derive(Accounts)]
+#[instruction(cycle_index: u64, betting_window_duration_seconds: u64)]
+pub struct CreateMarket<'info> {
+    #[account(mut)]
+    pub market_creator: Signer<'info>,
+
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+
+    /// CHECK: No sensitive ops, just storing its key and using for PDA derivation if needed.
+    pub user_being_bet_on: AccountInfo<'info>, 
+    
+    pub user_state_for_bet: Account<'info, UserState>,
+
+    #[account(
+        init,
+        payer = market_creator,
+        space = 8 + MarketState::INIT_SPACE,
+        seeds = [
+            MARKET_SEED, 
+            user_being_bet_on.key().as_ref(), 
+            user_state_for_bet.key().as_ref(), // Use UserState PDA key as part of the seed
+            user_state_for_bet.course_count.to_le_bytes().as_ref(),
+            cycle_index.to_le_bytes().as_ref(),
+            market_terms_seed(None, MarketMode::Parimutuel).as_ref()
+        ],
+        bump
+    )]
+    pub market_state: Account<'info, MarketState>,
+
+    #[account(
+        init,
+        payer = market_creator,
+        space = 8 + MarketEscrowVault::INIT_SPACE,
+        seeds = [MARKET_ESCROW_VAULT_SEED, market_state.key().as_ref()],
+        bump
+    )]
+    pub market_escrow_vault: Account<'info, MarketEscrowVault>,
+
+    #[account(
+        init,
+        payer = market_creator,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = market_escrow_vault, // market_escrow_vault PDA is the authority for this ATA
+    )]
+    pub market_escrow_token_account: Account<'info, TokenAccount>,
+
+    // Outcome token mints (fresh keypairs), the market escrow vault PDA is their mint authority
+    #[account(
+        init,
+        payer = market_creator,
+        mint::decimals = usdc_mint.decimals,
+        mint::authority = market_escrow_vault,
+    )]
+    pub long_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init,
+        payer = market_creator,
+        mint::decimals = usdc_mint.decimals,
+        mint::authority = market_escrow_vault,
+    )]
+    pub short_mint: Box<Account<'info, Mint>>,
+    
+    #[account(mut, address = program_config.usdc_mint @ ErrorCode::InvalidUsdcMint)] // For ATA initialization potentially
+    pub usdc_mint: Account<'info, Mint>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+// CreateStreakMarket context - CreateMarket with the streak terms in the market's seeds
+#[derive(Accounts)]
+#[instruction(cycle_index: u64, betting_window_duration_seconds: u64, params: StreakMarketParams)]
+pub struct CreateStreakMarket<'info> {
+    #[account(mut)]
+    pub market_creator: Signer<'info>,
+
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+
+    /// CHECK: No sensitive ops, just storing its key and using for PDA derivation if needed.
+    pub user_being_bet_on: AccountInfo<'info>,
+
+    pub user_state_for_bet: Account<'info, UserState>,
+
+    #[account(
+        init,
+        payer = market_creator,
+        space = 8 + MarketState::INIT_SPACE,
+        seeds = [
+            MARKET_SEED,
+            user_being_bet_on.key().as_ref(),
+            user_state_for_bet.key().as_ref(), // Use UserState PDA key as part of the seed
+            user_state_for_bet.course_count.to_le_bytes().as_ref(),
+            cycle_index.to_le_bytes().as_ref(),
+            market_terms_seed(Some(params), MarketMode::Parimutuel).as_ref()
+        ],
+        bump
+    )]
+    pub market_state: Account<'info, MarketState>,
+
+    #[account(
+        init,
+        payer = market_creator,
+        space = 8 + MarketEscrowVault::INIT_SPACE,
+        seeds = [MARKET_ESCROW_VAULT_SEED, market_state.key().as_ref()],
+        bump
+    )]
+    pub market_escrow_vault: Account<'info, MarketEscrowVault>,
+
+    #[account(
+        init,
+        payer = market_creator,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = market_escrow_vault, // market_escrow_vault PDA is the authority for this ATA
+    )]
+    pub market_escrow_token_account: Account<'info, TokenAccount>,
+
+    // Outcome token mints (fresh keypairs), the market escrow vault PDA is their mint authority
+    #[account(
+        init,
+        payer = market_creator,
+        mint::decimals = usdc_mint.decimals,
+        mint::authority = market_escrow_vault,
+    )]
+    pub long_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init,
+        payer = market_creator,
+        mint::decimals = usdc_mint.decimals,
+        mint::authority = market_escrow_vault,
+    )]
+    pub short_mint: Box<Account<'info, Mint>>,
+
+    #[account(mut, address = program_config.usdc_mint @ ErrorCode::InvalidUsdcMint)] // For ATA initialization potentially
+    pub usdc_mint: Account<'info, Mint>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+// CreateAmmMarket context - CreateMarket plus the creator's USDC account funding the pool
+#[derive(Accounts)]
+#[instruction(
+    cycle_index: u64,
+    betting_window_duration_seconds: u64,
+    liquidity_amount: u64,
+    streak_params: Option<StreakMarketParams>
+)]
+pub struct CreateAmmMarket<'info> {
+    #[account(mut)]
+    pub market_creator: Signer<'info>,
+
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+
+    /// CHECK: No sensitive ops, just storing its key and using for PDA derivation.
+    pub user_being_bet_on: AccountInfo<'info>,
+
+    pub user_state_for_bet: Account<'info, UserState>,
+
+    #[account(
+        init,
+        payer = market_creator,
+        space = 8 + MarketState::INIT_SPACE,
+        seeds = [
+            MARKET_SEED,
+            user_being_bet_on.key().as_ref(),
+            user_state_for_bet.key().as_ref(),
+            user_state_for_bet.course_count.to_le_bytes().as_ref(),
+            cycle_index.to_le_bytes().as_ref(),
+            market_terms_seed(streak_params, MarketMode::Amm).as_ref()
+        ],
+        bump
+    )]
+    pub market_state: Account<'info, MarketState>,
+
+    #[account(
+        init,
+        payer = market_creator,
+        space = 8 + MarketEscrowVault::INIT_SPACE,
+        seeds = [MARKET_ESCROW_VAULT_SEED, market_state.key().as_ref()],
+        bump
+    )]
+    pub market_escrow_vault: Account<'info, MarketEscrowVault>,
+
+    #[account(
+        init,
+        payer = market_creator,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = market_escrow_vault,
+    )]
+    pub market_escrow_token_account: Account<'info, TokenAccount>,
+
+    // Outcome token mints (fresh keypairs), the market escrow vault PDA is their mint authority
+    #[account(
+        init,
+        payer = market_creator,
+        mint::decimals = usdc_mint.decimals,
+        mint::authority = market_escrow_vault,
+    )]
+    pub long_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init,
+        payer = market_creator,
+        mint::decimals = usdc_mint.decimals,
+        mint::authority = market_escrow_vault,
+    )]
+    pub short_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut, // Creator's USDC account, funds the pool liquidity
+        constraint = creator_token_account.mint == usdc_mint.key() @ ErrorCode::TokenAccountMintMismatch,
+        constraint = creator_token_account.owner == market_creator.key()
+    )]
+    pub creator_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut, address = program_config.usdc_mint @ ErrorCode::InvalidUsdcMint)]
+    pub usdc_mint: Account<'info, Mint>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+// RollOverMarket context - permissionless, caller pays for the new market accounts
+#[derive(Accounts)]
+#[instruction(cycle_index: u64)]
+pub struct RollOverMarket<'info> {
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+
+    #[account(
+        seeds = [USER_SEED, user_state_for_bet.user.as_ref()],
+        bump
+    )]
+    pub user_state_for_bet: Account<'info, UserState>,
+
+    #[account(
+        init,
+        payer = caller,
+        space = 8 + MarketState::INIT_SPACE,
+        seeds = [
+            MARKET_SEED,
+            user_state_for_bet.user.as_ref(),
+            user_state_for_bet.key().as_ref(),
+            user_state_for_bet.course_count.to_le_bytes().as_ref(),
+            cycle_index.to_le_bytes().as_ref(),
+            market_terms_seed(None, MarketMode::Parimutuel).as_ref()
+        ],
+        bump
+    )]
+    pub market_state: Account<'info, MarketState>,
+
+    #[account(
+        init,
+        payer = caller,
+        space = 8 + MarketEscrowVault::INIT_SPACE,
+        seeds = [MARKET_ESCROW_VAULT_SEED, market_state.key().as_ref()],
+        bump
+    )]
+    pub market_escrow_vault: Account<'info, MarketEscrowVault>,
+
+    #[account(
+        init,
+        payer = caller,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = market_escrow_vault,
+    )]
+    pub market_escrow_token_account: Account<'info, TokenAccount>,
+
+    // Outcome token mints (fresh keypairs), the market escrow vault PDA is their mint authority
+    #[account(
+        init,
+        payer = caller,
+        mint::decimals = usdc_mint.decimals,
+        mint::authority = market_escrow_vault,
+    )]
+    pub long_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init,
+        payer = caller,
+        mint::decimals = usdc_mint.decimals,
+        mint::authority = market_escrow_vault,
+    )]
+    pub short_mint: Box<Account<'info, Mint>>,
+
+    #[account(address = program_config.usdc_mint @ ErrorCode::InvalidUsdcMint)]
+    pub usdc_mint: Account<'info, Mint>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+#[instruction(amount: u64, position_is_long: bool)]
+pub struct PlaceBet<'info> {
+    #[account(mut)]
+    pub bettor: Signer<'info>,
+
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        // Validate market_state PDA using its own stored fields that were used as seeds
+        seeds = [
+            MARKET_SEED, 
+            market_state.user_being_bet_on.as_ref(),
+            market_state.user_state_account_for_bet.as_ref(),
+            market_state.course_index.to_le_bytes().as_ref(),
+            market_state.cycle_index.to_le_bytes().as_ref(),
+            market_state.terms_seed().as_ref()
+        ],
+        bump = market_state.bump
+    )]
+    pub market_state: Account<'info, MarketState>,
+
+    // Subject's state, for the locked deposit that caps short positions
+    #[account(address = market_state.user_state_account_for_bet @ ErrorCode::UserStateMismatch)]
+    pub user_state_for_bet: Account<'info, UserState>,
+
+    /// CHECK: Subject's AffiliateList PDA, may not exist; deserialized by load_affiliate_list
+    #[account(
+        seeds = [AFFILIATES_SEED, market_state.user_being_bet_on.as_ref()],
+        bump
+    )]
+    pub subject_affiliates: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [MARKET_ESCROW_VAULT_SEED, market_state.key().as_ref()],
+        bump = market_escrow_vault.bump, // market_escrow_vault stores its own bump
+    )]
+    pub market_escrow_vault: Account<'info, MarketEscrowVault>,
+
+    #[account(
+        mut,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = market_escrow_vault,
+    )]
+    pub market_escrow_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut, // Bettor's USDC account
+        constraint = bettor_token_account.mint == usdc_mint.key() @ ErrorCode::TokenAccountMintMismatch,
+        constraint = bettor_token_account.owner == bettor.key()
+    )]
+    pub bettor_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed, // Repeat bets add to the bettor's existing position
+        payer = bettor,
+        space = 8 + BetState::INIT_SPACE,
+        seeds = [BET_SEED, market_state.key().as_ref(), bettor.key().as_ref()],
+        bump
+    )]
+    pub bet_state: Account<'info, BetState>,
+
+    // Outcome token mint of the side being bought
+    #[account(
+        mut,
+        address = outcome_mint(&market_state, position_is_long) @ ErrorCode::InvalidOutcomeMint
+    )]
+    pub position_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init_if_needed,
+        payer = bettor,
+        associated_token::mint = position_mint,
+        associated_token::authority = bettor,
+    )]
+    pub bettor_position_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = bettor,
+        space = 8 + BettorStats::INIT_SPACE,
+        seeds = [BETTOR_STATS_SEED, bettor.key().as_ref()],
+        bump
+    )]
+    pub bettor_stats: Box<Account<'info, BettorStats>>,
+    
+    #[account(mut, address = program_config.usdc_mint @ ErrorCode::InvalidUsdcMint)]
+    pub usdc_mint: Account<'info, Mint>, // Though mint itself isn't mutated, ATA init might need it.
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>, // For the bettor's outcome token account
+}
+
+#[derive(Accounts)]
+#[instruction(shares: u64, position_is_long: bool)]
+pub struct SellShares<'info> {
+    #[account(mut)]
+    pub bettor: Signer<'info>,
+
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        // Validate market_state PDA using its own stored fields that were used as seeds
+        seeds = [
+            MARKET_SEED, 
+            market_state.user_being_bet_on.as_ref(),
+            market_state.user_state_account_for_bet.as_ref(),
+            market_state.course_index.to_le_bytes().as_ref(),
+            market_state.cycle_index.to_le_bytes().as_ref(),
+            market_state.terms_seed().as_ref()
+        ],
+        bump = market_state.bump
+    )]
+    pub market_state: Account<'info, MarketState>,
+
+    /// CHECK: Subject's AffiliateList PDA, may not exist; deserialized by is_subject_or_affiliate
+    #[account(
+        seeds = [AFFILIATES_SEED, market_state.user_being_bet_on.as_ref()],
+        bump
+    )]
+    pub subject_affiliates: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [MARKET_ESCROW_VAULT_SEED, market_state.key().as_ref()],
+        bump = market_escrow_vault.bump, // market_escrow_vault stores its own bump
+    )]
+    pub market_escrow_vault: Account<'info, MarketEscrowVault>,
+
+    #[account(
+        mut,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = market_escrow_vault,
+    )]
+    pub market_escrow_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut, // Bettor's USDC account
+        constraint = bettor_token_account.mint == usdc_mint.key() @ ErrorCode::TokenAccountMintMismatch,
+        constraint = bettor_token_account.owner == bettor.key()
+    )]
+    pub bettor_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed, // Sales reduce the seller's stakes, shares bought by another wallet start one
+        payer = bettor,
+        space = 8 + BetState::INIT_SPACE,
+        seeds = [BET_SEED, market_state.key().as_ref(), bettor.key().as_ref()],
+        bump
+    )]
+    pub bet_state: Account<'info, BetState>,
+
+    // Outcome token mint of the side being sold
+    #[account(
+        mut,
+        address = outcome_mint(&market_state, position_is_long) @ ErrorCode::InvalidOutcomeMint
+    )]
+    pub position_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        associated_token::mint = position_mint,
+        associated_token::authority = bettor,
+    )]
+    pub bettor_position_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = bettor,
+        space = 8 + BettorStats::INIT_SPACE,
+        seeds = [BETTOR_STATS_SEED, bettor.key().as_ref()],
+        bump
+    )]
+    pub bettor_stats: Box<Account<'info, BettorStats>>,
+    
+    #[account(address = program_config.usdc_mint @ ErrorCode::InvalidUsdcMint)]
+    pub usdc_mint: Account<'info, Mint>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct QuoteBet<'info> {
+    #[account(
+        seeds = [
+            MARKET_SEED,
+            market_state.user_being_bet_on.as_ref(),
+            market_state.user_state_account_for_bet.as_ref(),
+            market_state.course_index.to_le_bytes().as_ref(),
+            market_state.cycle_index.to_le_bytes().as_ref(),
+            market_state.terms_seed().as_ref()
+        ],
+        bump = market_state.bump
+    )]
+    pub market_state: Account<'info, MarketState>,
+}
+
+#[derive(Accounts)]
+pub struct DeclareAffiliates<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + AffiliateList::INIT_SPACE,
+        seeds = [AFFILIATES_SEED, user.key().as_ref()],
+        bump
+    )]
+    pub affiliate_list: Account<'info, AffiliateList>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct TriggerMarketResolution<'info> {
+    #[account(mut)] // Pays for the fee recipients' token accounts when a fee is paid out
+    pub caller: Signer<'info>,
+
+    #[account(
+        mut, // Receives the resolution bounty
+        constraint = caller_token_account.mint == usdc_mint.key() @ ErrorCode::TokenAccountMintMismatch,
+        constraint = caller_token_account.owner == caller.key()
+    )]
+    pub caller_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            MARKET_SEED,
+            market_state.user_being_bet_on.as_ref(),
+            market_state.user_state_account_for_bet.as_ref(),
+            market_state.course_index.to_le_bytes().as_ref(),
+            market_state.cycle_index.to_le_bytes().as_ref(),
+            market_state.terms_seed().as_ref()
+        ],
+        bump = market_state.bump
+    )]
+    pub market_state: Account<'info, MarketState>,
+
+    // UserState account of the user whose streak was bet on.
+    // Used to determine the outcome.
+    #[account(
+        mut, // Locked deposit may be forfeited to longs
+        // Constraint: user_state_for_bet.key() == market_state.user_state_account_for_bet (checked in logic)
+        // Constraint: user_state_for_bet.user == market_state.user_being_bet_on (checked in logic)
+    )]
+    pub user_state_for_bet: Account<'info, UserState>,
+
+    /// CHECK: Vault PDA
+    #[account(
+        seeds = [VAULT_SEED],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = vault,
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [VAULT_STATE_SEED],
+        bump = vault_state.bump
+    )]
+    pub vault_state: Account<'info, VaultState>,
+
+    #[account(
+        seeds = [YIELD_CONFIG_SEED],
+        bump = yield_config.bump
+    )]
+    pub yield_config: Account<'info, YieldConfig>,
+
+    #[account(
+        mut, // For transferring fee out
+        seeds = [MARKET_ESCROW_VAULT_SEED, market_state.key().as_ref()],
+        bump = market_escrow_vault.bump,
+    )]
+    pub market_escrow_vault: Account<'info, MarketEscrowVault>,
+
+    #[account(
+        mut,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = market_escrow_vault,
+    )]
+    pub market_escrow_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Treasury's USDC ATA to receive platform fees, created by create_fee_token_account
+    #[account(
+        mut,
+        address = get_associated_token_address(&treasury_wallet_account.key(), &usdc_mint.key()) @ ErrorCode::InvalidTreasuryAccount
+    )]
+    pub treasury_token_account: UncheckedAccount<'info>,
+    
+    /// CHECK: Treasury wallet pubkey, constrained by treasury_wallet()
+    #[account(address = treasury_wallet(&program_config) @ ErrorCode::InvalidTreasuryAccount)]
+    pub treasury_wallet_account: AccountInfo<'info>,
+
+    /// CHECK: USDC ATA of the market's fee rebate recipient (creator or subject), created by
+    /// create_fee_token_account
+    #[account(
+        mut,
+        address = get_associated_token_address(&fee_rebate_recipient.key(), &usdc_mint.key()) @ ErrorCode::InvalidFeeRebateRecipient
+    )]
+    pub fee_rebate_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Fee rebate recipient, constrained to the one stored in the market
+    #[account(address = market_state.fee_rebate_recipient @ ErrorCode::InvalidFeeRebateRecipient)]
+    pub fee_rebate_recipient: AccountInfo<'info>,
+
+    // Subject's resolution record, created when they start their first course
+    #[account(
+        mut,
+        seeds = [SUBJECT_STATS_SEED, market_state.user_being_bet_on.as_ref()],
+        bump = subject_stats.bump
+    )]
+    pub subject_stats: Box<Account<'info, SubjectStats>>,
+
+    // Receives the bond back if the resolver misses its deadline on an open dispute
+    #[account(
+        mut,
+        constraint = disputer_token_account.mint == usdc_mint.key() @ ErrorCode::TokenAccountMintMismatch,
+        constraint = disputer_token_account.owner == market_state.disputer @ ErrorCode::InvalidDisputerAccount
+    )]
+    pub disputer_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    // Outcome token mints, their supplies are the positions the forfeiture check looks at
+    #[account(address = market_state.long_mint @ ErrorCode::InvalidOutcomeMint)]
+    pub long_mint: Box<Account<'info, Mint>>,
+
+    #[account(address = market_state.short_mint @ ErrorCode::InvalidOutcomeMint)]
+    pub short_mint: Box<Account<'info, Mint>>,
+
+    #[account(mut, address = program_config.usdc_mint @ ErrorCode::InvalidUsdcMint)]
+    pub usdc_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>, // For create_fee_token_account
+    pub associated_token_program: Program<'info, AssociatedToken>, // For create_fee_token_account
+}
+
+#[derive(Accounts)]
+pub struct ClaimWinnings<'info> {
+    #[account(mut)]
+    pub bettor: Signer<'info>,
+
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut, // Tallies claims for finalize_market
+        seeds = [
+            MARKET_SEED,
+            market_state.user_being_bet_on.as_ref(),
+            market_state.user_state_account_for_bet.as_ref(),
+            market_state.course_index.to_le_bytes().as_ref(),
+            market_state.cycle_index.to_le_bytes().as_ref(),
+            market_state.terms_seed().as_ref()
+        ],
+        bump = market_state.bump
+    )]
+    pub market_state: Account<'info, MarketState>,
+
+    // The bettor's own position, optional: outcome tokens bought elsewhere redeem without one
+    #[account(
+        mut,
+        seeds = [BET_SEED, market_state.key().as_ref(), bettor.key().as_ref()],
+        bump = bet_state.bump, // bet_state stores its own bump
+        constraint = bet_state.bettor == bettor.key(), // Ensure bettor owns this bet_state
+        constraint = bet_state.market == market_state.key(), // Ensure bet_state is for this market
+        constraint = bet_state.market_creation_timestamp == market_state.market_creation_timestamp @ ErrorCode::StaleBetState,
+    )]
+    pub bet_state: Option<Account<'info, BetState>>,
+
+    #[account(mut, address = market_state.long_mint @ ErrorCode::InvalidOutcomeMint)]
+    pub long_mint: Box<Account<'info, Mint>>,
+
+    #[account(mut, address = market_state.short_mint @ ErrorCode::InvalidOutcomeMint)]
+    pub short_mint: Box<Account<'info, Mint>>,
+
+    // Outcome token accounts to burn from, pass the ones the bettor holds
+    #[account(mut, token::mint = long_mint, token::authority = bettor)]
+    pub bettor_long_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    #[account(mut, token::mint = short_mint, token::authority = bettor)]
+    pub bettor_short_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// CHECK: Subject's AffiliateList PDA, may not exist; deserialized by load_affiliate_list
+    #[account(
+        seeds = [AFFILIATES_SEED, market_state.user_being_bet_on.as_ref()],
+        bump
+    )]
+    pub subject_affiliates: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [MARKET_ESCROW_VAULT_SEED, market_state.key().as_ref()],
+        bump = market_escrow_vault.bump,
+    )]
+    pub market_escrow_vault: Account<'info, MarketEscrowVault>,
+
+    #[account(
+        mut,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = market_escrow_vault,
+    )]
+    pub market_escrow_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut, // To receive winnings
+        constraint = bettor_token_account.mint == usdc_mint.key() @ ErrorCode::TokenAccountMintMismatch,
+        constraint = bettor_token_account.owner == bettor.key()
+    )]
+    pub bettor_token_account: Account<'info, TokenAccount>,
+
+    // Created here for holders of outcome tokens bought from other wallets
+    #[account(
+        init_if_needed,
+        payer = bettor,
+        space = 8 + BettorStats::INIT_SPACE,
+        seeds = [BETTOR_STATS_SEED, bettor.key().as_ref()],
+        bump
+    )]
+    pub bettor_stats: Box<Account<'info, BettorStats>>,
+    
+    #[account(mut, address = program_config.usdc_mint @ ErrorCode::InvalidUsdcMint)]
+    pub usdc_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DisputeOutcome<'info> {
+    pub disputer: Signer<'info>,
+
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            MARKET_SEED,
+            market_state.user_being_bet_on.as_ref(),
+            market_state.user_state_account_for_bet.as_ref(),
+            market_state.course_index.to_le_bytes().as_ref(),
+            market_state.cycle_index.to_le_bytes().as_ref(),
+            market_state.terms_seed().as_ref()
+        ],
+        bump = market_state.bump
+    )]
+    pub market_state: Account<'info, MarketState>,
+
+    // Only bettors of the market can dispute
+    #[account(
+        seeds = [BET_SEED, market_state.key().as_ref(), disputer.key().as_ref()],
+        bump = bet_state.bump,
+        constraint = bet_state.market_creation_timestamp == market_state.market_creation_timestamp @ ErrorCode::StaleBetState,
+    )]
+    pub bet_state: Account<'info, BetState>,
+
+    #[account(
+        seeds = [MARKET_ESCROW_VAULT_SEED, market_state.key().as_ref()],
+        bump = market_escrow_vault.bump,
+    )]
+    pub market_escrow_vault: Account<'info, MarketEscrowVault>,
+
+    #[account(
+        mut,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = market_escrow_vault,
+    )]
+    pub market_escrow_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut, // Pays the dispute bond
+        constraint = disputer_token_account.mint == usdc_mint.key() @ ErrorCode::TokenAccountMintMismatch,
+        constraint = disputer_token_account.owner == disputer.key()
+    )]
+    pub disputer_token_account: Account<'info, TokenAccount>,
+
+    #[account(address = program_config.usdc_mint @ ErrorCode::InvalidUsdcMint)]
+    pub usdc_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveDispute<'info> {
+    #[account(mut, address = program_config.resolver_authority @ ErrorCode::NotResolverAuthority)]
+    pub resolver: Signer<'info>,
+
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            MARKET_SEED,
+            market_state.user_being_bet_on.as_ref(),
+            market_state.user_state_account_for_bet.as_ref(),
+            market_state.course_index.to_le_bytes().as_ref(),
+            market_state.cycle_index.to_le_bytes().as_ref(),
+            market_state.terms_seed().as_ref()
+        ],
+        bump = market_state.bump
+    )]
+    pub market_state: Account<'info, MarketState>,
+
+    #[account(
+        seeds = [MARKET_ESCROW_VAULT_SEED, market_state.key().as_ref()],
+        bump = market_escrow_vault.bump,
+    )]
+    pub market_escrow_vault: Account<'info, MarketEscrowVault>,
+
+    #[account(
+        mut,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = market_escrow_vault,
+    )]
+    pub market_escrow_token_account: Account<'info, TokenAccount>,
+
+    // Receives the bond back if the outcome is flipped
+    #[account(
+        mut,
+        constraint = disputer_token_account.mint == usdc_mint.key() @ ErrorCode::TokenAccountMintMismatch,
+        constraint = disputer_token_account.owner == market_state.disputer @ ErrorCode::InvalidDisputerAccount
+    )]
+    pub disputer_token_account: Option<Account<'info, TokenAccount>>,
+
+    // Receives the bond if the dispute fails
+    #[account(
+        init_if_needed,
+        payer = resolver,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = treasury_wallet_account,
+    )]
+    pub treasury_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Treasury wallet pubkey, constrained by treasury_wallet()
+    #[account(address = treasury_wallet(&program_config) @ ErrorCode::InvalidTreasuryAccount)]
+    pub treasury_wallet_account: AccountInfo<'info>,
+
+    #[account(address = program_config.usdc_mint @ ErrorCode::InvalidUsdcMint)]
+    pub usdc_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+// SettleBets context - permissionless, the bets to settle are passed as remaining_accounts
+#[derive(Accounts)]
+pub struct SettleBets<'info> {
+    pub caller: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            MARKET_SEED,
+            market_state.user_being_bet_on.as_ref(),
+            market_state.user_state_account_for_bet.as_ref(),
+            market_state.course_index.to_le_bytes().as_ref(),
+            market_state.cycle_index.to_le_bytes().as_ref(),
+            market_state.terms_seed().as_ref()
+        ],
+        bump = market_state.bump
+    )]
+    pub market_state: Account<'info, MarketState>,
+
+    #[account(
+        seeds = [MARKET_ESCROW_VAULT_SEED, market_state.key().as_ref()],
+        bump = market_escrow_vault.bump,
+    )]
+    pub market_escrow_vault: Account<'info, MarketEscrowVault>,
+
+    #[account(
+        mut,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = market_escrow_vault,
+    )]
+    pub market_escrow_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut, address = market_state.long_mint @ ErrorCode::InvalidOutcomeMint)]
+    pub long_mint: Box<Account<'info, Mint>>,
+
+    #[account(mut, address = market_state.short_mint @ ErrorCode::InvalidOutcomeMint)]
+    pub short_mint: Box<Account<'info, Mint>>,
+
+    /// CHECK: Subject's AffiliateList PDA, may not exist; deserialized by load_affiliate_list
+    #[account(
+        seeds = [AFFILIATES_SEED, market_state.user_being_bet_on.as_ref()],
+        bump
+    )]
+    pub subject_affiliates: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+
+    #[account(address = program_config.usdc_mint @ ErrorCode::InvalidUsdcMint)]
+    pub usdc_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
 }
 
-// Optional: Define Custom Errors
-#[error_code]
-pub enum ErrorCode {
-    #[msg("Deposit amount cannot be zero.")]
-    ZeroDepositAmount,
-    #[msg("Lock-in period has not ended yet.")]
-    LockInPeriodNotEnded,
-    #[msg("Lock-in period has already ended. Use normal withdraw.")]
-    LockInPeriodEnded,
-    #[msg("An arithmetic operation failed (overflow/underflow/divide by zero).")]
-    ArithmeticError,
-    #[msg("Cannot start a course that has already begun.")]
-    CourseAlreadyStarted,
-    #[msg("Cannot start a course without an initial deposit.")]
-    NoDepositToStartCourse,
-    #[msg("Lock amount cannot be zero.")]
-    ZeroLockAmount,
-    #[msg("Insufficient balance for requested lock amount.")]
-    InsufficientBalance,
-    #[msg("No unlocked balance available for withdrawal.")]
-    NoUnlockedBalance,
-    // Add other potential errors here
+#[derive(Accounts)]
+pub struct RedeemLiquidity<'info> {
+    #[account(address = market_state.market_creator @ ErrorCode::NotMarketCreator)]
+    pub market_creator: Signer<'info>,
 
-    // Betting System Errors
-    #[msg("Market is not open for betting.")]
-    MarketNotOpenForBets,
-    #[msg("Betting window for this market has closed.")]
-    BettingWindowClosed,
-    #[msg("Bet amount cannot be zero.")]
-    ZeroBetAmount,
-    #[msg("User cannot bet on their own streak.")]
-    CannotBetOnSelf,
-    #[msg("Market is not ready for resolution.")]
-    MarketNotReadyForResolution,
-    #[msg("Resolution grace period for this market is not over yet.")]
-    ResolutionGracePeriodNotOver,
-    #[msg("Market has already been resolved.")]
-    MarketAlreadyResolved,
-    #[msg("This bet belongs to another user.")]
-    BetBelongsToAnotherUser,
-    #[msg("Winnings for this bet have already been claimed.")]
-    WinningsAlreadyClaimed,
-    #[msg("Platform fee has not been claimed yet; market resolution might be incomplete.")]
-    PlatformFeeNotClaimed,
-    #[msg("The UserState account provided does not match the one associated with the market or user.")]
-    UserStateMismatch,
-    #[msg("The user being bet on has not started their course yet.")]
-    UserCourseNotStarted,
-    #[msg("The betting window duration extends beyond or too close to the task deadline.")]
-    BettingWindowTooLong,
-    #[msg("This market belongs to another user.")]
-    MarketBelongsToAnotherUser,
-    #[msg("Course is not active. Either it hasn't started or has already ended.")]
-    CourseNotActive,
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            MARKET_SEED,
+            market_state.user_being_bet_on.as_ref(),
+            market_state.user_state_account_for_bet.as_ref(),
+            market_state.course_index.to_le_bytes().as_ref(),
+            market_state.cycle_index.to_le_bytes().as_ref(),
+            market_state.terms_seed().as_ref()
+        ],
+        bump = market_state.bump
+    )]
+    pub market_state: Account<'info, MarketState>,
+
+    #[account(
+        seeds = [MARKET_ESCROW_VAULT_SEED, market_state.key().as_ref()],
+        bump = market_escrow_vault.bump,
+    )]
+    pub market_escrow_vault: Account<'info, MarketEscrowVault>,
+
+    #[account(
+        mut,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = market_escrow_vault,
+    )]
+    pub market_escrow_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut, // To receive the pool's payout
+        constraint = creator_token_account.mint == usdc_mint.key() @ ErrorCode::TokenAccountMintMismatch,
+        constraint = creator_token_account.owner == market_creator.key()
+    )]
+    pub creator_token_account: Account<'info, TokenAccount>,
+
+    #[account(address = program_config.usdc_mint @ ErrorCode::InvalidUsdcMint)]
+    pub usdc_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+// FinalizeMarket context - permissionless, caller pays for treasury ATA init if needed
+#[derive(Accounts)]
+pub struct FinalizeMarket<'info> {
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            MARKET_SEED,
+            market_state.user_being_bet_on.as_ref(),
+            market_state.user_state_account_for_bet.as_ref(),
+            market_state.course_index.to_le_bytes().as_ref(),
+            market_state.cycle_index.to_le_bytes().as_ref(),
+            market_state.terms_seed().as_ref()
+        ],
+        bump = market_state.bump
+    )]
+    pub market_state: Account<'info, MarketState>,
+
+    #[account(
+        mut,
+        seeds = [MARKET_ESCROW_VAULT_SEED, market_state.key().as_ref()],
+        bump = market_escrow_vault.bump,
+    )]
+    pub market_escrow_vault: Account<'info, MarketEscrowVault>,
+
+    #[account(
+        mut,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = market_escrow_vault,
+    )]
+    pub market_escrow_token_account: Account<'info, TokenAccount>,
+
+    // Outcome token supplies tell whether claims are outstanding
+    #[account(address = market_state.long_mint @ ErrorCode::InvalidOutcomeMint)]
+    pub long_mint: Box<Account<'info, Mint>>,
+
+    #[account(address = market_state.short_mint @ ErrorCode::InvalidOutcomeMint)]
+    pub short_mint: Box<Account<'info, Mint>>,
+
+    /// CHECK: Receives the market rent, constrained to the market creator
+    #[account(mut, address = market_state.market_creator)]
+    pub market_creator: AccountInfo<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = caller,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = treasury_wallet_account,
+    )]
+    pub treasury_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Treasury wallet pubkey, constrained by treasury_wallet()
+    #[account(address = treasury_wallet(&program_config) @ ErrorCode::InvalidTreasuryAccount)]
+    pub treasury_wallet_account: AccountInfo<'info>,
+
+    #[account(address = program_config.usdc_mint @ ErrorCode::InvalidUsdcMint)]
+    pub usdc_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+
+// --- Event Structs for Betting ---
+
+#[event]
+pub struct MarketCreated {
+    pub market: Pubkey,
+    pub user_being_bet_on: Pubkey,
+    pub cycle_index: u64,
+    pub market_type: MarketType,
+    pub horizon_cycles: u64,
+    pub streak_threshold: u64,
+    pub task_deadline_timestamp: i64,
+    pub betting_ends_timestamp: i64,
+    pub platform_fee_basis_points: u16,
+    pub fee_rebate_basis_points: u16,
+    pub fee_rebate_recipient: Pubkey,
+}
+
+#[event]
+pub struct BetPlaced {
+    pub market: Pubkey,
+    pub bettor: Pubkey,
+    pub amount: u64,
+    pub position_is_long: bool,
+    pub position_long_amount: u64,  // Bettor's total long stake after this bet
+    pub position_short_amount: u64, // Bettor's total short stake after this bet
+    pub implied_long_probability_basis_points: u16,
+}
+
+#[event]
+pub struct SharesTraded {
+    pub market: Pubkey,
+    pub bettor: Pubkey,
+    pub position_is_long: bool,
+    pub is_buy: bool,
+    pub usdc_amount: u64, // Paid in (buy, fee included) or received (sell, after fee)
+    pub shares: u64,
+    pub fee_amount: u64,
+    pub implied_long_probability_basis_points: u16,
+}
+
+#[event]
+pub struct LiquidityRedeemed {
+    pub market: Pubkey,
+    pub market_creator: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct MarketResolved {
+    pub market: Pubkey,
+    pub status: MarketStatus, // So off-chain can see outcome
+    pub total_long_amount: u64,
+    pub total_short_amount: u64,
+}
+
+#[event]
+pub struct MarketProvisionallyResolved {
+    pub market: Pubkey,
+    pub longs_win: bool,
+    pub dispute_ends_timestamp: i64,
+}
+
+#[event]
+pub struct OutcomeDisputed {
+    pub market: Pubkey,
+    pub disputer: Pubkey,
+    pub bond_amount: u64,
+    pub ruling_deadline_timestamp: i64,
+}
+
+#[event]
+pub struct DisputeResolved {
+    pub market: Pubkey,
+    pub longs_win: bool,
+    pub flipped: bool,      // Resolver overturned the provisional outcome
+    pub disputer: Pubkey,   // Default if the ruling was made without a dispute
+    pub bond_amount: u64,   // Returned to the disputer if flipped, otherwise slashed
+}
+
+#[event]
+pub struct PlatformFeeCollected {
+    pub market: Pubkey,
+    pub treasury_amount: u64,
+    pub rebate_amount: u64,
+    pub fee_rebate_recipient: Pubkey,
+    pub resolution_bounty_amount: u64,
+    pub caller: Pubkey, // Resolver who received the bounty
 }
 
+#[event]
+pub struct WinningsClaimed {
+    pub market: Pubkey,
+    pub bettor: Pubkey,
+    pub amount_claimed: u64,
+}
+
+#[event]
+pub struct BetsSettled {
+    pub market: Pubkey,
+    pub settled_bet_count: u64, // BetStates closed in this call
+    pub total_paid_amount: u64,
+}
+
+#[event]
+pub struct MarketFinalized {
+    pub market: Pubkey,
+    pub claimed_bet_count: u64,
+    pub bet_count: u64,
+    pub total_claimed_amount: u64,
+    pub swept_amount: u64, // Dust and unclaimed funds sent to the treasury
+}
+
+#[event]
+pub struct SubjectDepositForfeited {
+    pub market: Pubkey,
+    pub user: Pubkey,
+    pub forfeited_amount: u64,
+    pub remaining_locked_amount: u64,
+}
+
+#[event]
+pub struct MarketCancelled {
+    pub market: Pubkey,
+    pub total_long_amount: u64,
+    pub total_short_amount: u64,
+}
+
+#[event]
+pub struct RefundClaimed {
+    pub market: Pubkey,
+    pub bettor: Pubkey,
+    pub amount_refunded: u64,
+}
+
+// Add new CloseMarket context
 #[derive(Accounts)]
-pub struct Withdraw<'info> {
+pub struct CloseMarket<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
 
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        seeds = [
+            MARKET_SEED,
+            user.key().as_ref(),
+            user_state.key().as_ref(),
+            market_state.course_index.to_le_bytes().as_ref(),
+            market_state.cycle_index.to_le_bytes().as_ref(),
+            market_state.terms_seed().as_ref()
+        ],
+        bump = market_state.bump,
+        constraint = market_state.user_being_bet_on == user.key()
+    )]
+    pub market_state: Account<'info, MarketState>,
+
     #[account(
         mut,
+        seeds = [MARKET_ESCROW_VAULT_SEED, market_state.key().as_ref()],
+        bump
+    )]
+    pub market_escrow_vault: Account<'info, MarketEscrowVault>,
+
+    #[account(
+        mut,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = market_escrow_vault,
+    )]
+    pub market_escrow_token_account: Account<'info, TokenAccount>,
+
+    #[account(
         seeds = [USER_SEED, user.key().as_ref()],
         bump,
-        constraint = user_state.user == user.key(),
+        constraint = user_state.user == user.key()
     )]
     pub user_state: Account<'info, UserState>,
+
+    #[account(address = program_config.usdc_mint @ ErrorCode::InvalidUsdcMint)]
+    pub usdc_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct EarlyWithdraw<'info> {
+pub struct WithdrawUnlocked<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
 
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+
     #[account(
         mut,
         seeds = [USER_SEED, user.key().as_ref()],
@@ -950,12 +5900,12 @@ pub struct EarlyWithdraw<'info> {
 
     #[account(
         mut,
-        constraint = user_token_account.mint == usdc_mint.key(),
+        constraint = user_token_account.mint == usdc_mint.key() @ ErrorCode::TokenAccountMintMismatch,
         constraint = user_token_account.owner == user.key()
     )]
     pub user_token_account: Account<'info, TokenAccount>,
 
-    /// CHECK: Vault PDA
+    /// CHECK: Vault PDA is safe because it's derived from a known seed and verified by token account authority
     #[account(
         seeds = [VAULT_SEED],
         bump
@@ -969,494 +5919,722 @@ pub struct EarlyWithdraw<'info> {
     )]
     pub vault_token_account: Account<'info, TokenAccount>,
 
-    // Initialize treasury ATA if it doesn't exist
     #[account(
-        init_if_needed,
-        payer = user, // User pays for initialization
-        associated_token::mint = usdc_mint,
-        associated_token::authority = treasury_wallet_account, // Use the actual treasury wallet AccountInfo as authority
+        mut,
+        seeds = [VAULT_STATE_SEED],
+        bump = vault_state.bump
     )]
-    pub treasury_token_account: Account<'info, TokenAccount>,
+    pub vault_state: Account<'info, VaultState>,
 
-    /// CHECK: Hardcoded treasury wallet address.
-    pub treasury_wallet_account: AccountInfo<'info>,
+    #[account(address = program_config.usdc_mint @ ErrorCode::InvalidUsdcMint)]
+    pub usdc_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+// Add RecordTask context after WithdrawUnlocked context
+#[derive(Accounts)]
+pub struct RecordTask<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
 
-    // Add market accounts for closing
     #[account(
         mut,
-        seeds = [
-            MARKET_SEED,
-            user.key().as_ref(),
-            user_state.key().as_ref()
-        ],
+        seeds = [USER_SEED, user.key().as_ref()],
         bump,
-        constraint = market_state.user_being_bet_on == user.key()
+        constraint = user_state.user == user.key()
     )]
-    pub market_state: Account<'info, MarketState>,
+    pub user_state: Account<'info, UserState>,
 
     #[account(
         mut,
-        seeds = [MARKET_ESCROW_VAULT_SEED, market_state.key().as_ref()],
-        bump
+        seeds = [VAULT_STATE_SEED],
+        bump = vault_state.bump
     )]
-    pub market_escrow_vault: Account<'info, MarketEscrowVault>,
+    pub vault_state: Account<'info, VaultState>,
+
+    #[account(
+        seeds = [YIELD_CONFIG_SEED],
+        bump = yield_config.bump
+    )]
+    pub yield_config: Account<'info, YieldConfig>,
 
-    pub usdc_mint: Account<'info, Mint>,
-    pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub rent: Sysvar<'info, Rent>,
 }
 
-// --- Start Course Accounts Struct (Phase 1) ---
-// Defines the accounts needed for the start_course instruction.
-// This now also includes accounts for automatic market creation.
+// RecordMiss context - permissionless crank, caller pays for treasury ATA init if needed
 #[derive(Accounts)]
-#[instruction(lock_in_duration_days: u64, lock_amount: u64)] // Added lock_amount parameter
-pub struct StartCourse<'info> {
-    // The user starting the course.
+pub struct RecordMiss<'info> {
     #[account(mut)]
-    pub user: Signer<'info>,
+    pub caller: Signer<'info>,
+
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
 
-    // The user's state account, which will be modified.
     #[account(
         mut,
-        seeds = [USER_SEED, user.key().as_ref()],
+        seeds = [USER_SEED, user_state.user.as_ref()],
         bump,
-        constraint = user_state.user == user.key() // Ensure the state belongs to the signer
     )]
     pub user_state: Account<'info, UserState>,
 
-    // --- Accounts for automatic market creation ---
+    /// CHECK: Vault PDA
     #[account(
-        init_if_needed,
-        payer = user, // User pays for market state creation
-        space = 8 + MarketState::INIT_SPACE,
-        seeds = [
-            MARKET_SEED,
-            user.key().as_ref(),      // user_being_bet_on is the one starting the course (user)
-            user_state.key().as_ref() // user_state_account_for_bet is their user_state
-        ],
+        seeds = [VAULT_SEED],
         bump
     )]
-    pub market_state: Account<'info, MarketState>,
+    pub vault: AccountInfo<'info>,
 
     #[account(
-        init_if_needed,
-        payer = user, // User pays for market escrow vault creation
-        space = 8 + MarketEscrowVault::INIT_SPACE,
-        seeds = [MARKET_ESCROW_VAULT_SEED, market_state.key().as_ref()], // market_state.key() is now available
-        bump
+        mut,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = vault,
     )]
-    pub market_escrow_vault: Account<'info, MarketEscrowVault>,
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [VAULT_STATE_SEED],
+        bump = vault_state.bump
+    )]
+    pub vault_state: Account<'info, VaultState>,
+
+    #[account(
+        seeds = [YIELD_CONFIG_SEED],
+        bump = yield_config.bump
+    )]
+    pub yield_config: Account<'info, YieldConfig>,
 
     #[account(
         init_if_needed,
-        payer = user, // User pays for ATA creation
+        payer = caller,
         associated_token::mint = usdc_mint,
-        associated_token::authority = market_escrow_vault, // market_escrow_vault PDA is the authority
+        associated_token::authority = treasury_wallet_account,
     )]
-    pub market_escrow_token_account: Account<'info, TokenAccount>,
+    pub treasury_token_account: Account<'info, TokenAccount>,
 
-    // Common accounts needed for initialization of market-related accounts
-    #[account(mut)] // Marked as mut for consistency with CreateMarket, ATA init might require it.
+    /// CHECK: Treasury wallet pubkey, constrained by treasury_wallet()
+    #[account(address = treasury_wallet(&program_config) @ ErrorCode::InvalidTreasuryAccount)]
+    pub treasury_wallet_account: AccountInfo<'info>,
+
+    #[account(address = program_config.usdc_mint @ ErrorCode::InvalidUsdcMint)]
     pub usdc_mint: Account<'info, Mint>,
-    pub system_program: Program<'info, System>,
     pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub rent: Sysvar<'info, Rent>,
 }
 
-// --- Betting System Structs and Enums ---
-
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace)]
-pub enum MarketStatus {
-    Open,              // Betting is active
-    AwaitingResolution,// Betting closed, outcome not yet determined by contract
-    ResolvedLongsWin,  // User maintained streak, longs win
-    ResolvedShortsWin, // User broke streak, shorts win
-    Cancelled,         // Optional: If market needs to be voided for some reason
-}
-
-impl Default for MarketStatus {
-    fn default() -> Self {
-        MarketStatus::Open
-    }
+#[event]
+pub struct StreakUpdated {
+    pub user: Pubkey,
+    pub current_streak: u64,
+    pub longest_streak: u64,
+    pub last_streak_increment_timestamp: i64,
+    pub streak_expires_at: i64,
+    pub streak_reset: bool,
 }
 
-#[account]
-#[derive(InitSpace)]
-pub struct MarketState {
-    pub market_creator: Pubkey,          // Who initiated this market
-    pub user_being_bet_on: Pubkey,       // The user whose streak is the subject of the bet
-    pub user_state_account_for_bet: Pubkey, // PDA of UserState for user_being_bet_on (to check outcome)
-    
-    pub total_long_amount: u64,          // Total USDC staked on "long"
-    pub total_short_amount: u64,         // Total USDC staked on "short"
-    
-    pub market_creation_timestamp: i64,  // When this market was created
-    pub betting_ends_timestamp: i64,     // Betting stops
-    pub task_deadline_timestamp: i64,    // The specific task deadline for the user_being_bet_on this market refers to
-    pub resolution_timestamp: i64,       // When outcome can be checked (task_deadline_timestamp + grace period)
-    
-    pub status: MarketStatus,
-    pub platform_fee_basis_points: u16,  // e.g., 500 for 5.00% (500 / 10000)
-    pub platform_fee_claimed: bool,      // Has the platform fee been transferred to treasury?
-
-    pub bump: u8,
+#[event]
+pub struct MissRecorded {
+    pub user: Pubkey,
+    pub miss_count: u64,
+    pub missed_deadline_timestamp: i64,
+    pub penalty_basis_points: u16,
+    pub penalty_amount: u64,
+    pub remaining_locked_amount: u64,
+    pub yield_rate_halved: bool,
 }
 
-#[account]
-#[derive(InitSpace)]
-pub struct BetState {
-    pub market: Pubkey,             // PDA of the MarketState this bet belongs to
-    pub bettor: Pubkey,             // User who placed the bet
-    pub amount: u64,                // Amount of USDC bet
-    pub position_is_long: bool,     // True if "long", false if "short"
-    pub winnings_claimed: bool,     // True if winnings (if any) have been claimed
-    pub bump: u8,
-}
+#[derive(Accounts)]
+pub struct TopUpAfterMiss<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
 
-// PDA to act as authority for the market's escrow token account
-#[account]
-#[derive(InitSpace)]
-pub struct MarketEscrowVault {
-    pub market: Pubkey, // Links vault to the MarketState PDA
-    pub bump: u8,
-}
+    #[account(
+        seeds = [CONFIG_SEED],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
 
+    #[account(
+        mut,
+        seeds = [USER_SEED, user.key().as_ref()],
+        bump,
+        constraint = user_state.user == user.key(),
+    )]
+    pub user_state: Account<'info, UserState>,
 
-// --- Betting System Account Contexts ---
+    #[account(
+        mut,
+        constraint = user_token_account.mint == usdc_mint.key() @ ErrorCode::TokenAccountMintMismatch,
+        constraint = user_token_account.owner == user.key()
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
 
-#[derive(Accounts)]
-#[instruction(betting_window_duration_seconds: u64)]
-pub struct CreateMarket<'info> {
-    #[account(mut)]
-    pub market_creator: Signer<'info>,
+    /// CHECK: Vault PDA
+    #[account(
+        seeds = [VAULT_SEED],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
 
-    /// CHECK: No sensitive ops, just storing its key and using for PDA derivation if needed.
-    pub user_being_bet_on: AccountInfo<'info>, 
-    
-    pub user_state_for_bet: Account<'info, UserState>,
+    #[account(
+        mut,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = vault,
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
 
     #[account(
-        init,
-        payer = market_creator,
-        space = 8 + MarketState::INIT_SPACE,
-        seeds = [
-            MARKET_SEED, 
-            user_being_bet_on.key().as_ref(), 
-            user_state_for_bet.key().as_ref() // Use UserState PDA key as part of the seed
-        ],
-        bump
+        mut,
+        seeds = [VAULT_STATE_SEED],
+        bump = vault_state.bump
     )]
-    pub market_state: Account<'info, MarketState>,
+    pub vault_state: Account<'info, VaultState>,
 
     #[account(
-        init,
-        payer = market_creator,
-        space = 8 + MarketEscrowVault::INIT_SPACE,
-        seeds = [MARKET_ESCROW_VAULT_SEED, market_state.key().as_ref()],
-        bump
+        seeds = [YIELD_CONFIG_SEED],
+        bump = yield_config.bump
     )]
-    pub market_escrow_vault: Account<'info, MarketEscrowVault>,
+    pub yield_config: Account<'info, YieldConfig>,
 
     #[account(
-        init,
-        payer = market_creator,
+        init_if_needed,
+        payer = user,
         associated_token::mint = usdc_mint,
-        associated_token::authority = market_escrow_vault, // market_escrow_vault PDA is the authority for this ATA
+        associated_token::authority = treasury_wallet_account,
     )]
-    pub market_escrow_token_account: Account<'info, TokenAccount>,
-    
-    #[account(mut)] // For ATA initialization potentially
+    pub treasury_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Treasury wallet pubkey, constrained by treasury_wallet()
+    #[account(address = treasury_wallet(&program_config) @ ErrorCode::InvalidTreasuryAccount)]
+    pub treasury_wallet_account: AccountInfo<'info>,
+
+    #[account(address = program_config.usdc_mint @ ErrorCode::InvalidUsdcMint)]
     pub usdc_mint: Account<'info, Mint>,
-    pub system_program: Program<'info, System>,
     pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub rent: Sysvar<'info, Rent>,
 }
 
+#[event]
+pub struct MissToppedUp {
+    pub user: Pubkey,
+    pub miss_count: u64,
+    pub missed_deadline_timestamp: i64,
+    pub top_up_basis_points: u16,
+    pub top_up_amount: u64,
+    pub treasury_amount: u64,
+    pub credited_amount: u64,
+    pub current_streak: u64,
+}
+
 #[derive(Accounts)]
-#[instruction(amount: u64, position_is_long: bool)]
-pub struct PlaceBet<'info> {
+#[instruction(amount: u64)]
+pub struct FundYieldReserve<'info> {
     #[account(mut)]
-    pub bettor: Signer<'info>,
+    pub funder: Signer<'info>,
 
     #[account(
-        mut,
-        // Validate market_state PDA using its own stored fields that were used as seeds
-        seeds = [
-            MARKET_SEED, 
-            market_state.user_being_bet_on.as_ref(),
-            market_state.user_state_account_for_bet.as_ref()
-        ],
-        bump = market_state.bump
+        seeds = [CONFIG_SEED],
+        bump = program_config.bump
     )]
-    pub market_state: Account<'info, MarketState>,
+    pub program_config: Account<'info, ProgramConfig>,
 
     #[account(
         mut,
-        seeds = [MARKET_ESCROW_VAULT_SEED, market_state.key().as_ref()],
-        bump = market_escrow_vault.bump, // market_escrow_vault stores its own bump
+        constraint = funder_token_account.mint == usdc_mint.key() @ ErrorCode::TokenAccountMintMismatch,
+        constraint = funder_token_account.owner == funder.key()
     )]
-    pub market_escrow_vault: Account<'info, MarketEscrowVault>,
+    pub funder_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Vault PDA
+    #[account(
+        seeds = [VAULT_SEED],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
 
     #[account(
-        mut,
+        init_if_needed,
+        payer = funder,
         associated_token::mint = usdc_mint,
-        associated_token::authority = market_escrow_vault,
+        associated_token::authority = vault,
     )]
-    pub market_escrow_token_account: Account<'info, TokenAccount>,
-
-    #[account(mut)] // Bettor's USDC account
-    pub bettor_token_account: Account<'info, TokenAccount>,
+    pub vault_token_account: Account<'info, TokenAccount>,
 
     #[account(
-        init,
-        payer = bettor,
-        space = 8 + BetState::INIT_SPACE,
-        seeds = [BET_SEED, market_state.key().as_ref(), bettor.key().as_ref()],
+        init_if_needed,
+        payer = funder,
+        space = 8 + VaultState::INIT_SPACE,
+        seeds = [VAULT_STATE_SEED],
         bump
     )]
-    pub bet_state: Account<'info, BetState>,
-    
-    #[account(mut)]
-    pub usdc_mint: Account<'info, Mint>, // Though mint itself isn't mutated, ATA init might need it.
+    pub vault_state: Account<'info, VaultState>,
+
+    #[account(address = program_config.usdc_mint @ ErrorCode::InvalidUsdcMint)]
+    pub usdc_mint: Account<'info, Mint>,
     pub system_program: Program<'info, System>,
     pub token_program: Program<'info, Token>,
-    // No AssociatedToken program needed here as market_escrow_token_account already exists.
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[event]
+pub struct YieldReserveFunded {
+    pub funder: Pubkey,
+    pub amount: u64,
+    pub yield_reserve: u64,
+    pub total_yield_liabilities: u64,
 }
 
 #[derive(Accounts)]
-pub struct TriggerMarketResolution<'info> {
-    #[account(mut)] // Caller needs to be mutable if paying for treasury_token_account init
-    pub caller: Signer<'info>,
+pub struct InitializeStrategy<'info> {
+    #[account(mut, address = program_config.admin @ ErrorCode::UnauthorizedStrategyAuthority)]
+    pub authority: Signer<'info>,
 
     #[account(
-        mut,
-        seeds = [
-            MARKET_SEED,
-            market_state.user_being_bet_on.as_ref(),
-            market_state.user_state_account_for_bet.as_ref()
-        ],
-        bump = market_state.bump
+        seeds = [CONFIG_SEED],
+        bump = program_config.bump
     )]
-    pub market_state: Account<'info, MarketState>,
+    pub program_config: Account<'info, ProgramConfig>,
 
-    // UserState account of the user whose streak was bet on.
-    // Used to determine the outcome.
     #[account(
-        // Constraint: user_state_for_bet.key() == market_state.user_state_account_for_bet (checked in logic)
-        // Constraint: user_state_for_bet.user == market_state.user_being_bet_on (checked in logic)
+        init,
+        payer = authority,
+        space = 8 + YieldStrategy::INIT_SPACE,
+        seeds = [STRATEGY_SEED, lending_program.key().as_ref(), lending_pool.key().as_ref()],
+        bump
     )]
-    pub user_state_for_bet: Account<'info, UserState>,
+    pub yield_strategy: Account<'info, YieldStrategy>,
 
-    #[account(
-        mut, // For transferring fee out
-        seeds = [MARKET_ESCROW_VAULT_SEED, market_state.key().as_ref()],
-        bump = market_escrow_vault.bump,
-    )]
-    pub market_escrow_vault: Account<'info, MarketEscrowVault>,
+    /// CHECK: External lending program implementing the strategy interface
+    #[account(executable)]
+    pub lending_program: AccountInfo<'info>,
 
-    #[account(
-        mut,
-        associated_token::mint = usdc_mint,
-        associated_token::authority = market_escrow_vault,
-    )]
-    pub market_escrow_token_account: Account<'info, TokenAccount>,
+    /// CHECK: Pool account owned by the lending program
+    #[account(owner = lending_program.key())]
+    pub lending_pool: AccountInfo<'info>,
 
-    // Treasury's token account to receive platform fees
-    #[account(
-        init_if_needed, // Treasury ATA might not exist yet
-        payer = caller, // Caller pays for init if needed
-        associated_token::mint = usdc_mint,
-        associated_token::authority = treasury_wallet_account, // Treasury wallet is authority
-    )]
-    pub treasury_token_account: Account<'info, TokenAccount>,
-    
-    /// CHECK: Treasury wallet pubkey, constrained by treasury_wallet()
-    #[account(address = treasury_wallet())]
-    pub treasury_wallet_account: AccountInfo<'info>,
+    #[account(constraint = lending_pool_token_account.mint == usdc_mint.key())]
+    pub lending_pool_token_account: Account<'info, TokenAccount>,
 
-    #[account(mut)]
+    /// CHECK: The vault's position inside the lending program, may be created on first deposit
+    pub lending_position: AccountInfo<'info>,
+
+    #[account(address = program_config.usdc_mint @ ErrorCode::InvalidUsdcMint)]
     pub usdc_mint: Account<'info, Mint>,
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>, // For init_if_needed
-    pub associated_token_program: Program<'info, AssociatedToken>, // For init_if_needed
-    pub rent: Sysvar<'info, Rent>, // For init_if_needed
+    pub system_program: Program<'info, System>,
 }
 
+// Shared by deploy_to_strategy, recall_from_strategy (admin only) and harvest (anyone)
 #[derive(Accounts)]
-pub struct ClaimWinnings<'info> {
+pub struct StrategyOperation<'info> {
     #[account(mut)]
-    pub bettor: Signer<'info>,
+    pub caller: Signer<'info>,
 
     #[account(
-        seeds = [
-            MARKET_SEED,
-            market_state.user_being_bet_on.as_ref(),
-            market_state.user_state_account_for_bet.as_ref()
-        ],
-        bump = market_state.bump
+        seeds = [CONFIG_SEED],
+        bump = program_config.bump
     )]
-    pub market_state: Account<'info, MarketState>,
+    pub program_config: Account<'info, ProgramConfig>,
 
+    /// CHECK: Vault PDA, signs for the lending position
     #[account(
-        mut,
-        seeds = [BET_SEED, market_state.key().as_ref(), bettor.key().as_ref()],
-        bump = bet_state.bump, // bet_state stores its own bump
-        constraint = bet_state.bettor == bettor.key(), // Ensure bettor owns this bet_state
-        constraint = bet_state.market == market_state.key(), // Ensure bet_state is for this market
+        seeds = [VAULT_SEED],
+        bump
     )]
-    pub bet_state: Account<'info, BetState>,
+    pub vault: AccountInfo<'info>,
 
     #[account(
         mut,
-        seeds = [MARKET_ESCROW_VAULT_SEED, market_state.key().as_ref()],
-        bump = market_escrow_vault.bump,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = vault,
     )]
-    pub market_escrow_vault: Account<'info, MarketEscrowVault>,
+    pub vault_token_account: Account<'info, TokenAccount>,
 
     #[account(
         mut,
-        associated_token::mint = usdc_mint,
-        associated_token::authority = market_escrow_vault,
+        seeds = [VAULT_STATE_SEED],
+        bump = vault_state.bump
     )]
-    pub market_escrow_token_account: Account<'info, TokenAccount>,
+    pub vault_state: Account<'info, VaultState>,
 
     #[account(
-        mut, // To receive winnings
-        constraint = bettor_token_account.mint == usdc_mint.key(),
-        constraint = bettor_token_account.owner == bettor.key()
+        mut,
+        seeds = [STRATEGY_SEED, yield_strategy.lending_program.as_ref(), yield_strategy.lending_pool.as_ref()],
+        bump = yield_strategy.bump
     )]
-    pub bettor_token_account: Account<'info, TokenAccount>,
-    
-    #[account(mut)]
-    pub usdc_mint: Account<'info, Mint>,
-    pub token_program: Program<'info, Token>,
-}
+    pub yield_strategy: Account<'info, YieldStrategy>,
 
+    /// CHECK: Constrained to the strategy's registered lending program
+    #[account(executable, address = yield_strategy.lending_program)]
+    pub lending_program: AccountInfo<'info>,
 
-// --- Event Structs for Betting ---
+    /// CHECK: Constrained to the strategy's registered pool
+    #[account(mut, address = yield_strategy.lending_pool)]
+    pub lending_pool: AccountInfo<'info>,
 
-#[event]
-pub struct MarketCreated {
-    pub market: Pubkey,
-    pub user_being_bet_on: Pubkey,
-    pub task_deadline_timestamp: i64,
-    pub betting_ends_timestamp: i64,
-}
+    /// CHECK: Constrained to the strategy's registered pool token account
+    #[account(mut, address = yield_strategy.lending_pool_token_account)]
+    pub lending_pool_token_account: AccountInfo<'info>,
 
-#[event]
-pub struct BetPlaced {
-    pub market: Pubkey,
-    pub bettor: Pubkey,
-    pub amount: u64,
-    pub position_is_long: bool,
+    /// CHECK: Constrained to the strategy's registered position
+    #[account(mut, address = yield_strategy.lending_position)]
+    pub lending_position: AccountInfo<'info>,
+
+    #[account(address = program_config.usdc_mint @ ErrorCode::InvalidUsdcMint)]
+    pub usdc_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
 }
 
 #[event]
-pub struct MarketResolved {
-    pub market: Pubkey,
-    pub status: MarketStatus, // So off-chain can see outcome
-    pub total_long_amount: u64,
-    pub total_short_amount: u64,
+pub struct StrategyFundsMoved {
+    pub strategy: Pubkey,
+    pub amount: u64,
+    pub deployed: bool, // true for deploy_to_strategy, false for recall_from_strategy
+    pub strategy_deployed_amount: u64,
 }
 
 #[event]
-pub struct WinningsClaimed {
-    pub market: Pubkey,
-    pub bettor: Pubkey,
-    pub amount_claimed: u64,
+pub struct StrategyHarvested {
+    pub strategy: Pubkey,
+    pub yield_amount: u64,
+    pub loss_amount: u64,
+    pub yield_reserve: u64,
 }
 
-// Add new CloseMarket context
 #[derive(Accounts)]
-pub struct CloseMarket<'info> {
-    #[account(mut)]
-    pub user: Signer<'info>,
+pub struct InitializeYieldConfig<'info> {
+    #[account(mut, address = program_config.admin @ ErrorCode::UnauthorizedConfigAuthority)]
+    pub authority: Signer<'info>,
 
     #[account(
-        mut,
-        seeds = [
-            MARKET_SEED,
-            user.key().as_ref(),
-            user_state.key().as_ref()
-        ],
-        bump = market_state.bump,
-        constraint = market_state.user_being_bet_on == user.key()
+        seeds = [CONFIG_SEED],
+        bump = program_config.bump
     )]
-    pub market_state: Account<'info, MarketState>,
+    pub program_config: Account<'info, ProgramConfig>,
 
     #[account(
-        mut,
-        seeds = [MARKET_ESCROW_VAULT_SEED, market_state.key().as_ref()],
+        init,
+        payer = authority,
+        space = 8 + YieldConfig::INIT_SPACE,
+        seeds = [YIELD_CONFIG_SEED],
         bump
     )]
-    pub market_escrow_vault: Account<'info, MarketEscrowVault>,
-
-    #[account(
-        seeds = [USER_SEED, user.key().as_ref()],
-        bump,
-        constraint = user_state.user == user.key()
-    )]
-    pub user_state: Account<'info, UserState>,
+    pub yield_config: Account<'info, YieldConfig>,
 
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct WithdrawUnlocked<'info> {
-    #[account(mut)]
-    pub user: Signer<'info>,
+pub struct UpdateYieldConfig<'info> {
+    #[account(address = program_config.admin @ ErrorCode::UnauthorizedConfigAuthority)]
+    pub authority: Signer<'info>,
 
     #[account(
-        mut,
-        seeds = [USER_SEED, user.key().as_ref()],
-        bump,
-        constraint = user_state.user == user.key(),
+        seeds = [CONFIG_SEED],
+        bump = program_config.bump
     )]
-    pub user_state: Account<'info, UserState>,
+    pub program_config: Account<'info, ProgramConfig>,
 
     #[account(
         mut,
-        constraint = user_token_account.mint == usdc_mint.key(),
-        constraint = user_token_account.owner == user.key()
+        seeds = [YIELD_CONFIG_SEED],
+        bump = yield_config.bump
     )]
-    pub user_token_account: Account<'info, TokenAccount>,
+    pub yield_config: Account<'info, YieldConfig>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
 
-    /// CHECK: Vault PDA is safe because it's derived from a known seed and verified by token account authority
     #[account(
-        seeds = [VAULT_SEED],
+        init,
+        payer = admin,
+        space = 8 + ProgramConfig::INIT_SPACE,
+        seeds = [CONFIG_SEED],
         bump
     )]
-    pub vault: AccountInfo<'info>,
+    pub program_config: Account<'info, ProgramConfig>,
+
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::SkillstreakProgram>,
 
     #[account(
-        mut,
-        associated_token::mint = usdc_mint,
-        associated_token::authority = vault,
+        constraint = program_data.upgrade_authority_address == Some(admin.key()) @ ErrorCode::NotUpgradeAuthority
     )]
-    pub vault_token_account: Account<'info, TokenAccount>,
+    pub program_data: Account<'info, ProgramData>,
 
-    pub usdc_mint: Account<'info, Mint>,
-    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
 }
 
-// Add RecordTask context after WithdrawUnlocked context
 #[derive(Accounts)]
-pub struct RecordTask<'info> {
-    #[account(mut)]
-    pub user: Signer<'info>,
+pub struct UpdateConfig<'info> {
+    #[account(address = program_config.admin @ ErrorCode::UnauthorizedConfigAuthority)]
+    pub admin: Signer<'info>,
 
     #[account(
         mut,
-        seeds = [USER_SEED, user.key().as_ref()],
-        bump,
-        constraint = user_state.user == user.key()
+        seeds = [CONFIG_SEED],
+        bump = program_config.bump
     )]
-    pub user_state: Account<'info, UserState>,
+    pub program_config: Account<'info, ProgramConfig>,
+}
 
-    pub system_program: Program<'info, System>,
+#[derive(Accounts)]
+pub struct AcceptConfigAdmin<'info> {
+    #[account(address = program_config.pending_admin @ ErrorCode::NotPendingConfigAdmin)]
+    pub new_admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [CONFIG_SEED],
+        bump = program_config.bump
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn miss_penalty_tiers_escalate_then_flatten() {
+        assert_eq!(miss_penalty_basis_points(0), 0);
+        assert_eq!(miss_penalty_basis_points(1), 1_000);
+        assert_eq!(miss_penalty_basis_points(2), 2_000);
+        assert_eq!(miss_penalty_basis_points(3), 3_000);
+        // 4th and later misses take the flat repeat penalty
+        assert_eq!(miss_penalty_basis_points(REPEAT_MISS_THRESHOLD), REPEAT_MISS_PENALTY_BASIS_POINTS);
+        assert_eq!(miss_penalty_basis_points(10), REPEAT_MISS_PENALTY_BASIS_POINTS);
+    }
+
+    #[test]
+    fn miss_deadline_starts_from_the_later_of_task_and_recorded_miss() {
+        let mut user_state = UserState {
+            last_task_timestamp: 1_000,
+            ..Default::default()
+        };
+        assert_eq!(next_miss_deadline(&user_state, 100).unwrap(), 1_100);
+
+        // After a recorded miss, the next window starts at that miss's deadline
+        user_state.last_miss_deadline_timestamp = 1_100;
+        assert_eq!(next_miss_deadline(&user_state, 100).unwrap(), 1_200);
+    }
+
+    #[test]
+    fn task_history_stops_covering_cycles_past_its_length() {
+        let mut user_state = UserState::default();
+        mark_cycle_completed(&mut user_state, 0);
+        mark_cycle_completed(&mut user_state, TASK_CYCLE_HISTORY_LEN - 1);
+        assert!(task_history_covers(&user_state, 0));
+        assert!(cycle_completed(&user_state, 0));
+
+        // One more cycle shifts cycle 0 out of the history
+        mark_cycle_completed(&mut user_state, TASK_CYCLE_HISTORY_LEN);
+        assert!(!task_history_covers(&user_state, 0));
+        assert!(!cycle_completed(&user_state, 0));
+        assert!(task_history_covers(&user_state, 1));
+    }
+
+    #[test]
+    fn markets_with_different_terms_get_distinct_seeds() {
+        let week = StreakMarketParams { horizon_cycles: 7, streak_threshold: 7 };
+        let seeds = [
+            market_terms_seed(None, MarketMode::Parimutuel),
+            market_terms_seed(None, MarketMode::Amm),
+            market_terms_seed(Some(StreakMarketParams { horizon_cycles: 1, streak_threshold: 1 }), MarketMode::Parimutuel),
+            market_terms_seed(Some(week), MarketMode::Parimutuel),
+            market_terms_seed(Some(StreakMarketParams { horizon_cycles: 7, streak_threshold: 5 }), MarketMode::Parimutuel),
+            market_terms_seed(Some(week), MarketMode::Amm),
+        ];
+        for (i, seed) in seeds.iter().enumerate() {
+            assert!(seeds[i + 1..].iter().all(|other| other != seed));
+        }
+
+        // A market re-derives the seed it was created with
+        let market_state = MarketState {
+            market_type: MarketType::StreakSurvival,
+            market_mode: MarketMode::Amm,
+            horizon_cycles: 7,
+            streak_threshold: 7,
+            ..Default::default()
+        };
+        assert_eq!(market_state.terms_seed(), market_terms_seed(Some(week), MarketMode::Amm));
+    }
+
+    #[test]
+    fn short_heavy_above_two_thirds_of_positions() {
+        assert!(!is_short_heavy(0, 0).unwrap());
+        assert!(!is_short_heavy(1_000, 1_999).unwrap());
+        assert!(is_short_heavy(1_000, 2_001).unwrap());
+        assert!(is_short_heavy(0, 1).unwrap());
+    }
+
+    #[test]
+    fn forfeit_share_splits_per_eligible_long_token_up_to_what_is_left() {
+        let mut market_state = MarketState {
+            status: MarketStatus::ResolvedShortsWin,
+            forfeited_amount: 5_000,
+            forfeit_eligible_long_tokens: 10_000,
+            ..Default::default()
+        };
+        assert_eq!(forfeit_share(&market_state, 4_000).unwrap(), 2_000);
+
+        // Tokens an affiliate passed on after resolution can't draw more than is left
+        market_state.forfeit_claimed_amount = 4_500;
+        assert_eq!(forfeit_share(&market_state, 4_000).unwrap(), 500);
+
+        market_state.status = MarketStatus::ResolvedLongsWin;
+        assert_eq!(forfeit_share(&market_state, 4_000).unwrap(), 0);
+    }
+
+    #[test]
+    fn ceil_sqrt_rounds_up() {
+        assert_eq!(ceil_sqrt(0), 0);
+        assert_eq!(ceil_sqrt(1), 1);
+        assert_eq!(ceil_sqrt(2), 2);
+        assert_eq!(ceil_sqrt(4), 2);
+        assert_eq!(ceil_sqrt(5), 3);
+        assert_eq!(ceil_sqrt(40_200), 201);
+        assert_eq!(ceil_sqrt((u64::MAX as u128) * (u64::MAX as u128)), u64::MAX as u128);
+        assert_eq!(ceil_sqrt(u128::MAX), 1 << 64);
+    }
+
+    #[test]
+    fn amm_trades_keep_the_invariant_in_favour_of_the_pool() {
+        // 50 collateral mints 50 sets into a 100/100 pool: 150 * 67 >= 100 * 100
+        let (shares_out, side_reserve, other_reserve) = amm_buy(100, 100, 50).unwrap();
+        assert_eq!((shares_out, side_reserve, other_reserve), (83, 67, 150));
+
+        // Selling the shares straight back returns less than was paid in
+        let (collateral_out, side_reserve, other_reserve) = amm_sell(side_reserve, other_reserve, shares_out).unwrap();
+        assert_eq!((collateral_out, side_reserve, other_reserve), (49, 101, 101));
+        assert!(side_reserve as u128 * other_reserve as u128 >= 100 * 100);
+    }
+
+    #[test]
+    fn amm_sell_reports_overflow() {
+        assert!(amm_sell(u64::MAX, u64::MAX, u64::MAX).is_err());
+    }
+
+    #[test]
+    fn cancelled_amm_market_refunds_stakes_not_shares() {
+        let mut market_state = MarketState {
+            market_mode: MarketMode::Amm,
+            status: MarketStatus::Cancelled,
+            amm_stake_amount: 70,
+            ..Default::default()
+        };
+        let bet_state = BetState {
+            market: Pubkey::default(),
+            market_creation_timestamp: 0,
+            bettor: Pubkey::default(),
+            long_amount: 50,
+            short_amount: 20,
+            winnings_claimed: false,
+            is_affiliate: false,
+            bump: 0,
+        };
+        assert_eq!(redemption_amount(&market_state, 83, 10).unwrap(), 0);
+        assert_eq!(refund_amm_stake(&mut market_state, &bet_state).unwrap(), 70);
+        assert_eq!(market_state.amm_stake_amount, 0);
+
+        // Resolved markets pay the winning shares, not the stakes
+        market_state.status = MarketStatus::ResolvedLongsWin;
+        assert_eq!(redemption_amount(&market_state, 83, 10).unwrap(), 83);
+        assert_eq!(refund_amm_stake(&mut market_state, &bet_state).unwrap(), 0);
+    }
+
+    #[test]
+    fn bettor_stats_track_pnl_and_settled_win_rate() {
+        let mut bettor_stats = BettorStats::default();
+        init_bettor_stats(&mut bettor_stats, Pubkey::new_unique(), 255);
+        record_bettor_stake(&mut bettor_stats, 100, true).unwrap();
+        record_bettor_stake(&mut bettor_stats, 50, false).unwrap();
+        record_bettor_stake(&mut bettor_stats, 40, true).unwrap();
+        assert_eq!(bettor_stats.markets_entered, 2);
+        assert_eq!(bettor_stats.net_pnl, -190);
+
+        // A won claim, a lost settlement and a refund
+        record_bettor_payout(&mut bettor_stats, 180, 0, Some(true)).unwrap();
+        record_bettor_payout(&mut bettor_stats, 0, 0, Some(false)).unwrap();
+        record_bettor_payout(&mut bettor_stats, 0, 25, None).unwrap();
+        assert_eq!((bettor_stats.markets_won, bettor_stats.markets_lost), (1, 1));
+        assert_eq!(bettor_stats.win_rate_basis_points, 5_000);
+        assert_eq!(bettor_stats.net_pnl, 15);
+
+        // Payouts of tokens bought from others don't count as positions of their own
+        record_bettor_payout(&mut bettor_stats, 10, 0, None).unwrap();
+        assert_eq!(bettor_stats.win_rate_basis_points, 5_000);
+        assert_eq!(bettor_stats.total_won, 190);
+    }
+
+    #[test]
+    fn settled_bets_win_by_their_own_position() {
+        let bet = |long_amount, short_amount| BetState {
+            market: Pubkey::default(),
+            market_creation_timestamp: 0,
+            bettor: Pubkey::default(),
+            long_amount,
+            short_amount,
+            winnings_claimed: false,
+            is_affiliate: false,
+            bump: 0,
+        };
+        let mut market_state = MarketState {
+            status: MarketStatus::ResolvedLongsWin,
+            total_long_amount: 100,
+            total_short_amount: 100,
+            platform_fee_basis_points: 200,
+            ..Default::default()
+        };
+        assert_eq!(bet_position_won(&market_state, &bet(10, 0)).unwrap(), Some(true));
+        assert_eq!(bet_position_won(&market_state, &bet(0, 10)).unwrap(), Some(false));
+        // Hedged: 50 LONG pays 98 for 60 staked, 10 LONG pays 19 for 60 staked
+        assert_eq!(bet_position_won(&market_state, &bet(50, 10)).unwrap(), Some(true));
+        assert_eq!(bet_position_won(&market_state, &bet(10, 50)).unwrap(), Some(false));
+
+        // A losing LONG bet stays lost, whatever forfeited deposit share its tokens draw
+        market_state.status = MarketStatus::ResolvedShortsWin;
+        assert_eq!(bet_position_won(&market_state, &bet(10, 0)).unwrap(), Some(false));
+
+        market_state.market_mode = MarketMode::Amm;
+        assert_eq!(bet_position_won(&market_state, &bet(20, 30)).unwrap(), Some(true));
+        assert_eq!(bet_position_won(&market_state, &bet(0, 0)).unwrap(), None);
+        market_state.status = MarketStatus::Refunding;
+        assert_eq!(bet_position_won(&market_state, &bet(10, 0)).unwrap(), None);
+    }
+
+    #[test]
+    fn subject_stats_count_resolutions_and_skip_refunds_in_the_long_rate() {
+        let mut subject_stats = SubjectStats::default();
+        record_subject_resolution(&mut subject_stats, &MarketStatus::ResolvedLongsWin).unwrap();
+        record_subject_resolution(&mut subject_stats, &MarketStatus::ResolvedLongsWin).unwrap();
+        record_subject_resolution(&mut subject_stats, &MarketStatus::ResolvedShortsWin).unwrap();
+        assert_eq!(subject_stats.long_rate_basis_points, 6_666);
+
+        record_subject_resolution(&mut subject_stats, &MarketStatus::Refunding).unwrap();
+        record_subject_resolution(&mut subject_stats, &MarketStatus::Cancelled).unwrap();
+        assert_eq!(subject_stats.markets_refunded, 1);
+        assert_eq!(subject_stats.long_rate_basis_points, 6_666);
+        assert_eq!(
+            (subject_stats.markets_resolved_long, subject_stats.markets_resolved_short),
+            (2, 1)
+        );
+    }
 }
@@ -0,0 +1,316 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program, BN } from "@coral-xyz/anchor";
+import { getOrCreateAssociatedTokenAccount, transfer } from "@solana/spl-token";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { expect } from "chai";
+import { SkillstreakProgram } from "../target/types/skillstreak_program";
+import {
+  USDC,
+  claimAccounts,
+  configParams,
+  configPda,
+  createFundedUser,
+  expectAnchorError,
+  getTestEnv,
+  marketPda,
+  placeBet,
+  providerTokenAccount,
+  resolveMarket,
+  startCourse,
+  TestEnv,
+  tokenBalance,
+  userStatePda,
+} from "./helpers";
+
+// Short task cycles so markets can be resolved within a test run
+const CYCLE_SECONDS = 10;
+const BETTING_WINDOW_SECONDS = 5;
+const GRACE_PERIOD_SECONDS = 1;
+
+describe("affiliates, short caps and deposit forfeiture", () => {
+  anchor.setProvider(anchor.AnchorProvider.env());
+  const provider = anchor.getProvider() as anchor.AnchorProvider;
+  const program = anchor.workspace
+    .skillstreakProgram as Program<SkillstreakProgram>;
+  const payer = (provider.wallet as anchor.Wallet).payer;
+
+  let env: TestEnv;
+  let callerTokenAccount: PublicKey;
+  let originalConfig: any;
+
+  before(async () => {
+    env = await getTestEnv(program);
+    callerTokenAccount = await providerTokenAccount(program, env);
+    originalConfig = await program.account.programConfig.fetch(
+      configPda(program)
+    );
+    await program.methods
+      .updateConfig(
+        configParams(originalConfig, {
+          dailyTaskCycleSeconds: new BN(CYCLE_SECONDS),
+          resolutionGracePeriodSeconds: new BN(GRACE_PERIOD_SECONDS),
+          autoMarketBettingWindowSeconds: new BN(BETTING_WINDOW_SECONDS),
+          disputeWindowSeconds: new BN(0),
+        })
+      )
+      .rpc();
+  });
+
+  after(async () => {
+    await program.methods.updateConfig(configParams(originalConfig)).rpc();
+  });
+
+  async function declareAffiliates(subject: Keypair, affiliates: PublicKey[]) {
+    await program.methods
+      .declareAffiliates(affiliates)
+      .accountsPartial({ user: subject.publicKey })
+      .signers([subject])
+      .rpc();
+  }
+
+  async function claimWinnings(
+    market: PublicKey,
+    bettor: { user: Keypair; tokenAccount: PublicKey }
+  ) {
+    await program.methods
+      .claimWinnings()
+      .accountsPartial(
+        await claimAccounts(
+          program,
+          env,
+          market,
+          bettor.user.publicKey,
+          bettor.tokenAccount
+        )
+      )
+      .signers([bettor.user])
+      .rpc();
+  }
+
+  it("keeps declared affiliates off the short side", async () => {
+    const affiliate = await createFundedUser(program, env, 10);
+    const subject = await createFundedUser(program, env, 100);
+    await startCourse(program, env, subject.user, subject.tokenAccount, 100);
+    // Declaring twice and including the subject itself only lists the affiliate once
+    await declareAffiliates(subject.user, [
+      affiliate.user.publicKey,
+      subject.user.publicKey,
+    ]);
+    await declareAffiliates(subject.user, [affiliate.user.publicKey]);
+
+    const [affiliateList] = PublicKey.findProgramAddressSync(
+      [Buffer.from("affiliates"), subject.user.publicKey.toBuffer()],
+      program.programId
+    );
+    const list = await program.account.affiliateList.fetch(affiliateList);
+    expect(list.affiliates.map((key) => key.toBase58())).to.deep.equal([
+      affiliate.user.publicKey.toBase58(),
+    ]);
+
+    const market = marketPda(program, subject.user.publicKey, 0);
+    await expectAnchorError(
+      placeBet(
+        program,
+        env,
+        market,
+        affiliate.user,
+        affiliate.tokenAccount,
+        5,
+        false
+      ),
+      "AffiliateCannotShort"
+    );
+    // Backing the subject is allowed
+    await placeBet(
+      program,
+      env,
+      market,
+      affiliate.user,
+      affiliate.tokenAccount,
+      5,
+      true
+    );
+  });
+
+  it("caps a short position at half of the subject's locked deposit", async () => {
+    const shorter = await createFundedUser(program, env, 60);
+    const subject = await createFundedUser(program, env, 100);
+    await startCourse(program, env, subject.user, subject.tokenAccount, 100);
+    const market = marketPda(program, subject.user.publicKey, 0);
+
+    await placeBet(
+      program,
+      env,
+      market,
+      shorter.user,
+      shorter.tokenAccount,
+      50,
+      false
+    );
+    // Repeat bets count towards the same position
+    await expectAnchorError(
+      placeBet(
+        program,
+        env,
+        market,
+        shorter.user,
+        shorter.tokenAccount,
+        1,
+        false
+      ),
+      "ShortPositionTooLarge"
+    );
+  });
+
+  it("refuses SHORT payouts to the subject and affiliates holding transferred tokens", async () => {
+    const long = await createFundedUser(program, env, 10);
+    const short = await createFundedUser(program, env, 10);
+    const affiliate = await createFundedUser(program, env, 0);
+    const subject = await createFundedUser(program, env, 100);
+    await startCourse(program, env, subject.user, subject.tokenAccount, 100);
+    await declareAffiliates(subject.user, [affiliate.user.publicKey]);
+    const market = marketPda(program, subject.user.publicKey, 0);
+
+    await placeBet(
+      program,
+      env,
+      market,
+      long.user,
+      long.tokenAccount,
+      10,
+      true
+    );
+    const shortTokens = await placeBet(
+      program,
+      env,
+      market,
+      short.user,
+      short.tokenAccount,
+      10,
+      false
+    );
+
+    // The short bettor passes part of their position to the subject's side
+    const { shortMint } = await program.account.marketState.fetch(market);
+    for (const [insider, usdc] of [
+      [affiliate.user.publicKey, 5],
+      [subject.user.publicKey, 2],
+    ] as const) {
+      const insiderShortTokens = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        payer,
+        shortMint,
+        insider
+      );
+      await transfer(
+        provider.connection,
+        payer,
+        shortTokens,
+        insiderShortTokens.address,
+        short.user,
+        usdc * USDC
+      );
+    }
+
+    // No task recorded, the subject missed
+    const state = await resolveMarket(program, env, market, callerTokenAccount);
+    expect(state.status).to.deep.equal({ resolvedShortsWin: {} });
+    for (const insider of [affiliate, subject]) {
+      await expectAnchorError(
+        claimWinnings(market, insider),
+        "InsiderCannotRedeemShort"
+      );
+    }
+
+    // The 3 SHORT tokens left with the bettor still pay their share
+    await claimWinnings(market, short);
+    const pool = 20 * USDC;
+    const payout = pool - (pool * state.platformFeeBasisPoints) / 10_000;
+    expect(await tokenBalance(program, short.tokenAccount)).to.equal(
+      (3 * payout) / 10
+    );
+  });
+
+  it("pays the forfeited deposit to whoever holds the non-affiliate LONG tokens", async () => {
+    const long = await createFundedUser(program, env, 5);
+    const affiliate = await createFundedUser(program, env, 5);
+    const short = await createFundedUser(program, env, 30);
+    const buyer = await createFundedUser(program, env, 0);
+    const subject = await createFundedUser(program, env, 100);
+    await startCourse(program, env, subject.user, subject.tokenAccount, 100);
+    await declareAffiliates(subject.user, [affiliate.user.publicKey]);
+    const market = marketPda(program, subject.user.publicKey, 0);
+
+    const longTokens = await placeBet(
+      program,
+      env,
+      market,
+      long.user,
+      long.tokenAccount,
+      5,
+      true
+    );
+    await placeBet(
+      program,
+      env,
+      market,
+      affiliate.user,
+      affiliate.tokenAccount,
+      5,
+      true
+    );
+    await placeBet(
+      program,
+      env,
+      market,
+      short.user,
+      short.tokenAccount,
+      30,
+      false
+    );
+
+    // The long bettor passes their position on before resolution
+    const { longMint } = await program.account.marketState.fetch(market);
+    const buyerLongTokens = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      longMint,
+      buyer.user.publicKey
+    );
+    await transfer(
+      provider.connection,
+      payer,
+      longTokens,
+      buyerLongTokens.address,
+      long.user,
+      5 * USDC
+    );
+
+    // No task recorded while 75% of the positions are short
+    const state = await resolveMarket(program, env, market, callerTokenAccount);
+    expect(state.status).to.deep.equal({ resolvedShortsWin: {} });
+    // 10% of the deposit, capped at the 5 USDC of LONG tokens outside the affiliate
+    expect(state.forfeitedAmount.toNumber()).to.equal(5 * USDC);
+    expect(state.forfeitEligibleLongTokens.toNumber()).to.equal(5 * USDC);
+    const subjectState = await program.account.userState.fetch(
+      userStatePda(program, subject.user.publicKey)
+    );
+    expect(subjectState.initialDepositAmount.toNumber()).to.equal(95 * USDC);
+
+    // The share follows the tokens, not the original bet
+    await claimWinnings(market, buyer);
+    expect(await tokenBalance(program, buyer.tokenAccount)).to.equal(5 * USDC);
+    await claimWinnings(market, long);
+    expect(await tokenBalance(program, long.tokenAccount)).to.equal(0);
+    // The affiliate's LONG tokens draw nothing and stay unburned
+    await claimWinnings(market, affiliate);
+    expect(await tokenBalance(program, affiliate.tokenAccount)).to.equal(0);
+
+    await claimWinnings(market, short);
+    const pool = 40 * USDC;
+    const fee = (pool * state.platformFeeBasisPoints) / 10_000;
+    expect(await tokenBalance(program, short.tokenAccount)).to.equal(
+      pool - fee
+    );
+  });
+});
@@ -0,0 +1,187 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { getOrCreateAssociatedTokenAccount, transfer } from "@solana/spl-token";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { expect } from "chai";
+import { SkillstreakProgram } from "../target/types/skillstreak_program";
+import {
+  USDC,
+  buyShares,
+  claimAccounts,
+  createAmmMarket,
+  createFundedUser,
+  getTestEnv,
+  sellShares,
+  startCourse,
+  TestEnv,
+  tokenBalance,
+} from "./helpers";
+
+describe("AMM markets", () => {
+  anchor.setProvider(anchor.AnchorProvider.env());
+  const provider = anchor.getProvider() as anchor.AnchorProvider;
+  const program = anchor.workspace
+    .skillstreakProgram as Program<SkillstreakProgram>;
+  const payer = (provider.wallet as anchor.Wallet).payer;
+
+  let env: TestEnv;
+
+  before(async () => {
+    env = await getTestEnv(program);
+  });
+
+  async function claimRefund(
+    market: PublicKey,
+    bettor: { user: Keypair; tokenAccount: PublicKey }
+  ) {
+    await program.methods
+      .claimRefund()
+      .accountsPartial(
+        await claimAccounts(
+          program,
+          env,
+          market,
+          bettor.user.publicKey,
+          bettor.tokenAccount
+        )
+      )
+      .signers([bettor.user])
+      .rpc();
+  }
+
+  it("refunds stakes net of sales and the liquidity when cancelled", async () => {
+    const creator = await createFundedUser(program, env, 100);
+    const long = await createFundedUser(program, env, 20);
+    const short = await createFundedUser(program, env, 10);
+    const holder = await createFundedUser(program, env, 0);
+    const subject = await createFundedUser(program, env, 100);
+    await startCourse(program, env, subject.user, subject.tokenAccount, 100);
+    const market = await createAmmMarket(
+      program,
+      env,
+      creator.user,
+      creator.tokenAccount,
+      subject.user.publicKey,
+      0,
+      60,
+      100
+    );
+
+    const longShares = await buyShares(
+      program,
+      env,
+      market,
+      long.user,
+      long.tokenAccount,
+      20,
+      true
+    );
+    await buyShares(
+      program,
+      env,
+      market,
+      short.user,
+      short.tokenAccount,
+      10,
+      false
+    );
+
+    // The long trader sells half of their shares and hands the rest on
+    const shares = await tokenBalance(program, longShares);
+    await sellShares(
+      program,
+      env,
+      market,
+      long.user,
+      long.tokenAccount,
+      Math.floor(shares / 2),
+      true
+    );
+    const soldFor = await tokenBalance(program, long.tokenAccount);
+    expect(soldFor).to.be.greaterThan(0);
+    const betState = await program.account.betState.fetch(
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("bet"), market.toBuffer(), long.user.publicKey.toBuffer()],
+        program.programId
+      )[0]
+    );
+    expect(betState.longAmount.toNumber()).to.equal(20 * USDC - soldFor);
+
+    const { longMint } = await program.account.marketState.fetch(market);
+    const holderShares = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      longMint,
+      holder.user.publicKey
+    );
+    await transfer(
+      provider.connection,
+      payer,
+      longShares,
+      holderShares.address,
+      long.user,
+      await tokenBalance(program, longShares)
+    );
+    // The holder never paid into the market, so their sale comes out of the liquidity
+    await sellShares(
+      program,
+      env,
+      market,
+      holder.user,
+      holder.tokenAccount,
+      await tokenBalance(program, holderShares.address),
+      true
+    );
+    const holderSoldFor = await tokenBalance(program, holder.tokenAccount);
+    let state = await program.account.marketState.fetch(market);
+    expect(state.ammStakeAmount.toNumber()).to.equal(30 * USDC - soldFor);
+    expect(state.ammSoldBeyondStakesAmount.toNumber()).to.equal(holderSoldFor);
+
+    // The subject cancels while betting is open
+    await program.methods
+      .closeMarket()
+      .accountsPartial({
+        user: subject.user.publicKey,
+        marketState: market,
+        usdcMint: env.usdcMint,
+      })
+      .signers([subject.user])
+      .rpc();
+
+    await claimRefund(market, long);
+    expect(await tokenBalance(program, long.tokenAccount)).to.equal(20 * USDC);
+    await claimRefund(market, short);
+    expect(await tokenBalance(program, short.tokenAccount)).to.equal(
+      10 * USDC
+    );
+    await program.methods
+      .redeemLiquidity()
+      .accountsPartial({
+        marketCreator: creator.user.publicKey,
+        marketState: market,
+        creatorTokenAccount: creator.tokenAccount,
+        usdcMint: env.usdcMint,
+      })
+      .signers([creator.user])
+      .rpc();
+    expect(await tokenBalance(program, creator.tokenAccount)).to.equal(
+      100 * USDC - holderSoldFor
+    );
+
+    // Every refund is covered by the escrow, nothing is left over
+    state = await program.account.marketState.fetch(market);
+    expect(state.ammStakeAmount.toNumber()).to.equal(0);
+    const [escrowVault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("market_escrow_vault"), market.toBuffer()],
+      program.programId
+    );
+    const escrow = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      env.usdcMint,
+      escrowVault,
+      true
+    );
+    expect(Number(escrow.amount)).to.equal(0);
+  });
+});
@@ -0,0 +1,198 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program, BN } from "@coral-xyz/anchor";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { expect } from "chai";
+import { SkillstreakProgram } from "../target/types/skillstreak_program";
+import {
+  USDC,
+  claimAccounts,
+  configParams,
+  configPda,
+  createFundedUser,
+  expectAnchorError,
+  getTestEnv,
+  marketPda,
+  placeBet,
+  startCourse,
+  TestEnv,
+  tokenBalance,
+  userStatePda,
+  waitForTimestamp,
+} from "./helpers";
+
+// Short task cycles so the betting window closes within a test run
+const CYCLE_SECONDS = 10;
+const BETTING_WINDOW_SECONDS = 5;
+
+describe("closing markets with open bets", () => {
+  anchor.setProvider(anchor.AnchorProvider.env());
+  const program = anchor.workspace
+    .skillstreakProgram as Program<SkillstreakProgram>;
+
+  let env: TestEnv;
+  let originalConfig: any;
+
+  before(async () => {
+    env = await getTestEnv(program);
+    originalConfig = await program.account.programConfig.fetch(
+      configPda(program)
+    );
+    await program.methods
+      .updateConfig(
+        configParams(originalConfig, {
+          dailyTaskCycleSeconds: new BN(CYCLE_SECONDS),
+          autoMarketBettingWindowSeconds: new BN(BETTING_WINDOW_SECONDS),
+        })
+      )
+      .rpc();
+  });
+
+  after(async () => {
+    await program.methods.updateConfig(configParams(originalConfig)).rpc();
+  });
+
+  // Starts a course for a fresh subject and places a 10 USDC short on its cycle 0 market.
+  async function openMarketWithBet() {
+    const bettor = await createFundedUser(program, env, 10);
+    const subject = await createFundedUser(program, env, 100);
+    await startCourse(program, env, subject.user, subject.tokenAccount, 100);
+    const market = marketPda(program, subject.user.publicKey, 0);
+    await placeBet(
+      program,
+      env,
+      market,
+      bettor.user,
+      bettor.tokenAccount,
+      10,
+      false
+    );
+    return { subject: subject.user, bettor, market };
+  }
+
+  function closeMarket(subject: Keypair, market: PublicKey) {
+    return program.methods
+      .closeMarket()
+      .accountsPartial({
+        user: subject.publicKey,
+        marketState: market,
+        usdcMint: env.usdcMint,
+      })
+      .signers([subject])
+      .rpc();
+  }
+
+  async function claimRefund(
+    market: PublicKey,
+    bettor: { user: Keypair; tokenAccount: PublicKey }
+  ) {
+    await program.methods
+      .claimRefund()
+      .accountsPartial(
+        await claimAccounts(
+          program,
+          env,
+          market,
+          bettor.user.publicKey,
+          bettor.tokenAccount
+        )
+      )
+      .signers([bettor.user])
+      .rpc();
+  }
+
+  it("cancels a market while betting is open and refunds the bettors", async () => {
+    const { subject, bettor, market } = await openMarketWithBet();
+
+    await closeMarket(subject, market);
+    const state = await program.account.marketState.fetch(market);
+    expect(state.status).to.deep.equal({ cancelled: {} });
+
+    await claimRefund(market, bettor);
+    expect(await tokenBalance(program, bettor.tokenAccount)).to.equal(
+      10 * USDC
+    );
+  });
+
+  it("refuses to cancel once betting has closed", async () => {
+    const { subject, market } = await openMarketWithBet();
+    const opened = await program.account.marketState.fetch(market);
+
+    // The subject now knows the book and could back out of a losing market
+    await waitForTimestamp(program, opened.bettingEndsTimestamp.toNumber());
+    await expectAnchorError(closeMarket(subject, market), "EscrowNotEmpty");
+    const state = await program.account.marketState.fetch(market);
+    expect(state.status).to.deep.equal({ open: {} });
+  });
+
+  it("starts a fresh bet state when a closed market is re-created", async () => {
+    const bettor = await createFundedUser(program, env, 10);
+    const subject = await createFundedUser(program, env, 100);
+    await startCourse(program, env, subject.user, subject.tokenAccount, 100);
+    const market = marketPda(program, subject.user.publicKey, 1);
+
+    // Opens the subject's cycle 1 market, betting until shortly before cycle 1 starts
+    async function createMarket() {
+      const longMint = Keypair.generate();
+      const shortMint = Keypair.generate();
+      await program.methods
+        .createMarket(new BN(1), new BN(8), null)
+        .accountsPartial({
+          marketCreator: bettor.user.publicKey,
+          userBeingBetOn: subject.user.publicKey,
+          userStateForBet: userStatePda(program, subject.user.publicKey),
+          marketState: market,
+          longMint: longMint.publicKey,
+          shortMint: shortMint.publicKey,
+          usdcMint: env.usdcMint,
+        })
+        .signers([bettor.user, longMint, shortMint])
+        .rpc();
+      return program.account.marketState.fetch(market);
+    }
+
+    const first = await createMarket();
+    await placeBet(
+      program,
+      env,
+      market,
+      bettor.user,
+      bettor.tokenAccount,
+      4,
+      true
+    );
+    // Cancel, refund, then close the now empty market
+    await closeMarket(subject.user, market);
+    await claimRefund(market, bettor);
+    await closeMarket(subject.user, market);
+
+    await waitForTimestamp(
+      program,
+      first.marketCreationTimestamp.toNumber() + 1
+    );
+    const second = await createMarket();
+    await placeBet(
+      program,
+      env,
+      market,
+      bettor.user,
+      bettor.tokenAccount,
+      3,
+      false
+    );
+
+    const [betStatePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("bet"), market.toBuffer(), bettor.user.publicKey.toBuffer()],
+      program.programId
+    );
+    const betState = await program.account.betState.fetch(betStatePda);
+    expect(betState.marketCreationTimestamp.toNumber()).to.equal(
+      second.marketCreationTimestamp.toNumber()
+    );
+    // Nothing of the refunded position carries over
+    expect(betState.longAmount.toNumber()).to.equal(0);
+    expect(betState.shortAmount.toNumber()).to.equal(3 * USDC);
+    expect(betState.winningsClaimed).to.equal(false);
+    const state = await program.account.marketState.fetch(market);
+    expect(state.betCount.toNumber()).to.equal(1);
+  });
+});
@@ -0,0 +1,74 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { expect } from "chai";
+import { SkillstreakProgram } from "../target/types/skillstreak_program";
+import {
+  configParams,
+  configPda,
+  createFundedUser,
+  expectAnchorError,
+  getTestEnv,
+  TestEnv,
+} from "./helpers";
+
+describe("config admin transfer", () => {
+  anchor.setProvider(anchor.AnchorProvider.env());
+  const provider = anchor.getProvider() as anchor.AnchorProvider;
+  const program = anchor.workspace
+    .skillstreakProgram as Program<SkillstreakProgram>;
+  const payer = (provider.wallet as anchor.Wallet).payer;
+
+  let env: TestEnv;
+
+  before(async () => {
+    env = await getTestEnv(program);
+  });
+
+  function transferAdmin(admin: Keypair, newAdmin: PublicKey) {
+    return program.methods
+      .transferConfigAdmin(newAdmin)
+      .accountsPartial({ admin: admin.publicKey })
+      .signers([admin])
+      .rpc();
+  }
+
+  function acceptAdmin(newAdmin: Keypair) {
+    return program.methods
+      .acceptConfigAdmin()
+      .accountsPartial({ newAdmin: newAdmin.publicKey })
+      .signers([newAdmin])
+      .rpc();
+  }
+
+  it("only hands over the admin role once the proposed key accepts", async () => {
+    const { user: newAdmin } = await createFundedUser(program, env, 0);
+    const { user: stranger } = await createFundedUser(program, env, 0);
+
+    await expectAnchorError(
+      transferAdmin(payer, PublicKey.default),
+      "InvalidConfigAdmin"
+    );
+
+    await transferAdmin(payer, newAdmin.publicKey);
+    let config = await program.account.programConfig.fetch(configPda(program));
+    expect(config.admin.equals(payer.publicKey)).to.be.true;
+    expect(config.pendingAdmin.equals(newAdmin.publicKey)).to.be.true;
+    await expectAnchorError(acceptAdmin(stranger), "NotPendingConfigAdmin");
+
+    await acceptAdmin(newAdmin);
+    try {
+      config = await program.account.programConfig.fetch(configPda(program));
+      expect(config.admin.equals(newAdmin.publicKey)).to.be.true;
+      expect(config.pendingAdmin.equals(PublicKey.default)).to.be.true;
+      await expectAnchorError(
+        program.methods.updateConfig(configParams(config)).rpc(),
+        "UnauthorizedConfigAuthority"
+      );
+    } finally {
+      // Hand the role back to the provider wallet the other tests run as
+      await transferAdmin(newAdmin, payer.publicKey);
+      await acceptAdmin(payer);
+    }
+  });
+});
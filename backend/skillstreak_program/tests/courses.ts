@@ -0,0 +1,115 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program, BN } from "@coral-xyz/anchor";
+import { Keypair } from "@solana/web3.js";
+import { expect } from "chai";
+import { SkillstreakProgram } from "../target/types/skillstreak_program";
+import {
+  USDC,
+  configParams,
+  configPda,
+  createFundedUser,
+  getTestEnv,
+  marketPda,
+  startCourse,
+  TestEnv,
+  userStatePda,
+} from "./helpers";
+
+describe("repeated courses", () => {
+  anchor.setProvider(anchor.AnchorProvider.env());
+  const program = anchor.workspace
+    .skillstreakProgram as Program<SkillstreakProgram>;
+
+  let env: TestEnv;
+
+  before(async () => {
+    env = await getTestEnv(program);
+  });
+
+  it("opens the next course's market while the previous one still exists", async () => {
+    const { user, tokenAccount } = await createFundedUser(program, env, 100);
+    await startCourse(program, env, user, tokenAccount, 100);
+    const firstMarket = marketPda(program, user.publicKey, 0, 1);
+
+    // Exiting cancels the first market, which stays open for refunds
+    await program.methods
+      .earlyWithdraw()
+      .accountsPartial({
+        user: user.publicKey,
+        userTokenAccount: tokenAccount,
+        treasuryWalletAccount: env.treasury,
+        marketState: firstMarket,
+        usdcMint: env.usdcMint,
+      })
+      .signers([user])
+      .rpc();
+
+    const longMint = Keypair.generate();
+    const shortMint = Keypair.generate();
+    const secondMarket = marketPda(program, user.publicKey, 0, 2);
+    await program.methods
+      .startCourse(new BN(30), new BN(50 * USDC))
+      .accountsPartial({
+        user: user.publicKey,
+        marketState: secondMarket,
+        usdcMint: env.usdcMint,
+        longMint: longMint.publicKey,
+        shortMint: shortMint.publicKey,
+      })
+      .signers([user, longMint, shortMint])
+      .rpc();
+
+    const state = await program.account.userState.fetch(
+      userStatePda(program, user.publicKey)
+    );
+    expect(state.courseCount.toNumber()).to.equal(2);
+    const first = await program.account.marketState.fetch(firstMarket);
+    expect(first.status).to.deep.equal({ cancelled: {} });
+    expect(first.courseIndex.toNumber()).to.equal(1);
+    const second = await program.account.marketState.fetch(secondMarket);
+    expect(second.status).to.deep.equal({ open: {} });
+    expect(second.courseIndex.toNumber()).to.equal(2);
+  });
+
+  it("keeps a running course's cycle length when the config changes", async () => {
+    const running = await createFundedUser(program, env, 100);
+    await startCourse(program, env, running.user, running.tokenAccount, 100);
+
+    const config = await program.account.programConfig.fetch(
+      configPda(program)
+    );
+    await program.methods
+      .updateConfig(
+        configParams(config, {
+          dailyTaskCycleSeconds: new BN(10),
+          autoMarketBettingWindowSeconds: new BN(5),
+        })
+      )
+      .rpc();
+    try {
+      const later = await createFundedUser(program, env, 100);
+      await startCourse(program, env, later.user, later.tokenAccount, 100);
+      const laterState = await program.account.userState.fetch(
+        userStatePda(program, later.user.publicKey)
+      );
+      expect(laterState.taskCycleSeconds.toNumber()).to.equal(10);
+    } finally {
+      await program.methods.updateConfig(configParams(config)).rpc();
+    }
+
+    // The running course and its market keep the cycles they started with
+    const state = await program.account.userState.fetch(
+      userStatePda(program, running.user.publicKey)
+    );
+    expect(state.taskCycleSeconds.toNumber()).to.equal(
+      config.dailyTaskCycleSeconds.toNumber()
+    );
+    const market = await program.account.marketState.fetch(
+      marketPda(program, running.user.publicKey, 0)
+    );
+    expect(market.taskDeadlineTimestamp.toNumber()).to.equal(
+      state.depositTimestamp.toNumber() +
+        config.dailyTaskCycleSeconds.toNumber()
+    );
+  });
+});
@@ -0,0 +1,228 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program, BN } from "@coral-xyz/anchor";
+import { getAssociatedTokenAddressSync } from "@solana/spl-token";
+import { PublicKey } from "@solana/web3.js";
+import { expect } from "chai";
+import { SkillstreakProgram } from "../target/types/skillstreak_program";
+import {
+  USDC,
+  configParams,
+  configPda,
+  createFundedUser,
+  expectAnchorError,
+  getTestEnv,
+  marketPda,
+  placeBet,
+  providerTokenAccount,
+  recordTask,
+  resolveMarket,
+  startCourse,
+  TestEnv,
+  tokenBalance,
+  waitForTimestamp,
+} from "./helpers";
+
+// Short task cycles and dispute windows so disputes play out within a test run
+const CYCLE_SECONDS = 10;
+const BETTING_WINDOW_SECONDS = 5;
+const GRACE_PERIOD_SECONDS = 1;
+const DISPUTE_WINDOW_SECONDS = 4;
+
+describe("disputes", () => {
+  anchor.setProvider(anchor.AnchorProvider.env());
+  const program = anchor.workspace
+    .skillstreakProgram as Program<SkillstreakProgram>;
+
+  let env: TestEnv;
+  let originalConfig: any;
+  let testConfig: any;
+
+  before(async () => {
+    env = await getTestEnv(program);
+    originalConfig = await program.account.programConfig.fetch(
+      configPda(program)
+    );
+    testConfig = configParams(originalConfig, {
+      dailyTaskCycleSeconds: new BN(CYCLE_SECONDS),
+      resolutionGracePeriodSeconds: new BN(GRACE_PERIOD_SECONDS),
+      autoMarketBettingWindowSeconds: new BN(BETTING_WINDOW_SECONDS),
+      disputeWindowSeconds: new BN(DISPUTE_WINDOW_SECONDS),
+    });
+    await program.methods.updateConfig(testConfig).rpc();
+  });
+
+  after(async () => {
+    await program.methods.updateConfig(configParams(originalConfig)).rpc();
+  });
+
+  it("rejects dispute windows without a resolver and free disputes", async () => {
+    await expectAnchorError(
+      program.methods
+        .updateConfig(
+          configParams(originalConfig, { resolverAuthority: PublicKey.default })
+        )
+        .rpc(),
+      "InvalidConfigParams"
+    );
+    await expectAnchorError(
+      program.methods
+        .updateConfig(
+          configParams(originalConfig, { disputeBondAmount: new BN(0) })
+        )
+        .rpc(),
+      "InvalidConfigParams"
+    );
+
+    // Without a dispute window neither is needed
+    await program.methods
+      .updateConfig(
+        configParams(originalConfig, {
+          resolverAuthority: PublicKey.default,
+          disputeWindowSeconds: new BN(0),
+          disputeBondAmount: new BN(0),
+        })
+      )
+      .rpc();
+    await program.methods.updateConfig(testConfig).rpc();
+  });
+
+  it("keeps the resolver from overturning an undisputed outcome after the window", async () => {
+    const long = await createFundedUser(program, env, 10);
+    const short = await createFundedUser(program, env, 5);
+    const subject = await createFundedUser(program, env, 100);
+    await startCourse(program, env, subject.user, subject.tokenAccount, 100);
+    const market = marketPda(program, subject.user.publicKey, 0);
+
+    await placeBet(
+      program,
+      env,
+      market,
+      long.user,
+      long.tokenAccount,
+      10,
+      true
+    );
+    await placeBet(
+      program,
+      env,
+      market,
+      short.user,
+      short.tokenAccount,
+      5,
+      false
+    );
+    await recordTask(program, subject.user);
+    const callerTokenAccount = await providerTokenAccount(program, env);
+    let state = await resolveMarket(program, env, market, callerTokenAccount);
+    expect(state.status).to.deep.equal({ provisionallyResolved: {} });
+
+    await waitForTimestamp(program, state.disputeEndsTimestamp.toNumber());
+    await expectAnchorError(
+      program.methods
+        .resolveDispute(false)
+        .accountsPartial({
+          marketState: market,
+          disputerTokenAccount: null,
+          treasuryTokenAccount: getAssociatedTokenAddressSync(
+            env.usdcMint,
+            env.treasury
+          ),
+          treasuryWalletAccount: env.treasury,
+          usdcMint: env.usdcMint,
+        })
+        .rpc(),
+      "DisputeWindowClosed"
+    );
+    state = await resolveMarket(program, env, market, callerTokenAccount);
+    expect(state.status).to.deep.equal({ resolvedLongsWin: {} });
+  });
+
+  it("lets the provisional outcome stand once the resolver misses its deadline", async () => {
+    const long = await createFundedUser(program, env, 10);
+    const short = await createFundedUser(program, env, 15);
+    const subject = await createFundedUser(program, env, 100);
+    await startCourse(program, env, subject.user, subject.tokenAccount, 100);
+    const market = marketPda(program, subject.user.publicKey, 0);
+
+    await placeBet(
+      program,
+      env,
+      market,
+      long.user,
+      long.tokenAccount,
+      10,
+      true
+    );
+    await placeBet(
+      program,
+      env,
+      market,
+      short.user,
+      short.tokenAccount,
+      5,
+      false
+    );
+    await recordTask(program, subject.user);
+    const callerTokenAccount = await providerTokenAccount(program, env);
+    let state = await resolveMarket(program, env, market, callerTokenAccount);
+    expect(state.status).to.deep.equal({ provisionallyResolved: {} });
+    expect(state.provisionalLongsWin).to.be.true;
+
+    // The short bettor disputes and posts the 10 USDC bond
+    await program.methods
+      .disputeOutcome()
+      .accountsPartial({
+        disputer: short.user.publicKey,
+        marketState: market,
+        disputerTokenAccount: short.tokenAccount,
+        usdcMint: env.usdcMint,
+      })
+      .signers([short.user])
+      .rpc();
+    expect(await tokenBalance(program, short.tokenAccount)).to.equal(0);
+    state = await program.account.marketState.fetch(market);
+    expect(state.rulingDeadlineTimestamp.toNumber()).to.equal(
+      state.disputeEndsTimestamp.toNumber() + DISPUTE_WINDOW_SECONDS
+    );
+
+    // Until the deadline the market waits for the resolver
+    await waitForTimestamp(program, state.disputeEndsTimestamp.toNumber());
+    await expectAnchorError(
+      resolveMarket(program, env, market, callerTokenAccount),
+      "DisputePending"
+    );
+
+    // Afterwards the resolver can no longer rule...
+    await waitForTimestamp(program, state.rulingDeadlineTimestamp.toNumber());
+    await expectAnchorError(
+      program.methods
+        .resolveDispute(false)
+        .accountsPartial({
+          marketState: market,
+          disputerTokenAccount: short.tokenAccount,
+          treasuryTokenAccount: getAssociatedTokenAddressSync(
+            env.usdcMint,
+            env.treasury
+          ),
+          treasuryWalletAccount: env.treasury,
+          usdcMint: env.usdcMint,
+        })
+        .rpc(),
+      "RulingDeadlinePassed"
+    );
+
+    // ...and resolution finalizes the provisional outcome, returning the bond
+    state = await resolveMarket(
+      program,
+      env,
+      market,
+      callerTokenAccount,
+      short.tokenAccount
+    );
+    expect(state.status).to.deep.equal({ resolvedLongsWin: {} });
+    expect(state.disputer.equals(PublicKey.default)).to.be.true;
+    expect(await tokenBalance(program, short.tokenAccount)).to.equal(
+      10 * USDC
+    );
+  });
+});
@@ -0,0 +1,537 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program, BN } from "@coral-xyz/anchor";
+import {
+  createMint,
+  getAccount,
+  getAssociatedTokenAddressSync,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from "@solana/spl-token";
+import { Keypair, LAMPORTS_PER_SOL, PublicKey } from "@solana/web3.js";
+import { SkillstreakProgram } from "../target/types/skillstreak_program";
+
+const BPF_LOADER_UPGRADEABLE = new PublicKey(
+  "BPFLoaderUpgradeab1e11111111111111111111111"
+);
+
+export const USDC = 1_000_000; // 6 decimals
+
+export interface TestEnv {
+  usdcMint: PublicKey;
+  treasury: PublicKey;
+}
+
+let cachedEnv: TestEnv | null = null;
+
+// ProgramConfig and YieldConfig are singletons, so every test file shares one
+// mint and treasury. The provider wallet deploys the program under `anchor test`,
+// which makes it the upgrade authority allowed to initialize the config.
+export async function getTestEnv(
+  program: Program<SkillstreakProgram>
+): Promise<TestEnv> {
+  if (cachedEnv) return cachedEnv;
+
+  const provider = program.provider as anchor.AnchorProvider;
+  const payer = (provider.wallet as anchor.Wallet).payer;
+
+  const [configPda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("config")],
+    program.programId
+  );
+  const existing = await program.account.programConfig.fetchNullable(configPda);
+  if (existing) {
+    cachedEnv = {
+      usdcMint: existing.usdcMint,
+      treasury: existing.treasuryWallet,
+    };
+    return cachedEnv;
+  }
+
+  const usdcMint = await createMint(
+    provider.connection,
+    payer,
+    payer.publicKey,
+    null,
+    6
+  );
+  const treasury = Keypair.generate().publicKey;
+  const [programData] = PublicKey.findProgramAddressSync(
+    [program.programId.toBuffer()],
+    BPF_LOADER_UPGRADEABLE
+  );
+
+  await program.methods
+    .initializeConfig({
+      treasuryWallet: treasury,
+      usdcMint,
+      dailyTaskCycleSeconds: new BN(24 * 60 * 60),
+      resolutionGracePeriodSeconds: new BN(5 * 60),
+      defaultPlatformFeeBasisPoints: 200,
+      autoMarketBettingWindowSeconds: new BN(12 * 60 * 60),
+      earlyExitFeeBasisPoints: 5_000,
+      claimPeriodSeconds: new BN(30 * 24 * 60 * 60),
+      minPlatformFeeBasisPoints: 100,
+      maxPlatformFeeBasisPoints: 1_000,
+      maxFeeRebateBasisPoints: 5_000,
+      resolutionBountyBasisPoints: 1_000,
+      resolverAuthority: payer.publicKey,
+      disputeWindowSeconds: new BN(6 * 60 * 60),
+      disputeBondAmount: new BN(10 * USDC),
+      maxPoolDepositMultipleBasisPoints: new BN(100_000),
+      maxBettorDepositMultipleBasisPoints: new BN(20_000),
+    })
+    .accountsPartial({
+      admin: payer.publicKey,
+      programData,
+    })
+    .rpc();
+
+  await program.methods
+    .initializeYieldConfig({
+      durationTierDays: [new BN(30), new BN(90), new BN(180), new BN(365)],
+      durationTierAprBasisPoints: [500, 1_000, 1_500, 2_000],
+      streakBonusPerDayBasisPoints: 100,
+      maxStreakMultiplierBasisPoints: 15_000,
+      maxAprBasisPoints: 3_000,
+    })
+    .accountsPartial({ authority: payer.publicKey })
+    .rpc();
+
+  cachedEnv = { usdcMint, treasury };
+  return cachedEnv;
+}
+
+// ConfigParams for update_config: the current config with `overrides` applied.
+// Test files that need short task cycles override the singleton config in `before`
+// and restore the fetched original in `after`.
+export function configParams(config: any, overrides: object = {}) {
+  return {
+    treasuryWallet: config.treasuryWallet,
+    usdcMint: config.usdcMint,
+    dailyTaskCycleSeconds: config.dailyTaskCycleSeconds,
+    resolutionGracePeriodSeconds: config.resolutionGracePeriodSeconds,
+    defaultPlatformFeeBasisPoints: config.defaultPlatformFeeBasisPoints,
+    autoMarketBettingWindowSeconds: config.autoMarketBettingWindowSeconds,
+    earlyExitFeeBasisPoints: config.earlyExitFeeBasisPoints,
+    claimPeriodSeconds: config.claimPeriodSeconds,
+    minPlatformFeeBasisPoints: config.minPlatformFeeBasisPoints,
+    maxPlatformFeeBasisPoints: config.maxPlatformFeeBasisPoints,
+    maxFeeRebateBasisPoints: config.maxFeeRebateBasisPoints,
+    resolutionBountyBasisPoints: config.resolutionBountyBasisPoints,
+    resolverAuthority: config.resolverAuthority,
+    disputeWindowSeconds: config.disputeWindowSeconds,
+    disputeBondAmount: config.disputeBondAmount,
+    maxPoolDepositMultipleBasisPoints: config.maxPoolDepositMultipleBasisPoints,
+    maxBettorDepositMultipleBasisPoints: config.maxBettorDepositMultipleBasisPoints,
+    ...overrides,
+  };
+}
+
+// The ProgramConfig singleton PDA.
+export function configPda(program: Program<SkillstreakProgram>): PublicKey {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from("config")],
+    program.programId
+  )[0];
+}
+
+// UserState PDA of `user`.
+export function userStatePda(
+  program: Program<SkillstreakProgram>,
+  user: PublicKey
+): PublicKey {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from("user"), user.toBuffer()],
+    program.programId
+  )[0];
+}
+
+// Terms that tell apart markets starting at the same cycle, single-cycle parimutuel by default.
+export interface MarketTerms {
+  streak?: { horizonCycles: number; streakThreshold: number };
+  amm?: boolean;
+}
+
+// Mirrors market_terms_seed: market type, market mode, horizon and streak threshold.
+function marketTermsSeed(terms: MarketTerms): Buffer {
+  const { horizonCycles, streakThreshold } = terms.streak ?? {
+    horizonCycles: 1,
+    streakThreshold: 1,
+  };
+  return Buffer.concat([
+    Buffer.from([terms.streak ? 1 : 0, terms.amm ? 1 : 0]),
+    new BN(horizonCycles).toArrayLike(Buffer, "le", 8),
+    new BN(streakThreshold).toArrayLike(Buffer, "le", 8),
+  ]);
+}
+
+// Market PDA for `user`'s task cycle `cycleIndex` (day N of their course, from 0)
+// in their `courseIndex`-th course (from 1).
+export function marketPda(
+  program: Program<SkillstreakProgram>,
+  user: PublicKey,
+  cycleIndex: number,
+  courseIndex = 1,
+  terms: MarketTerms = {}
+): PublicKey {
+  const userState = userStatePda(program, user);
+  const [market] = PublicKey.findProgramAddressSync(
+    [
+      Buffer.from("market"),
+      user.toBuffer(),
+      userState.toBuffer(),
+      new BN(courseIndex).toArrayLike(Buffer, "le", 8),
+      new BN(cycleIndex).toArrayLike(Buffer, "le", 8),
+      marketTermsSeed(terms),
+    ],
+    program.programId
+  );
+  return market;
+}
+
+// Creates a funded wallet holding `usdcAmount` whole USDC in its ATA.
+export async function createFundedUser(
+  program: Program<SkillstreakProgram>,
+  env: TestEnv,
+  usdcAmount: number
+): Promise<{ user: Keypair; tokenAccount: PublicKey }> {
+  const provider = program.provider as anchor.AnchorProvider;
+  const payer = (provider.wallet as anchor.Wallet).payer;
+  const user = Keypair.generate();
+
+  const sig = await provider.connection.requestAirdrop(
+    user.publicKey,
+    2 * LAMPORTS_PER_SOL
+  );
+  await provider.connection.confirmTransaction(sig, "confirmed");
+
+  const ata = await getOrCreateAssociatedTokenAccount(
+    provider.connection,
+    payer,
+    env.usdcMint,
+    user.publicKey
+  );
+  await mintTo(
+    provider.connection,
+    payer,
+    env.usdcMint,
+    ata.address,
+    payer,
+    usdcAmount * USDC
+  );
+
+  return { user, tokenAccount: ata.address };
+}
+
+// Creates the user's state, deposits and locks `lockUsdc`, which also opens their first market.
+export async function startCourse(
+  program: Program<SkillstreakProgram>,
+  env: TestEnv,
+  user: Keypair,
+  tokenAccount: PublicKey,
+  lockUsdc: number,
+  lockInDays = 30
+): Promise<void> {
+  await program.methods
+    .createUserState()
+    .accountsPartial({ user: user.publicKey })
+    .signers([user])
+    .rpc();
+
+  await program.methods
+    .deposit(new BN(lockUsdc * USDC))
+    .accountsPartial({
+      user: user.publicKey,
+      userTokenAccount: tokenAccount,
+      usdcMint: env.usdcMint,
+    })
+    .signers([user])
+    .rpc();
+
+  // Fresh keypairs for the cycle 0 market's outcome token mints
+  const longMint = Keypair.generate();
+  const shortMint = Keypair.generate();
+  await program.methods
+    .startCourse(new BN(lockInDays), new BN(lockUsdc * USDC))
+    .accountsPartial({
+      user: user.publicKey,
+      marketState: marketPda(program, user.publicKey, 0),
+      usdcMint: env.usdcMint,
+      longMint: longMint.publicKey,
+      shortMint: shortMint.publicKey,
+    })
+    .signers([user, longMint, shortMint])
+    .rpc();
+}
+
+// Token balance of `tokenAccount`, 0 if the account does not exist (yet).
+export async function tokenBalance(
+  program: Program<SkillstreakProgram>,
+  tokenAccount: PublicKey
+): Promise<number> {
+  return getAccount(program.provider.connection, tokenAccount)
+    .then((account) => Number(account.amount))
+    .catch(() => 0);
+}
+
+// Records a task for `user` in the current cycle.
+export async function recordTask(
+  program: Program<SkillstreakProgram>,
+  user: Keypair
+): Promise<void> {
+  await program.methods
+    .recordTask()
+    .accountsPartial({ user: user.publicKey })
+    .signers([user])
+    .rpc();
+}
+
+// Places a parimutuel bet of `usdc` whole USDC and returns the bettor's outcome token account.
+export async function placeBet(
+  program: Program<SkillstreakProgram>,
+  env: TestEnv,
+  market: PublicKey,
+  bettor: Keypair,
+  bettorTokenAccount: PublicKey,
+  usdc: number,
+  long: boolean
+): Promise<PublicKey> {
+  const state = await program.account.marketState.fetch(market);
+  const positionMint = long ? state.longMint : state.shortMint;
+  const bettorPositionTokenAccount = getAssociatedTokenAddressSync(
+    positionMint,
+    bettor.publicKey
+  );
+  await program.methods
+    .placeBet(new BN(usdc * USDC), long)
+    .accountsPartial({
+      bettor: bettor.publicKey,
+      marketState: market,
+      userStateForBet: state.userStateAccountForBet,
+      bettorTokenAccount,
+      positionMint,
+      bettorPositionTokenAccount,
+      usdcMint: env.usdcMint,
+    })
+    .signers([bettor])
+    .rpc();
+  return bettorPositionTokenAccount;
+}
+
+// Opens an AMM market on `subject`'s cycle `cycleIndex`, seeded with `liquidityUsdc` whole USDC
+// from `creator`, and returns its PDA.
+export async function createAmmMarket(
+  program: Program<SkillstreakProgram>,
+  env: TestEnv,
+  creator: Keypair,
+  creatorTokenAccount: PublicKey,
+  subject: PublicKey,
+  cycleIndex: number,
+  bettingWindowSeconds: number,
+  liquidityUsdc: number
+): Promise<PublicKey> {
+  const market = marketPda(program, subject, cycleIndex, 1, { amm: true });
+  const longMint = Keypair.generate();
+  const shortMint = Keypair.generate();
+  await program.methods
+    .createAmmMarket(
+      new BN(cycleIndex),
+      new BN(bettingWindowSeconds),
+      new BN(liquidityUsdc * USDC),
+      null,
+      null
+    )
+    .accountsPartial({
+      marketCreator: creator.publicKey,
+      userBeingBetOn: subject,
+      userStateForBet: userStatePda(program, subject),
+      marketState: market,
+      longMint: longMint.publicKey,
+      shortMint: shortMint.publicKey,
+      creatorTokenAccount,
+      usdcMint: env.usdcMint,
+    })
+    .signers([creator, longMint, shortMint])
+    .rpc();
+  return market;
+}
+
+// Buys AMM shares for `usdc` whole USDC and returns the bettor's outcome token account.
+export async function buyShares(
+  program: Program<SkillstreakProgram>,
+  env: TestEnv,
+  market: PublicKey,
+  bettor: Keypair,
+  bettorTokenAccount: PublicKey,
+  usdc: number,
+  long: boolean
+): Promise<PublicKey> {
+  const state = await program.account.marketState.fetch(market);
+  const positionMint = long ? state.longMint : state.shortMint;
+  const bettorPositionTokenAccount = getAssociatedTokenAddressSync(
+    positionMint,
+    bettor.publicKey
+  );
+  await program.methods
+    .buyShares(new BN(usdc * USDC), long, new BN(0))
+    .accountsPartial({
+      bettor: bettor.publicKey,
+      marketState: market,
+      userStateForBet: state.userStateAccountForBet,
+      bettorTokenAccount,
+      positionMint,
+      bettorPositionTokenAccount,
+      usdcMint: env.usdcMint,
+    })
+    .signers([bettor])
+    .rpc();
+  return bettorPositionTokenAccount;
+}
+
+// Sells `shares` AMM shares (token base units) back to the pool.
+export async function sellShares(
+  program: Program<SkillstreakProgram>,
+  env: TestEnv,
+  market: PublicKey,
+  bettor: Keypair,
+  bettorTokenAccount: PublicKey,
+  shares: number,
+  long: boolean
+) {
+  const state = await program.account.marketState.fetch(market);
+  const positionMint = long ? state.longMint : state.shortMint;
+  await program.methods
+    .sellShares(new BN(shares), long, new BN(0))
+    .accountsPartial({
+      bettor: bettor.publicKey,
+      marketState: market,
+      bettorTokenAccount,
+      positionMint,
+      bettorPositionTokenAccount: getAssociatedTokenAddressSync(
+        positionMint,
+        bettor.publicKey
+      ),
+      usdcMint: env.usdcMint,
+    })
+    .signers([bettor])
+    .rpc();
+}
+
+// Waits out the market's task cycle and grace period, then calls trigger_market_resolution
+// from the provider wallet, whose USDC account `callerTokenAccount` receives the bounty.
+// A dispute left unruled past its deadline has its bond returned to `disputerTokenAccount`.
+export async function resolveMarket(
+  program: Program<SkillstreakProgram>,
+  env: TestEnv,
+  market: PublicKey,
+  callerTokenAccount: PublicKey,
+  disputerTokenAccount: PublicKey | null = null
+) {
+  const state = await program.account.marketState.fetch(market);
+  await waitForTimestamp(program, state.resolutionTimestamp.toNumber() + 1);
+  await program.methods
+    .triggerMarketResolution()
+    .accountsPartial({
+      callerTokenAccount,
+      marketState: market,
+      userStateForBet: state.userStateAccountForBet,
+      treasuryWalletAccount: env.treasury,
+      treasuryTokenAccount: getAssociatedTokenAddressSync(
+        env.usdcMint,
+        env.treasury
+      ),
+      feeRebateRecipient: state.feeRebateRecipient,
+      feeRebateTokenAccount: getAssociatedTokenAddressSync(
+        env.usdcMint,
+        state.feeRebateRecipient
+      ),
+      disputerTokenAccount,
+      longMint: state.longMint,
+      shortMint: state.shortMint,
+      usdcMint: env.usdcMint,
+    })
+    .rpc();
+  return program.account.marketState.fetch(market);
+}
+
+// Accounts for claim_winnings/claim_refund, burning whichever outcome tokens the bettor holds.
+export async function claimAccounts(
+  program: Program<SkillstreakProgram>,
+  env: TestEnv,
+  market: PublicKey,
+  bettor: PublicKey,
+  bettorTokenAccount: PublicKey
+) {
+  const connection = program.provider.connection;
+  const { longMint, shortMint } = await program.account.marketState.fetch(
+    market
+  );
+  const [betState] = PublicKey.findProgramAddressSync(
+    [Buffer.from("bet"), market.toBuffer(), bettor.toBuffer()],
+    program.programId
+  );
+  const existing = async (account: PublicKey) =>
+    (await connection.getAccountInfo(account)) ? account : null;
+  return {
+    bettor,
+    marketState: market,
+    betState: await existing(betState),
+    longMint,
+    shortMint,
+    bettorLongTokenAccount: await existing(
+      getAssociatedTokenAddressSync(longMint, bettor)
+    ),
+    bettorShortTokenAccount: await existing(
+      getAssociatedTokenAddressSync(shortMint, bettor)
+    ),
+    bettorTokenAccount,
+    usdcMint: env.usdcMint,
+  };
+}
+
+// The provider wallet's USDC account, which receives resolution bounties.
+export async function providerTokenAccount(
+  program: Program<SkillstreakProgram>,
+  env: TestEnv
+): Promise<PublicKey> {
+  const provider = program.provider as anchor.AnchorProvider;
+  const payer = (provider.wallet as anchor.Wallet).payer;
+  return (
+    await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      env.usdcMint,
+      payer.publicKey
+    )
+  ).address;
+}
+
+// Waits until the cluster clock reaches `timestamp` (unix seconds).
+export async function waitForTimestamp(
+  program: Program<SkillstreakProgram>,
+  timestamp: number
+): Promise<void> {
+  const connection = program.provider.connection;
+  for (;;) {
+    const blockTime = await connection.getBlockTime(await connection.getSlot());
+    if (blockTime !== null && blockTime >= timestamp) return;
+    await new Promise((resolve) => setTimeout(resolve, 500));
+  }
+}
+
+// Asserts that `promise` fails with the given Anchor error code.
+export async function expectAnchorError(
+  promise: Promise<unknown>,
+  code: string
+): Promise<void> {
+  try {
+    await promise;
+  } catch (err) {
+    const actual = (err as anchor.AnchorError).error?.errorCode?.code;
+    if (actual !== code) {
+      throw new Error(`expected ${code}, got ${actual ?? err}`);
+    }
+    return;
+  }
+  throw new Error(`expected ${code}, but the transaction succeeded`);
+}
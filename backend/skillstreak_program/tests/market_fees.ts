@@ -0,0 +1,190 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program, BN } from "@coral-xyz/anchor";
+import { getAssociatedTokenAddressSync } from "@solana/spl-token";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { expect } from "chai";
+import { SkillstreakProgram } from "../target/types/skillstreak_program";
+import {
+  USDC,
+  configParams,
+  configPda,
+  createFundedUser,
+  expectAnchorError,
+  getTestEnv,
+  marketPda,
+  placeBet,
+  providerTokenAccount,
+  recordTask,
+  resolveMarket,
+  startCourse,
+  TestEnv,
+  tokenBalance,
+  userStatePda,
+  waitForTimestamp,
+} from "./helpers";
+
+// Short task cycles so markets can be resolved within a test run
+const CYCLE_SECONDS = 10;
+const BETTING_WINDOW_SECONDS = 5;
+const GRACE_PERIOD_SECONDS = 1;
+
+interface FeeParams {
+  platformFeeBasisPoints: number;
+  rebateBasisPoints: number;
+  rebateToSubject: boolean;
+}
+
+describe("market fees and rebates", () => {
+  anchor.setProvider(anchor.AnchorProvider.env());
+  const program = anchor.workspace
+    .skillstreakProgram as Program<SkillstreakProgram>;
+
+  let env: TestEnv;
+  let callerTokenAccount: PublicKey;
+  let originalConfig: any;
+
+  before(async () => {
+    env = await getTestEnv(program);
+    callerTokenAccount = await providerTokenAccount(program, env);
+    originalConfig = await program.account.programConfig.fetch(
+      configPda(program)
+    );
+    await program.methods
+      .updateConfig(
+        configParams(originalConfig, {
+          dailyTaskCycleSeconds: new BN(CYCLE_SECONDS),
+          resolutionGracePeriodSeconds: new BN(GRACE_PERIOD_SECONDS),
+          autoMarketBettingWindowSeconds: new BN(BETTING_WINDOW_SECONDS),
+          disputeWindowSeconds: new BN(0),
+          // Fee bounds the creator must pick from
+          minPlatformFeeBasisPoints: 100,
+          maxPlatformFeeBasisPoints: 1_000,
+          maxFeeRebateBasisPoints: 5_000,
+          resolutionBountyBasisPoints: 1_000,
+        })
+      )
+      .rpc();
+  });
+
+  after(async () => {
+    await program.methods.updateConfig(configParams(originalConfig)).rpc();
+  });
+
+  // Opens a market on the subject's cycle 1 with the creator's fee terms.
+  function createMarket(
+    creator: Keypair,
+    subject: PublicKey,
+    feeParams: FeeParams
+  ) {
+    const longMint = Keypair.generate();
+    const shortMint = Keypair.generate();
+    return program.methods
+      .createMarket(new BN(1), new BN(8), feeParams)
+      .accountsPartial({
+        marketCreator: creator.publicKey,
+        userBeingBetOn: subject,
+        userStateForBet: userStatePda(program, subject),
+        longMint: longMint.publicKey,
+        shortMint: shortMint.publicKey,
+        usdcMint: env.usdcMint,
+      })
+      .signers([creator, longMint, shortMint])
+      .rpc();
+  }
+
+  it("rejects fee terms outside the configured bounds", async () => {
+    const creator = await createFundedUser(program, env, 0);
+    const subject = await createFundedUser(program, env, 100);
+    await startCourse(program, env, subject.user, subject.tokenAccount, 100);
+
+    // Fee below the minimum, fee above the maximum, rebate above the maximum
+    for (const [platformFeeBasisPoints, rebateBasisPoints] of [
+      [50, 0],
+      [2_000, 0],
+      [500, 6_000],
+    ]) {
+      const feeParams = {
+        platformFeeBasisPoints,
+        rebateBasisPoints,
+        rebateToSubject: true,
+      };
+      await expectAnchorError(
+        createMarket(creator.user, subject.user.publicKey, feeParams),
+        "InvalidMarketFeeParams"
+      );
+    }
+  });
+
+  it("splits the fee between the resolver bounty, the rebate and the treasury", async () => {
+    const creator = await createFundedUser(program, env, 0);
+    const long = await createFundedUser(program, env, 10);
+    const short = await createFundedUser(program, env, 10);
+    const subject = await createFundedUser(program, env, 100);
+    await startCourse(program, env, subject.user, subject.tokenAccount, 100);
+
+    await createMarket(creator.user, subject.user.publicKey, {
+      platformFeeBasisPoints: 500,
+      rebateBasisPoints: 5_000,
+      rebateToSubject: true,
+    });
+    const market = marketPda(program, subject.user.publicKey, 1);
+    const created = await program.account.marketState.fetch(market);
+    expect(created.platformFeeBasisPoints).to.equal(500);
+    expect(created.feeRebateBasisPoints).to.equal(5_000);
+    expect(created.feeRebateRecipient).to.deep.equal(subject.user.publicKey);
+
+    await placeBet(
+      program,
+      env,
+      market,
+      long.user,
+      long.tokenAccount,
+      10,
+      true
+    );
+    await placeBet(
+      program,
+      env,
+      market,
+      short.user,
+      short.tokenAccount,
+      10,
+      false
+    );
+
+    // Complete cycle 0 late enough that cycle 1 starts before the streak window ends
+    const subjectState = await program.account.userState.fetch(
+      userStatePda(program, subject.user.publicKey)
+    );
+    const courseStart = subjectState.depositTimestamp.toNumber();
+    await waitForTimestamp(program, courseStart + CYCLE_SECONDS - 3);
+    await recordTask(program, subject.user);
+    await waitForTimestamp(program, courseStart + CYCLE_SECONDS + 1);
+    await recordTask(program, subject.user);
+
+    const treasuryTokenAccount = getAssociatedTokenAddressSync(
+      env.usdcMint,
+      env.treasury
+    );
+    const treasuryBefore = await tokenBalance(program, treasuryTokenAccount);
+    const callerBefore = await tokenBalance(program, callerTokenAccount);
+    const subjectBefore = await tokenBalance(program, subject.tokenAccount);
+
+    const state = await resolveMarket(program, env, market, callerTokenAccount);
+    expect(state.status).to.deep.equal({ resolvedLongsWin: {} });
+
+    // 5% of the 20 USDC pool; 10% of it to the resolver, half the rest to the subject
+    const fee = 1 * USDC;
+    const bounty = fee / 10;
+    const rebate = (fee - bounty) / 2;
+    expect(await tokenBalance(program, callerTokenAccount)).to.equal(
+      callerBefore + bounty
+    );
+    expect(await tokenBalance(program, subject.tokenAccount)).to.equal(
+      subjectBefore + rebate
+    );
+    expect(await tokenBalance(program, treasuryTokenAccount)).to.equal(
+      treasuryBefore + fee - bounty - rebate
+    );
+  });
+});
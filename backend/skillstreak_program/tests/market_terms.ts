@@ -0,0 +1,66 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program, BN } from "@coral-xyz/anchor";
+import { Keypair } from "@solana/web3.js";
+import { expect } from "chai";
+import { SkillstreakProgram } from "../target/types/skillstreak_program";
+import {
+  createFundedUser,
+  getTestEnv,
+  marketPda,
+  startCourse,
+  TestEnv,
+  userStatePda,
+} from "./helpers";
+
+describe("markets with different terms on one cycle", () => {
+  anchor.setProvider(anchor.AnchorProvider.env());
+  const program = anchor.workspace
+    .skillstreakProgram as Program<SkillstreakProgram>;
+
+  let env: TestEnv;
+
+  before(async () => {
+    env = await getTestEnv(program);
+  });
+
+  it("opens a streak market next to the course's single-cycle market", async () => {
+    const creator = await createFundedUser(program, env, 0);
+    const { user, tokenAccount } = await createFundedUser(program, env, 100);
+    await startCourse(program, env, user, tokenAccount, 100);
+
+    const streak = { horizonCycles: 3, streakThreshold: 3 };
+    const streakMarket = marketPda(program, user.publicKey, 0, 1, { streak });
+    const longMint = Keypair.generate();
+    const shortMint = Keypair.generate();
+    await program.methods
+      .createStreakMarket(
+        new BN(0),
+        new BN(60),
+        {
+          horizonCycles: new BN(streak.horizonCycles),
+          streakThreshold: new BN(streak.streakThreshold),
+        },
+        null
+      )
+      .accountsPartial({
+        marketCreator: creator.user.publicKey,
+        userBeingBetOn: user.publicKey,
+        userStateForBet: userStatePda(program, user.publicKey),
+        marketState: streakMarket,
+        longMint: longMint.publicKey,
+        shortMint: shortMint.publicKey,
+        usdcMint: env.usdcMint,
+      })
+      .signers([creator.user, longMint, shortMint])
+      .rpc();
+
+    const single = await program.account.marketState.fetch(
+      marketPda(program, user.publicKey, 0)
+    );
+    expect(single.marketType).to.deep.equal({ singleCycle: {} });
+    const created = await program.account.marketState.fetch(streakMarket);
+    expect(created.marketType).to.deep.equal({ streakSurvival: {} });
+    expect(created.cycleIndex.toNumber()).to.equal(0);
+    expect(created.horizonCycles.toNumber()).to.equal(3);
+  });
+});
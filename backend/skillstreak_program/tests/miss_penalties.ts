@@ -0,0 +1,154 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program, BN } from "@coral-xyz/anchor";
+import {
+  getAccount,
+  getAssociatedTokenAddressSync,
+} from "@solana/spl-token";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { expect } from "chai";
+import { SkillstreakProgram } from "../target/types/skillstreak_program";
+import {
+  USDC,
+  configParams,
+  configPda,
+  createFundedUser,
+  expectAnchorError,
+  getTestEnv,
+  startCourse,
+  TestEnv,
+  waitForTimestamp,
+} from "./helpers";
+
+// Short task cycles so several windows can be missed within a test run
+const CYCLE_SECONDS = 10;
+const BETTING_WINDOW_SECONDS = 5;
+
+describe("record_miss penalties", () => {
+  anchor.setProvider(anchor.AnchorProvider.env());
+  const program = anchor.workspace
+    .skillstreakProgram as Program<SkillstreakProgram>;
+  const connection = program.provider.connection;
+
+  let env: TestEnv;
+  let originalConfig: any;
+
+  before(async () => {
+    env = await getTestEnv(program);
+    originalConfig = await program.account.programConfig.fetch(
+      configPda(program)
+    );
+    await program.methods
+      .updateConfig(
+        configParams(originalConfig, {
+          dailyTaskCycleSeconds: new BN(CYCLE_SECONDS),
+          autoMarketBettingWindowSeconds: new BN(BETTING_WINDOW_SECONDS),
+        })
+      )
+      .rpc();
+  });
+
+  after(async () => {
+    await program.methods.updateConfig(configParams(originalConfig)).rpc();
+  });
+
+  function userStatePda(user: PublicKey): PublicKey {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), user.toBuffer()],
+      program.programId
+    )[0];
+  }
+
+  async function treasuryBalance(): Promise<number> {
+    return getAccount(
+      connection,
+      getAssociatedTokenAddressSync(env.usdcMint, env.treasury)
+    )
+      .then((account) => Number(account.amount))
+      .catch(() => 0);
+  }
+
+  function recordMiss(user: PublicKey) {
+    return program.methods
+      .recordMiss()
+      .accountsPartial({
+        userState: userStatePda(user),
+        treasuryWalletAccount: env.treasury,
+        usdcMint: env.usdcMint,
+      })
+      .rpc();
+  }
+
+  function topUp(user: Keypair, tokenAccount: PublicKey) {
+    return program.methods
+      .topUpAfterMiss()
+      .accountsPartial({
+        user: user.publicKey,
+        userTokenAccount: tokenAccount,
+        treasuryWalletAccount: env.treasury,
+        usdcMint: env.usdcMint,
+      })
+      .signers([user])
+      .rpc();
+  }
+
+  // Waits until the window after the last task or recorded miss has fully elapsed.
+  async function waitForNextMiss(user: PublicKey): Promise<void> {
+    const state = await program.account.userState.fetch(userStatePda(user));
+    const windowStart = Math.max(
+      state.lastTaskTimestamp.toNumber(),
+      state.lastMissDeadlineTimestamp.toNumber()
+    );
+    await waitForTimestamp(program, windowStart + CYCLE_SECONDS + 1);
+  }
+
+  it("rejects a miss before the task window has elapsed", async () => {
+    const { user, tokenAccount } = await createFundedUser(program, env, 100);
+    await startCourse(program, env, user, tokenAccount, 100);
+
+    await expectAnchorError(
+      recordMiss(user.publicKey),
+      "MissWindowNotElapsed"
+    );
+  });
+
+  it("holds the 1st-3rd miss for a top-up and applies the repeat tier from the 4th", async () => {
+    const { user, tokenAccount } = await createFundedUser(program, env, 200);
+    await startCourse(program, env, user, tokenAccount, 100);
+
+    // 1st-3rd miss: the hard deduction waits for the top-up window, which the user takes
+    for (const tierBasisPoints of [1_000, 2_000, 3_000]) {
+      await waitForNextMiss(user.publicKey);
+      await expectAnchorError(
+        recordMiss(user.publicKey),
+        "TopUpWindowStillOpen"
+      );
+      const treasuryBefore = await treasuryBalance();
+      await topUp(user, tokenAccount);
+      // The tier's amount is split between the treasury and the user's balance
+      expect(await treasuryBalance()).to.equal(
+        treasuryBefore + (100 * USDC * tierBasisPoints) / 10_000 / 2
+      );
+    }
+
+    // 4th miss: no top-up, record_miss deducts the repeat tier immediately
+    await waitForNextMiss(user.publicKey);
+    await expectAnchorError(topUp(user, tokenAccount), "TopUpNotAvailable");
+    const treasuryBefore = await treasuryBalance();
+    await recordMiss(user.publicKey);
+
+    expect(await treasuryBalance()).to.equal(treasuryBefore + 25 * USDC);
+    const state = await program.account.userState.fetch(
+      userStatePda(user.publicKey)
+    );
+    expect(state.missCount.toNumber()).to.equal(4);
+    expect(state.initialDepositAmount.toNumber()).to.equal(75 * USDC);
+    expect(state.currentStreak.toNumber()).to.equal(0);
+    expect(state.yieldRateHalved).to.be.true;
+
+    // Each call penalizes exactly one window
+    await expectAnchorError(
+      recordMiss(user.publicKey),
+      "MissWindowNotElapsed"
+    );
+  });
+});
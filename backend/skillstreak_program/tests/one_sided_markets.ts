@@ -0,0 +1,298 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program, BN } from "@coral-xyz/anchor";
+import {
+  getAccount,
+  getAssociatedTokenAddressSync,
+} from "@solana/spl-token";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { expect } from "chai";
+import { SkillstreakProgram } from "../target/types/skillstreak_program";
+import {
+  USDC,
+  claimAccounts,
+  configParams,
+  createFundedUser,
+  expectAnchorError,
+  getTestEnv,
+  marketPda,
+  placeBet,
+  providerTokenAccount,
+  recordTask,
+  resolveMarket,
+  startCourse,
+  TestEnv,
+  tokenBalance,
+} from "./helpers";
+
+// Short task cycles so markets can be resolved within a test run
+const CYCLE_SECONDS = 10;
+const BETTING_WINDOW_SECONDS = 5;
+const GRACE_PERIOD_SECONDS = 1;
+
+interface Bettor {
+  bettor: Keypair;
+  tokenAccount: PublicKey;
+  outcomeTokenAccount: PublicKey; // LONG or SHORT tokens received for the stake
+  long: boolean;
+  stake: number;
+}
+
+describe("one-sided and empty markets", () => {
+  anchor.setProvider(anchor.AnchorProvider.env());
+  const program = anchor.workspace
+    .skillstreakProgram as Program<SkillstreakProgram>;
+  const connection = program.provider.connection;
+
+  let env: TestEnv;
+  let configPda: PublicKey;
+  let callerTokenAccount: PublicKey; // Provider wallet's USDC account, receives resolution bounties
+  let originalConfig: any;
+
+  before(async () => {
+    env = await getTestEnv(program);
+    [configPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("config")],
+      program.programId
+    );
+    originalConfig = await program.account.programConfig.fetch(configPda);
+    callerTokenAccount = await providerTokenAccount(program, env);
+    await program.methods
+      .updateConfig(
+        configParams(originalConfig, {
+          dailyTaskCycleSeconds: new BN(CYCLE_SECONDS),
+          resolutionGracePeriodSeconds: new BN(GRACE_PERIOD_SECONDS),
+          autoMarketBettingWindowSeconds: new BN(BETTING_WINDOW_SECONDS),
+          // Resolve in a single trigger call, without a dispute phase
+          disputeWindowSeconds: new BN(0),
+        })
+      )
+      .rpc();
+  });
+
+  after(async () => {
+    await program.methods.updateConfig(configParams(originalConfig)).rpc();
+  });
+
+  // Starts a course for a fresh subject and places the given bets on its cycle 0 market.
+  async function openMarket(
+    bets: { long: boolean; usdc: number }[]
+  ): Promise<{ subject: Keypair; market: PublicKey; bettors: Bettor[] }> {
+    const funded = await Promise.all(
+      bets.map((bet) => createFundedUser(program, env, bet.usdc))
+    );
+    const { user: subject, tokenAccount } = await createFundedUser(
+      program,
+      env,
+      100
+    );
+    await startCourse(program, env, subject, tokenAccount, 100);
+    const market = marketPda(program, subject.publicKey, 0);
+
+    const bettors: Bettor[] = [];
+    for (const [i, bet] of bets.entries()) {
+      const { user: bettor, tokenAccount: bettorTokenAccount } = funded[i];
+      const outcomeTokenAccount = await placeBet(
+        program,
+        env,
+        market,
+        bettor,
+        bettorTokenAccount,
+        bet.usdc,
+        bet.long
+      );
+      // Outcome tokens are issued 1:1 with the stake
+      expect(
+        await tokenBalance(program, outcomeTokenAccount)
+      ).to.equal(bet.usdc * USDC);
+      bettors.push({
+        bettor,
+        tokenAccount: bettorTokenAccount,
+        outcomeTokenAccount,
+        long: bet.long,
+        stake: bet.usdc * USDC,
+      });
+    }
+    return { subject, market, bettors };
+  }
+
+  async function claimRefund(market: PublicKey, bettor: Bettor) {
+    await program.methods
+      .claimRefund()
+      .accountsPartial(
+        await claimAccounts(
+          program,
+          env,
+          market,
+          bettor.bettor.publicKey,
+          bettor.tokenAccount
+        )
+      )
+      .signers([bettor.bettor])
+      .rpc();
+  }
+
+  it("refunds long-only bets when shorts win", async () => {
+    const { subject, market, bettors } = await openMarket([
+      { long: true, usdc: 10 },
+      { long: true, usdc: 5 },
+    ]);
+
+    // No task recorded: shorts win, but nobody is short
+    const state = await resolveMarket(
+      program,
+      env,
+      market,
+      callerTokenAccount
+    );
+    expect(state.status).to.deep.equal({ refunding: {} });
+
+    for (const bettor of bettors) {
+      await claimRefund(market, bettor);
+      expect(
+        await tokenBalance(program, bettor.tokenAccount)
+      ).to.equal(bettor.stake);
+      expect(
+        await tokenBalance(program, bettor.outcomeTokenAccount)
+      ).to.equal(0);
+    }
+    const escrow = getAssociatedTokenAddressSync(
+      env.usdcMint,
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("market_escrow_vault"), market.toBuffer()],
+        program.programId
+      )[0],
+      true
+    );
+    expect(await tokenBalance(program, escrow)).to.equal(0);
+  });
+
+  it("refunds short-only bets when longs win", async () => {
+    const { subject, market, bettors } = await openMarket([
+      { long: false, usdc: 7 },
+    ]);
+    await recordTask(program, subject);
+
+    const state = await resolveMarket(
+      program,
+      env,
+      market,
+      callerTokenAccount
+    );
+    expect(state.status).to.deep.equal({ refunding: {} });
+
+    // claim_winnings is not available in refund mode
+    await expectAnchorError(
+      program.methods
+        .claimWinnings()
+        .accountsPartial(
+          await claimAccounts(
+            program,
+            env,
+            market,
+            bettors[0].bettor.publicKey,
+            bettors[0].tokenAccount
+          )
+        )
+        .signers([bettors[0].bettor])
+        .rpc(),
+      "MarketCancelled"
+    );
+
+    await claimRefund(market, bettors[0]);
+    expect(
+      await tokenBalance(program, bettors[0].tokenAccount)
+    ).to.equal(7 * USDC);
+    // Tokens are burned and the bet is settled, nothing is left to refund
+    await expectAnchorError(claimRefund(market, bettors[0]), "NothingToClaim");
+  });
+
+  it("resolves an empty market into refund mode and lets the subject close it", async () => {
+    const { subject, market } = await openMarket([]);
+    const treasuryTokenAccount = getAssociatedTokenAddressSync(
+      env.usdcMint,
+      env.treasury
+    );
+    const treasuryBefore = await getAccount(connection, treasuryTokenAccount)
+      .then((account) => Number(account.amount))
+      .catch(() => 0);
+    const caller = program.provider.publicKey;
+    const callerLamportsBefore = await connection.getBalance(caller);
+
+    const state = await resolveMarket(
+      program,
+      env,
+      market,
+      callerTokenAccount
+    );
+    expect(state.status).to.deep.equal({ refunding: {} });
+    expect(
+      await tokenBalance(program, treasuryTokenAccount)
+    ).to.equal(treasuryBefore);
+    // No fee, no bounty: the caller pays the transaction fee but no rent
+    const callerLamportsAfter = await connection.getBalance(caller);
+    expect(callerLamportsBefore - callerLamportsAfter).to.equal(5_000);
+    const [subjectStats] = PublicKey.findProgramAddressSync(
+      [Buffer.from("subject_stats"), subject.publicKey.toBuffer()],
+      program.programId
+    );
+    const stats = await program.account.subjectStats.fetch(subjectStats);
+    expect(stats.marketsRefunded.toNumber()).to.equal(1);
+
+    await program.methods
+      .closeMarket()
+      .accountsPartial({
+        user: subject.publicKey,
+        marketState: market,
+        usdcMint: env.usdcMint,
+      })
+      .signers([subject])
+      .rpc();
+    expect(await connection.getAccountInfo(market)).to.be.null;
+  });
+
+  it("pays a long-only market normally when longs win", async () => {
+    const { subject, market, bettors } = await openMarket([
+      { long: true, usdc: 10 },
+    ]);
+    await recordTask(program, subject);
+
+    const callerBefore = await tokenBalance(program, callerTokenAccount);
+    const state = await resolveMarket(
+      program,
+      env,
+      market,
+      callerTokenAccount
+    );
+    expect(state.status).to.deep.equal({ resolvedLongsWin: {} });
+
+    // The resolver is paid its bounty out of the platform fee
+    const fee = (10 * USDC * state.platformFeeBasisPoints) / 10_000;
+    const { resolutionBountyBasisPoints } =
+      await program.account.programConfig.fetch(configPda);
+    expect(await tokenBalance(program, callerTokenAccount)).to.equal(
+      callerBefore + Math.floor((fee * resolutionBountyBasisPoints) / 10_000)
+    );
+
+    await program.methods
+      .claimWinnings()
+      .accountsPartial(
+        await claimAccounts(
+          program,
+          env,
+          market,
+          bettors[0].bettor.publicKey,
+          bettors[0].tokenAccount
+        )
+      )
+      .signers([bettors[0].bettor])
+      .rpc();
+
+    // Stake back minus the platform fee
+    expect(
+      await tokenBalance(program, bettors[0].tokenAccount)
+    ).to.equal(10 * USDC - fee);
+    expect(
+      await tokenBalance(program, bettors[0].outcomeTokenAccount)
+    ).to.equal(0);
+  });
+});
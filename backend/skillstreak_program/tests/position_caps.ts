@@ -0,0 +1,126 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { SkillstreakProgram } from "../target/types/skillstreak_program";
+import {
+  buyShares,
+  createAmmMarket,
+  createFundedUser,
+  expectAnchorError,
+  getTestEnv,
+  marketPda,
+  placeBet,
+  startCourse,
+  TestEnv,
+} from "./helpers";
+
+// With the default caps a 10 USDC deposit allows a 100 USDC pool and 20 USDC
+// per bettor
+describe("pool and position caps", () => {
+  anchor.setProvider(anchor.AnchorProvider.env());
+  const program = anchor.workspace
+    .skillstreakProgram as Program<SkillstreakProgram>;
+
+  let env: TestEnv;
+
+  before(async () => {
+    env = await getTestEnv(program);
+  });
+
+  it("caps bettors and the pool of a parimutuel market", async () => {
+    const subject = await createFundedUser(program, env, 10);
+    await startCourse(program, env, subject.user, subject.tokenAccount, 10);
+    const market = marketPda(program, subject.user.publicKey, 0);
+
+    const bettors = [];
+    for (let i = 0; i < 5; i++) {
+      const bettor = await createFundedUser(program, env, 21);
+      await placeBet(
+        program,
+        env,
+        market,
+        bettor.user,
+        bettor.tokenAccount,
+        20,
+        true
+      );
+      bettors.push(bettor);
+    }
+    // Both sides count towards a bettor's position
+    await expectAnchorError(
+      placeBet(
+        program,
+        env,
+        market,
+        bettors[0].user,
+        bettors[0].tokenAccount,
+        1,
+        false
+      ),
+      "BettorPositionTooLarge"
+    );
+
+    const late = await createFundedUser(program, env, 1);
+    await expectAnchorError(
+      placeBet(program, env, market, late.user, late.tokenAccount, 1, true),
+      "MarketPoolCapExceeded"
+    );
+  });
+
+  it("counts the liquidity of an AMM market towards the pool cap", async () => {
+    const creator = await createFundedUser(program, env, 101);
+    const subject = await createFundedUser(program, env, 10);
+    await startCourse(program, env, subject.user, subject.tokenAccount, 10);
+
+    await expectAnchorError(
+      createAmmMarket(
+        program,
+        env,
+        creator.user,
+        creator.tokenAccount,
+        subject.user.publicKey,
+        0,
+        60,
+        101
+      ),
+      "MarketPoolCapExceeded"
+    );
+    const market = await createAmmMarket(
+      program,
+      env,
+      creator.user,
+      creator.tokenAccount,
+      subject.user.publicKey,
+      0,
+      60,
+      80
+    );
+
+    const bettor = await createFundedUser(program, env, 21);
+    await expectAnchorError(
+      buyShares(
+        program,
+        env,
+        market,
+        bettor.user,
+        bettor.tokenAccount,
+        21,
+        true
+      ),
+      "BettorPositionTooLarge"
+    );
+    await buyShares(
+      program,
+      env,
+      market,
+      bettor.user,
+      bettor.tokenAccount,
+      20,
+      true
+    );
+    const late = await createFundedUser(program, env, 1);
+    await expectAnchorError(
+      buyShares(program, env, market, late.user, late.tokenAccount, 1, false),
+      "MarketPoolCapExceeded"
+    );
+  });
+});
@@ -0,0 +1,122 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program, BN } from "@coral-xyz/anchor";
+import { expect } from "chai";
+import { SkillstreakProgram } from "../target/types/skillstreak_program";
+import {
+  USDC,
+  claimAccounts,
+  configParams,
+  configPda,
+  createFundedUser,
+  getTestEnv,
+  marketPda,
+  placeBet,
+  providerTokenAccount,
+  resolveMarket,
+  startCourse,
+  TestEnv,
+  tokenBalance,
+} from "./helpers";
+
+// Short task cycles so markets can be resolved within a test run
+const CYCLE_SECONDS = 10;
+const BETTING_WINDOW_SECONDS = 5;
+const GRACE_PERIOD_SECONDS = 1;
+
+describe("bet quotes", () => {
+  anchor.setProvider(anchor.AnchorProvider.env());
+  const program = anchor.workspace
+    .skillstreakProgram as Program<SkillstreakProgram>;
+
+  let env: TestEnv;
+  let originalConfig: any;
+
+  before(async () => {
+    env = await getTestEnv(program);
+    originalConfig = await program.account.programConfig.fetch(
+      configPda(program)
+    );
+    await program.methods
+      .updateConfig(
+        configParams(originalConfig, {
+          dailyTaskCycleSeconds: new BN(CYCLE_SECONDS),
+          resolutionGracePeriodSeconds: new BN(GRACE_PERIOD_SECONDS),
+          autoMarketBettingWindowSeconds: new BN(BETTING_WINDOW_SECONDS),
+          disputeWindowSeconds: new BN(0),
+        })
+      )
+      .rpc();
+  });
+
+  after(async () => {
+    await program.methods.updateConfig(configParams(originalConfig)).rpc();
+  });
+
+  it("quotes the payout claim_winnings pays if no other bet follows", async () => {
+    const long = await createFundedUser(program, env, 10);
+    const short = await createFundedUser(program, env, 5);
+    const subject = await createFundedUser(program, env, 100);
+    await startCourse(program, env, subject.user, subject.tokenAccount, 100);
+    const market = marketPda(program, subject.user.publicKey, 0);
+
+    await placeBet(
+      program,
+      env,
+      market,
+      long.user,
+      long.tokenAccount,
+      10,
+      true
+    );
+    const quote = await program.methods
+      .quoteBet(new BN(5 * USDC), false)
+      .accountsPartial({ marketState: market })
+      .view();
+    await placeBet(
+      program,
+      env,
+      market,
+      short.user,
+      short.tokenAccount,
+      5,
+      false
+    );
+
+    // 15 USDC pool less the platform fee, all to the only short
+    const { platformFeeBasisPoints } = await program.account.marketState.fetch(
+      market
+    );
+    const pool = 15 * USDC;
+    const expectedPayout = pool - (pool * platformFeeBasisPoints) / 10_000;
+    expect(quote.payoutAmount.toNumber()).to.equal(expectedPayout);
+    expect(quote.payoutMultiplierBasisPoints.toNumber()).to.equal(
+      Math.floor((expectedPayout * 10_000) / (5 * USDC))
+    );
+    expect(quote.impliedLongProbabilityBasisPoints).to.equal(6666);
+
+    // No task recorded, so the short side wins
+    const state = await resolveMarket(
+      program,
+      env,
+      market,
+      await providerTokenAccount(program, env)
+    );
+    expect(state.status).to.deep.equal({ resolvedShortsWin: {} });
+    await program.methods
+      .claimWinnings()
+      .accountsPartial(
+        await claimAccounts(
+          program,
+          env,
+          market,
+          short.user.publicKey,
+          short.tokenAccount
+        )
+      )
+      .signers([short.user])
+      .rpc();
+    expect(await tokenBalance(program, short.tokenAccount)).to.equal(
+      quote.payoutAmount.toNumber()
+    );
+  });
+});
@@ -0,0 +1,148 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program, BN } from "@coral-xyz/anchor";
+import { PublicKey } from "@solana/web3.js";
+import { expect } from "chai";
+import { SkillstreakProgram } from "../target/types/skillstreak_program";
+import {
+  USDC,
+  claimAccounts,
+  configParams,
+  configPda,
+  createFundedUser,
+  getTestEnv,
+  marketPda,
+  placeBet,
+  providerTokenAccount,
+  recordTask,
+  resolveMarket,
+  startCourse,
+  TestEnv,
+  tokenBalance,
+} from "./helpers";
+
+// Short task cycles so markets can be resolved within a test run
+const CYCLE_SECONDS = 10;
+const BETTING_WINDOW_SECONDS = 5;
+const GRACE_PERIOD_SECONDS = 1;
+
+describe("repeat and hedged bets", () => {
+  anchor.setProvider(anchor.AnchorProvider.env());
+  const program = anchor.workspace
+    .skillstreakProgram as Program<SkillstreakProgram>;
+
+  let env: TestEnv;
+  let callerTokenAccount: PublicKey;
+  let originalConfig: any;
+
+  before(async () => {
+    env = await getTestEnv(program);
+    callerTokenAccount = await providerTokenAccount(program, env);
+    originalConfig = await program.account.programConfig.fetch(
+      configPda(program)
+    );
+    await program.methods
+      .updateConfig(
+        configParams(originalConfig, {
+          dailyTaskCycleSeconds: new BN(CYCLE_SECONDS),
+          resolutionGracePeriodSeconds: new BN(GRACE_PERIOD_SECONDS),
+          autoMarketBettingWindowSeconds: new BN(BETTING_WINDOW_SECONDS),
+          disputeWindowSeconds: new BN(0),
+        })
+      )
+      .rpc();
+  });
+
+  after(async () => {
+    await program.methods.updateConfig(configParams(originalConfig)).rpc();
+  });
+
+  it("tracks both legs of one bettor and pays only the winning leg", async () => {
+    const hedger = await createFundedUser(program, env, 18);
+    const other = await createFundedUser(program, env, 10);
+    const subject = await createFundedUser(program, env, 100);
+    await startCourse(program, env, subject.user, subject.tokenAccount, 100);
+    const market = marketPda(program, subject.user.publicKey, 0);
+
+    // Long, then a short hedge, then more long on the same position
+    const longTokens = await placeBet(
+      program,
+      env,
+      market,
+      hedger.user,
+      hedger.tokenAccount,
+      10,
+      true
+    );
+    const shortTokens = await placeBet(
+      program,
+      env,
+      market,
+      hedger.user,
+      hedger.tokenAccount,
+      5,
+      false
+    );
+    await placeBet(
+      program,
+      env,
+      market,
+      hedger.user,
+      hedger.tokenAccount,
+      3,
+      true
+    );
+    await placeBet(
+      program,
+      env,
+      market,
+      other.user,
+      other.tokenAccount,
+      10,
+      false
+    );
+
+    const [betStatePda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("bet"), market.toBuffer(), hedger.user.publicKey.toBuffer()],
+      program.programId
+    );
+    const betState = await program.account.betState.fetch(betStatePda);
+    expect(betState.longAmount.toNumber()).to.equal(13 * USDC);
+    expect(betState.shortAmount.toNumber()).to.equal(5 * USDC);
+    expect(await tokenBalance(program, longTokens)).to.equal(13 * USDC);
+    expect(await tokenBalance(program, shortTokens)).to.equal(5 * USDC);
+
+    const opened = await program.account.marketState.fetch(market);
+    expect(opened.totalLongAmount.toNumber()).to.equal(13 * USDC);
+    expect(opened.totalShortAmount.toNumber()).to.equal(15 * USDC);
+    // Repeat bets reuse the bettor's position
+    expect(opened.betCount.toNumber()).to.equal(2);
+
+    await recordTask(program, subject.user);
+    const state = await resolveMarket(program, env, market, callerTokenAccount);
+    expect(state.status).to.deep.equal({ resolvedLongsWin: {} });
+
+    await program.methods
+      .claimWinnings()
+      .accountsPartial(
+        await claimAccounts(
+          program,
+          env,
+          market,
+          hedger.user.publicKey,
+          hedger.tokenAccount
+        )
+      )
+      .signers([hedger.user])
+      .rpc();
+
+    // The hedger holds every long token, so the long leg takes the whole net pool
+    const pool = 28 * USDC;
+    const fee = (pool * state.platformFeeBasisPoints) / 10_000;
+    expect(await tokenBalance(program, hedger.tokenAccount)).to.equal(
+      pool - fee
+    );
+    expect(await tokenBalance(program, longTokens)).to.equal(0);
+    // Losing SHORT tokens are left untouched and worth nothing
+    expect(await tokenBalance(program, shortTokens)).to.equal(5 * USDC);
+  });
+});
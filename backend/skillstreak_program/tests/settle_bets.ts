@@ -0,0 +1,255 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program, BN } from "@coral-xyz/anchor";
+import { getAssociatedTokenAddressSync, revoke } from "@solana/spl-token";
+import { AccountMeta, Keypair, PublicKey } from "@solana/web3.js";
+import { expect } from "chai";
+import { SkillstreakProgram } from "../target/types/skillstreak_program";
+import {
+  USDC,
+  claimAccounts,
+  configParams,
+  configPda,
+  createFundedUser,
+  expectAnchorError,
+  getTestEnv,
+  marketPda,
+  placeBet,
+  providerTokenAccount,
+  recordTask,
+  resolveMarket,
+  startCourse,
+  TestEnv,
+  tokenBalance,
+} from "./helpers";
+
+// Short task cycles so markets can be resolved within a test run
+const CYCLE_SECONDS = 10;
+const BETTING_WINDOW_SECONDS = 5;
+const GRACE_PERIOD_SECONDS = 1;
+
+describe("batch settlement", () => {
+  anchor.setProvider(anchor.AnchorProvider.env());
+  const provider = anchor.getProvider() as anchor.AnchorProvider;
+  const program = anchor.workspace
+    .skillstreakProgram as Program<SkillstreakProgram>;
+  const connection = provider.connection;
+  const payer = (provider.wallet as anchor.Wallet).payer;
+
+  let env: TestEnv;
+  let originalConfig: any;
+
+  before(async () => {
+    env = await getTestEnv(program);
+    originalConfig = await program.account.programConfig.fetch(
+      configPda(program)
+    );
+    await program.methods
+      .updateConfig(
+        configParams(originalConfig, {
+          dailyTaskCycleSeconds: new BN(CYCLE_SECONDS),
+          resolutionGracePeriodSeconds: new BN(GRACE_PERIOD_SECONDS),
+          autoMarketBettingWindowSeconds: new BN(BETTING_WINDOW_SECONDS),
+          disputeWindowSeconds: new BN(0),
+        })
+      )
+      .rpc();
+  });
+
+  after(async () => {
+    await program.methods.updateConfig(configParams(originalConfig)).rpc();
+  });
+
+  function betStatePda(market: PublicKey, bettor: PublicKey): PublicKey {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("bet"), market.toBuffer(), bettor.toBuffer()],
+      program.programId
+    )[0];
+  }
+
+  // BettorStats ("bettor_stats") or SubjectStats ("subject_stats") of a wallet.
+  function statsPda(seed: string, wallet: PublicKey): PublicKey {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from(seed), wallet.toBuffer()],
+      program.programId
+    )[0];
+  }
+
+  // The SETTLE_BET_ACCOUNTS remaining accounts of one bet, in program order.
+  function settleAccounts(
+    market: PublicKey,
+    state: { longMint: PublicKey; shortMint: PublicKey },
+    bettor: PublicKey,
+    bettorTokenAccount: PublicKey,
+    betStateOwner: PublicKey = bettor
+  ): AccountMeta[] {
+    return [
+      betStatePda(market, betStateOwner),
+      bettor,
+      bettorTokenAccount,
+      getAssociatedTokenAddressSync(state.longMint, bettor),
+      getAssociatedTokenAddressSync(state.shortMint, bettor),
+      statsPda("bettor_stats", bettor),
+    ].map((pubkey) => ({ pubkey, isWritable: true, isSigner: false }));
+  }
+
+  function settleBets(
+    market: PublicKey,
+    state: { longMint: PublicKey; shortMint: PublicKey },
+    remainingAccounts: AccountMeta[]
+  ) {
+    return program.methods
+      .settleBets()
+      .accountsPartial({
+        marketState: market,
+        longMint: state.longMint,
+        shortMint: state.shortMint,
+        usdcMint: env.usdcMint,
+      })
+      .remainingAccounts(remainingAccounts)
+      .rpc();
+  }
+
+  it("pays delegated winners, closes bet states and skips revoked positions", async () => {
+    const long = await createFundedUser(program, env, 10);
+    const short = await createFundedUser(program, env, 5);
+    const revoker = await createFundedUser(program, env, 4);
+    const subject = await createFundedUser(program, env, 100);
+    await startCourse(program, env, subject.user, subject.tokenAccount, 100);
+    const market = marketPda(program, subject.user.publicKey, 0);
+
+    await placeBet(
+      program,
+      env,
+      market,
+      long.user,
+      long.tokenAccount,
+      10,
+      true
+    );
+    await placeBet(
+      program,
+      env,
+      market,
+      short.user,
+      short.tokenAccount,
+      5,
+      false
+    );
+    const revokedTokens = await placeBet(
+      program,
+      env,
+      market,
+      revoker.user,
+      revoker.tokenAccount,
+      4,
+      true
+    );
+    // Opting out of batch settlement
+    await revoke(connection, payer, revokedTokens, revoker.user);
+
+    await recordTask(program, subject.user);
+    const state = await resolveMarket(
+      program,
+      env,
+      market,
+      await providerTokenAccount(program, env)
+    );
+    expect(state.status).to.deep.equal({ resolvedLongsWin: {} });
+    const pool = 19 * USDC;
+    const payoutPool = pool - (pool * state.platformFeeBasisPoints) / 10_000;
+
+    const accounts = (bettor: { user: Keypair; tokenAccount: PublicKey }) =>
+      settleAccounts(market, state, bettor.user.publicKey, bettor.tokenAccount);
+
+    // Incomplete groups, someone else's bet state and duplicates are rejected
+    await expectAnchorError(
+      settleBets(market, state, accounts(long).slice(0, 5)),
+      "InvalidSettleAccounts"
+    );
+    await expectAnchorError(
+      settleBets(
+        market,
+        state,
+        settleAccounts(
+          market,
+          state,
+          long.user.publicKey,
+          long.tokenAccount,
+          short.user.publicKey
+        )
+      ),
+      "InvalidSettleAccounts"
+    );
+    await expectAnchorError(
+      settleBets(market, state, [...accounts(short), ...accounts(short)]),
+      "InvalidSettleAccounts"
+    );
+
+    const longBetState = betStatePda(market, long.user.publicKey);
+    const betStateRent = await connection.getBalance(longBetState);
+    const longLamportsBefore = await connection.getBalance(long.user.publicKey);
+    await settleBets(market, state, [
+      ...accounts(long),
+      ...accounts(short),
+      ...accounts(revoker),
+    ]);
+
+    // The winner is paid and gets the bet state's rent back
+    expect(await tokenBalance(program, long.tokenAccount)).to.equal(
+      (10 * payoutPool) / 14
+    );
+    expect(await connection.getAccountInfo(longBetState)).to.be.null;
+    expect(await connection.getBalance(long.user.publicKey)).to.equal(
+      longLamportsBefore + betStateRent
+    );
+    expect(await tokenBalance(program, short.tokenAccount)).to.equal(0);
+
+    // Revoked tokens are left alone, they are redeemed via claim_winnings.
+    // The settled position still counts as the win it was.
+    expect(await tokenBalance(program, revoker.tokenAccount)).to.equal(0);
+    expect(await tokenBalance(program, revokedTokens)).to.equal(4 * USDC);
+    const bettorStats = (bettor: { user: Keypair }) =>
+      program.account.bettorStats.fetch(
+        statsPda("bettor_stats", bettor.user.publicKey)
+      );
+    const revokerStats = await bettorStats(revoker);
+    expect(revokerStats.marketsWon.toNumber()).to.equal(1);
+    expect(revokerStats.marketsLost.toNumber()).to.equal(0);
+    await program.methods
+      .claimWinnings()
+      .accountsPartial(
+        await claimAccounts(
+          program,
+          env,
+          market,
+          revoker.user.publicKey,
+          revoker.tokenAccount
+        )
+      )
+      .signers([revoker.user])
+      .rpc();
+    expect(await tokenBalance(program, revoker.tokenAccount)).to.equal(
+      (4 * payoutPool) / 14
+    );
+
+    // Settled positions count towards the bettors' and the subject's records
+    const longStats = await bettorStats(long);
+    expect(longStats.marketsWon.toNumber()).to.equal(1);
+    expect(longStats.winRateBasisPoints).to.equal(10_000);
+    expect(longStats.netPnl.toNumber()).to.equal(
+      (10 * payoutPool) / 14 - 10 * USDC
+    );
+    const shortStats = await bettorStats(short);
+    expect(shortStats.marketsLost.toNumber()).to.equal(1);
+    expect(shortStats.winRateBasisPoints).to.equal(0);
+    expect(shortStats.netPnl.toNumber()).to.equal(-5 * USDC);
+    expect((await bettorStats(revoker)).marketsWon.toNumber()).to.equal(1);
+
+    const subjectStats = await program.account.subjectStats.fetch(
+      statsPda("subject_stats", subject.user.publicKey)
+    );
+    expect(subjectStats.marketsResolvedLong.toNumber()).to.equal(1);
+    expect(subjectStats.marketsResolvedShort.toNumber()).to.equal(0);
+    expect(subjectStats.longRateBasisPoints).to.equal(10_000);
+  });
+});
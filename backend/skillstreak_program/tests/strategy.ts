@@ -0,0 +1,186 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program, BN } from "@coral-xyz/anchor";
+import {
+  getAccount,
+  getAssociatedTokenAddressSync,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from "@solana/spl-token";
+import { PublicKey } from "@solana/web3.js";
+import { expect } from "chai";
+import { MockLending } from "../target/types/mock_lending";
+import { SkillstreakProgram } from "../target/types/skillstreak_program";
+import {
+  USDC,
+  createFundedUser,
+  expectAnchorError,
+  getTestEnv,
+  startCourse,
+  TestEnv,
+} from "./helpers";
+
+// Highest rate the mock pool supports (655.35% APR), so a few seconds accrue visible interest
+const POOL_RATE_BASIS_POINTS = 65_535;
+const DEPLOY_USDC = 1_000_000;
+
+describe("yield strategy against mock_lending", () => {
+  anchor.setProvider(anchor.AnchorProvider.env());
+  const provider = anchor.getProvider() as anchor.AnchorProvider;
+  const program = anchor.workspace
+    .skillstreakProgram as Program<SkillstreakProgram>;
+  const lending = anchor.workspace.mockLending as Program<MockLending>;
+  const payer = (provider.wallet as anchor.Wallet).payer;
+
+  let env: TestEnv;
+  let pool: PublicKey;
+  let poolTokenAccount: PublicKey;
+  let position: PublicKey;
+  let vaultTokenAccount: PublicKey;
+  const [vault] = PublicKey.findProgramAddressSync(
+    [Buffer.from("vault")],
+    program.programId
+  );
+  const [vaultStatePda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("vault_state")],
+    program.programId
+  );
+
+  before(async () => {
+    env = await getTestEnv(program);
+    [pool] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pool"), env.usdcMint.toBuffer()],
+      lending.programId
+    );
+    poolTokenAccount = getAssociatedTokenAddressSync(env.usdcMint, pool, true);
+    [position] = PublicKey.findProgramAddressSync(
+      [Buffer.from("position"), pool.toBuffer(), vault.toBuffer()],
+      lending.programId
+    );
+    vaultTokenAccount = getAssociatedTokenAddressSync(
+      env.usdcMint,
+      vault,
+      true
+    );
+
+    // The vault needs principal to deploy
+    const { user, tokenAccount } = await createFundedUser(
+      program,
+      env,
+      DEPLOY_USDC
+    );
+    await startCourse(program, env, user, tokenAccount, DEPLOY_USDC);
+
+    await lending.methods
+      .initializePool(POOL_RATE_BASIS_POINTS)
+      .accountsPartial({ payer: payer.publicKey, mint: env.usdcMint })
+      .rpc();
+    // Liquidity the pool pays interest out of
+    const funder = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      env.usdcMint,
+      payer.publicKey
+    );
+    await mintTo(
+      provider.connection,
+      payer,
+      env.usdcMint,
+      funder.address,
+      payer,
+      DEPLOY_USDC * USDC
+    );
+    await lending.methods
+      .fundPool(new BN(DEPLOY_USDC * USDC))
+      .accountsPartial({
+        funder: payer.publicKey,
+        pool,
+        funderTokenAccount: funder.address,
+      })
+      .rpc();
+
+    await program.methods
+      .initializeStrategy()
+      .accountsPartial({
+        authority: payer.publicKey,
+        lendingProgram: lending.programId,
+        lendingPool: pool,
+        lendingPoolTokenAccount: poolTokenAccount,
+        lendingPosition: position,
+        usdcMint: env.usdcMint,
+      })
+      .rpc();
+  });
+
+  function strategyAccounts() {
+    return {
+      caller: payer.publicKey,
+      lendingProgram: lending.programId,
+      lendingPool: pool,
+      lendingPoolTokenAccount: poolTokenAccount,
+      lendingPosition: position,
+      usdcMint: env.usdcMint,
+    };
+  }
+
+  async function vaultBalance(): Promise<number> {
+    return Number(
+      (await getAccount(provider.connection, vaultTokenAccount)).amount
+    );
+  }
+
+  it("deploys, harvests accrued interest into the reserve and recalls the principal", async () => {
+    const amount = DEPLOY_USDC * USDC;
+    const vaultBefore = await vaultBalance();
+    const reserveBefore = (
+      await program.account.vaultState.fetch(vaultStatePda)
+    ).yieldReserve.toNumber();
+
+    await program.methods
+      .deployToStrategy(new BN(amount))
+      .accountsPartial(strategyAccounts())
+      .rpc();
+    expect(await vaultBalance()).to.equal(vaultBefore - amount);
+    expect(
+      (await lending.account.position.fetch(position)).balance.toNumber()
+    ).to.equal(amount);
+
+    // Let interest accrue in the pool
+    await new Promise((resolve) => setTimeout(resolve, 3000));
+
+    await program.methods
+      .harvest()
+      .accountsPartial(strategyAccounts())
+      .rpc();
+    const vaultState = await program.account.vaultState.fetch(vaultStatePda);
+    const harvested = vaultState.yieldReserve.toNumber() - reserveBefore;
+    expect(harvested).to.be.greaterThan(0);
+    // Only the interest stays in the vault, the principal is redeployed
+    expect(await vaultBalance()).to.equal(vaultBefore - amount + harvested);
+    expect(vaultState.totalDeployed.toNumber()).to.equal(amount);
+
+    const [strategy] = PublicKey.findProgramAddressSync(
+      [Buffer.from("strategy"), lending.programId.toBuffer(), pool.toBuffer()],
+      program.programId
+    );
+    const strategyState = await program.account.yieldStrategy.fetch(strategy);
+    expect(strategyState.deployedAmount.toNumber()).to.equal(amount);
+    expect(strategyState.totalHarvested.toNumber()).to.equal(harvested);
+
+    // Recalling more than the deployed principal is rejected
+    await expectAnchorError(
+      program.methods
+        .recallFromStrategy(new BN(amount + 1))
+        .accountsPartial(strategyAccounts())
+        .rpc(),
+      "InvalidStrategyAmount"
+    );
+
+    await program.methods
+      .recallFromStrategy(new BN(amount))
+      .accountsPartial(strategyAccounts())
+      .rpc();
+    expect(await vaultBalance()).to.equal(vaultBefore + harvested);
+    const recalled = await program.account.vaultState.fetch(vaultStatePda);
+    expect(recalled.totalDeployed.toNumber()).to.equal(0);
+  });
+});
@@ -0,0 +1,91 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program, BN } from "@coral-xyz/anchor";
+import { Keypair } from "@solana/web3.js";
+import { expect } from "chai";
+import { SkillstreakProgram } from "../target/types/skillstreak_program";
+import {
+  configParams,
+  configPda,
+  createFundedUser,
+  getTestEnv,
+  startCourse,
+  TestEnv,
+  userStatePda,
+  waitForTimestamp,
+} from "./helpers";
+
+// Short task cycles so several cycles pass within a test run
+const CYCLE_SECONDS = 10;
+const BETTING_WINDOW_SECONDS = 5;
+
+describe("streak counting", () => {
+  anchor.setProvider(anchor.AnchorProvider.env());
+  const program = anchor.workspace
+    .skillstreakProgram as Program<SkillstreakProgram>;
+
+  let env: TestEnv;
+  let originalConfig: any;
+
+  before(async () => {
+    env = await getTestEnv(program);
+    originalConfig = await program.account.programConfig.fetch(
+      configPda(program)
+    );
+    await program.methods
+      .updateConfig(
+        configParams(originalConfig, {
+          dailyTaskCycleSeconds: new BN(CYCLE_SECONDS),
+          autoMarketBettingWindowSeconds: new BN(BETTING_WINDOW_SECONDS),
+        })
+      )
+      .rpc();
+  });
+
+  after(async () => {
+    await program.methods.updateConfig(configParams(originalConfig)).rpc();
+  });
+
+  async function recordTask(user: Keypair) {
+    await program.methods
+      .recordTask()
+      .accountsPartial({ user: user.publicKey })
+      .signers([user])
+      .rpc();
+    return program.account.userState.fetch(
+      userStatePda(program, user.publicKey)
+    );
+  }
+
+  it("increments the streak at most once per cycle and tracks the longest streak", async () => {
+    const { user, tokenAccount } = await createFundedUser(program, env, 100);
+    await startCourse(program, env, user, tokenAccount, 100);
+
+    const first = await recordTask(user);
+    expect(first.currentStreak.toNumber()).to.equal(1);
+    expect(first.longestStreak.toNumber()).to.equal(1);
+    expect(first.streakExpiresAt.toNumber()).to.equal(
+      first.lastTaskTimestamp.toNumber() + CYCLE_SECONDS
+    );
+    const incrementedAt = first.lastStreakIncrementTimestamp.toNumber();
+
+    // Further tasks within the same cycle only extend the streak timer
+    await waitForTimestamp(program, incrementedAt + CYCLE_SECONDS / 2);
+    const repeat = await recordTask(user);
+    expect(repeat.currentStreak.toNumber()).to.equal(1);
+    expect(repeat.lastStreakIncrementTimestamp.toNumber()).to.equal(
+      incrementedAt
+    );
+    expect(repeat.streakExpiresAt.toNumber()).to.equal(
+      repeat.lastTaskTimestamp.toNumber() + CYCLE_SECONDS
+    );
+
+    // A full cycle after the last increment, the next task counts again
+    await waitForTimestamp(program, incrementedAt + CYCLE_SECONDS + 1);
+    const next = await recordTask(user);
+    expect(next.currentStreak.toNumber()).to.equal(2);
+    expect(next.longestStreak.toNumber()).to.equal(2);
+    expect(next.lastStreakIncrementTimestamp.toNumber()).to.be.at.least(
+      incrementedAt + CYCLE_SECONDS
+    );
+  });
+});
@@ -0,0 +1,134 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program, BN } from "@coral-xyz/anchor";
+import {
+  getAccount,
+  getAssociatedTokenAddressSync,
+} from "@solana/spl-token";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { expect } from "chai";
+import { SkillstreakProgram } from "../target/types/skillstreak_program";
+import {
+  USDC,
+  configParams,
+  configPda,
+  createFundedUser,
+  expectAnchorError,
+  getTestEnv,
+  startCourse,
+  TestEnv,
+  userStatePda,
+  waitForTimestamp,
+} from "./helpers";
+
+// Short task cycles so a window can be missed within a test run
+const CYCLE_SECONDS = 10;
+const BETTING_WINDOW_SECONDS = 5;
+
+describe("top_up_after_miss", () => {
+  anchor.setProvider(anchor.AnchorProvider.env());
+  const program = anchor.workspace
+    .skillstreakProgram as Program<SkillstreakProgram>;
+  const connection = program.provider.connection;
+
+  let env: TestEnv;
+  let originalConfig: any;
+
+  before(async () => {
+    env = await getTestEnv(program);
+    originalConfig = await program.account.programConfig.fetch(
+      configPda(program)
+    );
+    await program.methods
+      .updateConfig(
+        configParams(originalConfig, {
+          dailyTaskCycleSeconds: new BN(CYCLE_SECONDS),
+          autoMarketBettingWindowSeconds: new BN(BETTING_WINDOW_SECONDS),
+        })
+      )
+      .rpc();
+  });
+
+  after(async () => {
+    await program.methods.updateConfig(configParams(originalConfig)).rpc();
+  });
+
+  async function tokenBalance(tokenAccount: PublicKey): Promise<number> {
+    return getAccount(connection, tokenAccount)
+      .then((account) => Number(account.amount))
+      .catch(() => 0);
+  }
+
+  function recordTask(user: Keypair) {
+    return program.methods
+      .recordTask()
+      .accountsPartial({ user: user.publicKey })
+      .signers([user])
+      .rpc();
+  }
+
+  function topUp(user: Keypair, tokenAccount: PublicKey) {
+    return program.methods
+      .topUpAfterMiss()
+      .accountsPartial({
+        user: user.publicKey,
+        userTokenAccount: tokenAccount,
+        treasuryWalletAccount: env.treasury,
+        usdcMint: env.usdcMint,
+      })
+      .signers([user])
+      .rpc();
+  }
+
+  it("rejects a top-up while the task window is still open", async () => {
+    const { user, tokenAccount } = await createFundedUser(program, env, 150);
+    await startCourse(program, env, user, tokenAccount, 100);
+
+    await expectAnchorError(topUp(user, tokenAccount), "MissWindowNotElapsed");
+  });
+
+  it("buys back a missed window, splitting the top-up and keeping the streak", async () => {
+    const { user, tokenAccount } = await createFundedUser(program, env, 150);
+    await startCourse(program, env, user, tokenAccount, 100);
+    await recordTask(user);
+
+    // Let the window after the task elapse without another task
+    const userState = userStatePda(program, user.publicKey);
+    const started = await program.account.userState.fetch(userState);
+    await waitForTimestamp(
+      program,
+      started.lastTaskTimestamp.toNumber() + CYCLE_SECONDS + 1
+    );
+    await expectAnchorError(recordTask(user), "UnrecordedMiss");
+
+    const treasuryTokenAccount = getAssociatedTokenAddressSync(
+      env.usdcMint,
+      env.treasury
+    );
+    const walletBefore = await tokenBalance(tokenAccount);
+    const treasuryBefore = await tokenBalance(treasuryTokenAccount);
+    await topUp(user, tokenAccount);
+
+    // 1st miss tier: 10% of the locked 100 USDC, half to the treasury, half to the balance
+    expect(await tokenBalance(tokenAccount)).to.equal(walletBefore - 10 * USDC);
+    expect(await tokenBalance(treasuryTokenAccount)).to.equal(
+      treasuryBefore + 5 * USDC
+    );
+    const toppedUp = await program.account.userState.fetch(userState);
+    expect(toppedUp.depositAmount.toNumber()).to.equal(
+      started.depositAmount.toNumber() + 5 * USDC
+    );
+    expect(toppedUp.missCount.toNumber()).to.equal(1);
+    expect(toppedUp.currentStreak.toNumber()).to.equal(1);
+    expect(toppedUp.lastTopUpDeadlineTimestamp.toNumber()).to.equal(
+      toppedUp.lastMissDeadlineTimestamp.toNumber()
+    );
+
+    // The same window cannot be bought back twice
+    await expectAnchorError(topUp(user, tokenAccount), "MissWindowNotElapsed");
+
+    // The next task continues the streak instead of starting a new one
+    await recordTask(user);
+    const after = await program.account.userState.fetch(userState);
+    expect(after.currentStreak.toNumber()).to.equal(2);
+  });
+});
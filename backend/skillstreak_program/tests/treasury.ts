@@ -0,0 +1,81 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import {
+  getAccount,
+  getAssociatedTokenAddressSync,
+} from "@solana/spl-token";
+import { expect } from "chai";
+import { SkillstreakProgram } from "../target/types/skillstreak_program";
+import {
+  USDC,
+  createFundedUser,
+  expectAnchorError,
+  getTestEnv,
+  startCourse,
+  TestEnv,
+} from "./helpers";
+
+describe("treasury validation", () => {
+  anchor.setProvider(anchor.AnchorProvider.env());
+  const program = anchor.workspace
+    .skillstreakProgram as Program<SkillstreakProgram>;
+
+  let env: TestEnv;
+
+  before(async () => {
+    env = await getTestEnv(program);
+  });
+
+  it("rejects early_withdraw with a spoofed treasury wallet", async () => {
+    const { user, tokenAccount } = await createFundedUser(program, env, 100);
+    await startCourse(program, env, user, tokenAccount, 100);
+
+    // The user passes their own wallet (and ATA) as the "treasury" to recover the penalty
+    await expectAnchorError(
+      program.methods
+        .earlyWithdraw()
+        .accountsPartial({
+          user: user.publicKey,
+          userTokenAccount: tokenAccount,
+          treasuryWalletAccount: user.publicKey,
+          treasuryTokenAccount: tokenAccount,
+          usdcMint: env.usdcMint,
+        })
+        .signers([user])
+        .rpc(),
+      "InvalidTreasuryAccount"
+    );
+  });
+
+  it("sends the early exit fee to the configured treasury", async () => {
+    const { user, tokenAccount } = await createFundedUser(program, env, 100);
+    await startCourse(program, env, user, tokenAccount, 100);
+
+    const treasuryTokenAccount = getAssociatedTokenAddressSync(
+      env.usdcMint,
+      env.treasury
+    );
+    const before = await getAccount(
+      program.provider.connection,
+      treasuryTokenAccount
+    ).catch(() => null);
+
+    await program.methods
+      .earlyWithdraw()
+      .accountsPartial({
+        user: user.publicKey,
+        userTokenAccount: tokenAccount,
+        treasuryWalletAccount: env.treasury,
+        usdcMint: env.usdcMint,
+      })
+      .signers([user])
+      .rpc();
+
+    const after = await getAccount(
+      program.provider.connection,
+      treasuryTokenAccount
+    );
+    const received = Number(after.amount) - Number(before?.amount ?? 0);
+    expect(received).to.equal(50 * USDC);
+  });
+});
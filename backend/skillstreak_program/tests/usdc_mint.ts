@@ -0,0 +1,120 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program, BN } from "@coral-xyz/anchor";
+import {
+  createMint,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from "@solana/spl-token";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { SkillstreakProgram } from "../target/types/skillstreak_program";
+import {
+  USDC,
+  configParams,
+  configPda,
+  createFundedUser,
+  expectAnchorError,
+  getTestEnv,
+  TestEnv,
+} from "./helpers";
+
+describe("USDC mint validation", () => {
+  anchor.setProvider(anchor.AnchorProvider.env());
+  const provider = anchor.getProvider() as anchor.AnchorProvider;
+  const program = anchor.workspace
+    .skillstreakProgram as Program<SkillstreakProgram>;
+  const payer = (provider.wallet as anchor.Wallet).payer;
+
+  let env: TestEnv;
+  let fakeMint: PublicKey;
+
+  before(async () => {
+    env = await getTestEnv(program);
+    fakeMint = await createMint(
+      provider.connection,
+      payer,
+      payer.publicKey,
+      null,
+      6
+    );
+  });
+
+  // Token account of `user` for the fake mint, holding `usdcAmount` fake units.
+  async function fakeTokenAccount(
+    user: Keypair,
+    usdcAmount: number
+  ): Promise<PublicKey> {
+    const ata = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      fakeMint,
+      user.publicKey
+    );
+    await mintTo(
+      provider.connection,
+      payer,
+      fakeMint,
+      ata.address,
+      payer,
+      usdcAmount * USDC
+    );
+    return ata.address;
+  }
+
+  it("rejects a deposit with a mint other than the configured USDC mint", async () => {
+    const { user } = await createFundedUser(program, env, 0);
+    await program.methods
+      .createUserState()
+      .accountsPartial({ user: user.publicKey })
+      .signers([user])
+      .rpc();
+    const tokenAccount = await fakeTokenAccount(user, 100);
+
+    await expectAnchorError(
+      program.methods
+        .deposit(new BN(100 * USDC))
+        .accountsPartial({
+          user: user.publicKey,
+          userTokenAccount: tokenAccount,
+          usdcMint: fakeMint,
+        })
+        .signers([user])
+        .rpc(),
+      "InvalidUsdcMint"
+    );
+  });
+
+  it("rejects a deposit from a token account of another mint", async () => {
+    const { user } = await createFundedUser(program, env, 0);
+    await program.methods
+      .createUserState()
+      .accountsPartial({ user: user.publicKey })
+      .signers([user])
+      .rpc();
+    const tokenAccount = await fakeTokenAccount(user, 100);
+
+    await expectAnchorError(
+      program.methods
+        .deposit(new BN(100 * USDC))
+        .accountsPartial({
+          user: user.publicKey,
+          userTokenAccount: tokenAccount,
+          usdcMint: env.usdcMint,
+        })
+        .signers([user])
+        .rpc(),
+      "TokenAccountMintMismatch"
+    );
+  });
+
+  it("rejects changing the USDC mint through update_config", async () => {
+    const config = await program.account.programConfig.fetch(
+      configPda(program)
+    );
+    await expectAnchorError(
+      program.methods
+        .updateConfig(configParams(config, { usdcMint: fakeMint }))
+        .rpc(),
+      "UsdcMintImmutable"
+    );
+  });
+});
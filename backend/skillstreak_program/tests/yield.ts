@@ -0,0 +1,156 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { expect } from "chai";
+import { SkillstreakProgram } from "../target/types/skillstreak_program";
+import {
+  USDC,
+  createFundedUser,
+  getTestEnv,
+  startCourse,
+  TestEnv,
+} from "./helpers";
+
+// Large enough that a few seconds of APR accrue a measurable amount
+const LOCK_USDC = 1_000_000;
+const SECONDS_PER_YEAR = 365 * 24 * 60 * 60;
+
+describe("yield", () => {
+  anchor.setProvider(anchor.AnchorProvider.env());
+  const program = anchor.workspace
+    .skillstreakProgram as Program<SkillstreakProgram>;
+
+  let env: TestEnv;
+  const [vaultStatePda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("vault_state")],
+    program.programId
+  );
+
+  before(async () => {
+    env = await getTestEnv(program);
+  });
+
+  function userStatePda(user: PublicKey): PublicKey {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), user.toBuffer()],
+      program.programId
+    )[0];
+  }
+
+  async function recordTask(user: Keypair): Promise<void> {
+    await program.methods
+      .recordTask()
+      .accountsPartial({ user: user.publicKey })
+      .signers([user])
+      .rpc();
+  }
+
+  async function sleep(seconds: number): Promise<void> {
+    await new Promise((resolve) => setTimeout(resolve, seconds * 1000));
+  }
+
+  // Simple interest on the locked deposit, rounded down like settle_yield.
+  function expectedYield(
+    lockedAmount: number,
+    aprBasisPoints: number,
+    elapsedSeconds: number
+  ): number {
+    return Number(
+      (BigInt(lockedAmount) * BigInt(aprBasisPoints) * BigInt(elapsedSeconds)) /
+        BigInt(10_000 * SECONDS_PER_YEAR)
+    );
+  }
+
+  it("accrues the duration tier APR, boosted by the streak multiplier", async () => {
+    const { user, tokenAccount } = await createFundedUser(
+      program,
+      env,
+      LOCK_USDC
+    );
+    // 90-day lock-in: 10% APR tier
+    await startCourse(program, env, user, tokenAccount, LOCK_USDC, 90);
+    const userState = userStatePda(user.publicKey);
+    const started = await program.account.userState.fetch(userState);
+
+    // Settled at streak 0: the plain tier rate
+    await sleep(3);
+    await recordTask(user);
+    const first = await program.account.userState.fetch(userState);
+    const firstElapsed =
+      first.lastYieldAccrualTimestamp.toNumber() -
+      started.lastYieldAccrualTimestamp.toNumber();
+    expect(firstElapsed).to.be.greaterThan(0);
+    expect(first.accruedYield.toNumber()).to.equal(
+      expectedYield(LOCK_USDC * USDC, 1_000, firstElapsed)
+    );
+
+    // Settled at streak 1: +1% per streak day, 10.1% APR
+    await sleep(3);
+    await recordTask(user);
+    const second = await program.account.userState.fetch(userState);
+    const secondElapsed =
+      second.lastYieldAccrualTimestamp.toNumber() -
+      first.lastYieldAccrualTimestamp.toNumber();
+    expect(
+      second.accruedYield.toNumber() - first.accruedYield.toNumber()
+    ).to.equal(expectedYield(LOCK_USDC * USDC, 1_010, secondElapsed));
+
+    // Every settled amount is booked as a vault liability
+    const vaultState = await program.account.vaultState.fetch(vaultStatePda);
+    expect(vaultState.totalYieldLiabilities.toNumber()).to.be.at.least(
+      second.accruedYield.toNumber()
+    );
+  });
+
+  it("forfeits yield accrued during the course on early_withdraw", async () => {
+    const { user, tokenAccount } = await createFundedUser(
+      program,
+      env,
+      LOCK_USDC
+    );
+    await startCourse(program, env, user, tokenAccount, LOCK_USDC);
+    await sleep(3);
+    await recordTask(user);
+
+    const accrued = (
+      await program.account.userState.fetch(userStatePda(user.publicKey))
+    ).accruedYield.toNumber();
+    expect(accrued).to.be.greaterThan(0);
+
+    const liabilitiesBefore = (
+      await program.account.vaultState.fetch(vaultStatePda)
+    ).totalYieldLiabilities.toNumber();
+    await program.methods
+      .earlyWithdraw()
+      .accountsPartial({
+        user: user.publicKey,
+        userTokenAccount: tokenAccount,
+        treasuryWalletAccount: env.treasury,
+        usdcMint: env.usdcMint,
+      })
+      .signers([user])
+      .rpc();
+
+    // The settle at exit accrues a little more, which is forfeited along with the rest
+    const state = await program.account.userState.fetch(
+      userStatePda(user.publicKey)
+    );
+    expect(state.accruedYield.toNumber()).to.equal(0);
+    const liabilitiesAfter = (
+      await program.account.vaultState.fetch(vaultStatePda)
+    ).totalYieldLiabilities.toNumber();
+    expect(liabilitiesAfter).to.equal(liabilitiesBefore - accrued);
+
+    // withdraw no longer has any yield to credit: the balance stays at the returned half
+    const balanceBefore = state.depositAmount.toNumber();
+    await program.methods
+      .withdraw()
+      .accountsPartial({ user: user.publicKey })
+      .signers([user])
+      .rpc();
+    const after = await program.account.userState.fetch(
+      userStatePda(user.publicKey)
+    );
+    expect(after.depositAmount.toNumber()).to.equal(balanceBefore);
+  });
+});